@@ -0,0 +1,607 @@
+use super::{
+    cmp_by_asc, Batch, Map, MintUrlWithUnit, MintUrlWithUnitOwned, ProofExtended, ProofState,
+    ProofsExtended, Record, UnitedStore, Url, Write,
+};
+use crate::types::{
+    unixtime_ms, Mint, MintKeySet, PendingMelt, PendingOperation, Transaction, TransactionKind,
+    TransactionStatus,
+};
+use crate::wallet::CURRENCY_UNIT_SAT;
+use crate::UniError;
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+#[derive(Debug)]
+//
+#[derive(strum::EnumIs, thiserror::Error)]
+pub enum MemoryStoreError {
+    /// the proof of the secret exists in the mint already, the same as the unique (secret, mint) of sqlite
+    #[error("proof exists: {0} {1}")]
+    DuplicateProof(String, String),
+}
+
+impl From<MemoryStoreError> for UniError<MemoryStoreError> {
+    fn from(err: MemoryStoreError) -> Self {
+        Self::Store(err)
+    }
+}
+
+// the rows in order of insert, the upserts keep the ctime of the first insert
+#[derive(Debug, Default)]
+struct Tables {
+    mints: Vec<Mint>,
+    counters: Vec<Record>,
+    // (mint, proof)
+    proofs: Vec<(String, ProofExtended)>,
+    // the unique (mint, secret) of proofs
+    secrets: BTreeSet<(String, String)>,
+    transactions: Vec<Transaction>,
+    // (ctime, melt)
+    melts: Vec<(u64, PendingMelt)>,
+    keysets: Vec<(u64, MintKeySet)>,
+    operations: Vec<(u64, PendingOperation)>,
+}
+
+/// the UnitedStore keeps everything in memory with the same semantics as the sqlite store,
+/// for the tests and the wallets not persisted. the clones share the tables
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStore {
+    tables: Arc<Mutex<Tables>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Tables {
+    fn add_counter(&mut self, record: &Record) {
+        let found = self.counters.iter_mut().find(|r| {
+            r.mint == record.mint && r.keysetid == record.keysetid && r.pubkey == record.pubkey
+        });
+        match found {
+            Some(r) => r.counter = record.counter,
+            None => self.counters.push(record.clone()),
+        }
+    }
+
+    fn delete_proofs(&mut self, mint_url: &Url, proofs: &[ProofExtended]) {
+        let mint = mint_url.as_str();
+        let mut deleted = false;
+        for p in proofs {
+            deleted |= self.secrets.remove(&secret_key(mint, p));
+        }
+        if deleted {
+            self.proofs
+                .retain(|(m, p)| m != mint || !proofs.iter().any(|d| d.raw.secret == p.raw.secret));
+        }
+    }
+
+    // adding an existing proof is the only write that fails: check the writes before applying them,
+    // so a failed batch writes nothing without copying the tables
+    fn check(&self, writes: &[Write]) -> Result<(), MemoryStoreError> {
+        let mut added = BTreeSet::new();
+        let mut deleted = BTreeSet::new();
+        for w in writes {
+            match w {
+                Write::DeleteProofs(mint_url, proofs) => {
+                    for p in proofs {
+                        let key = secret_key(mint_url.as_str(), p);
+                        added.remove(&key);
+                        deleted.insert(key);
+                    }
+                }
+                Write::AddProofs(mint_url, proofs) => {
+                    for p in proofs {
+                        let key = secret_key(mint_url.as_str(), p);
+                        let exists = added.contains(&key)
+                            || (self.secrets.contains(&key) && !deleted.contains(&key));
+                        if exists {
+                            return Err(MemoryStoreError::DuplicateProof(key.0, key.1));
+                        }
+                        added.insert(key);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    // checked already
+    fn add_proofs(&mut self, mint_url: &Url, proofs: &[ProofExtended]) {
+        let mint = mint_url.as_str();
+        for p in proofs {
+            self.secrets.insert(secret_key(mint, p));
+
+            let mut p = p.clone();
+            p.ts = Some(p.ts.unwrap_or_else(unixtime_ms));
+            p.js = String::new();
+            self.proofs.push((mint.to_owned(), p));
+        }
+    }
+
+    fn update_proofs_state(
+        &mut self,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+        state: ProofState,
+        operation: Option<&str>,
+    ) {
+        let mint = mint_url.as_str();
+        let ts = unixtime_ms();
+        for (m, p) in self.proofs.iter_mut() {
+            if m != mint || !proofs.iter().any(|u| u.raw.secret == p.raw.secret) {
+                continue;
+            }
+
+            p.state = state;
+            if state == ProofState::Unspent {
+                p.operation = None;
+                p.reserved_at = None;
+            } else {
+                if let Some(op) = operation {
+                    p.operation = Some(op.to_owned());
+                }
+                p.reserved_at = Some(p.reserved_at.unwrap_or(ts));
+            }
+        }
+    }
+
+    // the proofs of the states order by ctime
+    fn proofs_with_state<'a>(
+        &'a self,
+        states: &'a [ProofState],
+    ) -> impl Iterator<Item = &'a (String, ProofExtended)> + 'a {
+        let mut proofs = self
+            .proofs
+            .iter()
+            .filter(|(_, p)| states.contains(&p.state))
+            .collect::<Vec<_>>();
+        proofs.sort_by(|a, b| cmp_by_asc(a.1.ts, b.1.ts));
+        proofs.into_iter()
+    }
+
+    fn add_transaction(&mut self, tx: &Transaction) {
+        let found = self
+            .transactions
+            .iter_mut()
+            .find(|t| t.id() == tx.id() && t.direction() == tx.direction());
+        let t = match found {
+            Some(t) => t,
+            None => {
+                self.transactions.push(tx.clone());
+                return;
+            }
+        };
+
+        *t.status_mut() = tx.status();
+        *t.info_mut() = tx.info().map(|s| s.to_owned());
+        match t {
+            Transaction::Cashu(t) => t.fee = tx.fee(),
+            Transaction::LN(t) => {
+                t.fee = tx.fee();
+                t.preimage = tx.preimage().map(|s| s.to_owned());
+            }
+        }
+    }
+
+    fn add_pending_melt(&mut self, melt: &PendingMelt) {
+        let found = self
+            .melts
+            .iter_mut()
+            .find(|(_, m)| m.quote == melt.quote && m.mint == melt.mint);
+        match found {
+            Some((_, m)) => *m = melt.clone(),
+            None => self.melts.push((melt.time, melt.clone())),
+        }
+    }
+
+    fn delete_pending_melt(&mut self, mint_url: &str, quote: &str) {
+        self.melts
+            .retain(|(_, m)| m.mint != mint_url || m.quote != quote);
+    }
+
+    fn delete_operation(&mut self, mint_url: &str, id: &str) {
+        self.operations
+            .retain(|(_, o)| o.mint != mint_url || o.id != id);
+    }
+
+    // checked already
+    fn apply(&mut self, w: &Write) {
+        match w {
+            Write::AddCounter(record) => self.add_counter(record),
+            Write::DeleteProofs(mint_url, proofs) => self.delete_proofs(mint_url, proofs),
+            Write::AddProofs(mint_url, proofs) => self.add_proofs(mint_url, proofs),
+            Write::UpdateProofsState(mint_url, proofs, state, operation) => {
+                self.update_proofs_state(mint_url, proofs, *state, operation.as_deref())
+            }
+            Write::AddTransaction(tx) => self.add_transaction(tx),
+            Write::AddPendingMelt(melt) => self.add_pending_melt(melt),
+            Write::DeletePendingMelt(mint_url, quote) => self.delete_pending_melt(mint_url, quote),
+            Write::DeleteOperation(mint_url, id) => self.delete_operation(mint_url, id),
+        }
+    }
+}
+
+fn secret_key(mint: &str, p: &ProofExtended) -> (String, String) {
+    (mint.to_owned(), p.raw.secret.to_string())
+}
+
+// the values order by ctime
+fn sorted_by_ctime<T: Clone>(rows: &[(u64, T)]) -> Vec<T> {
+    let mut rows = rows.iter().collect::<Vec<_>>();
+    rows.sort_by(|a, b| cmp_by_asc(a.0, b.0));
+    rows.into_iter().map(|(_, t)| t.clone()).collect()
+}
+
+#[async_trait]
+impl UnitedStore for MemoryStore {
+    type Error = MemoryStoreError;
+
+    // counter records
+    async fn add_counter(&self, record: &Record) -> Result<(), Self::Error> {
+        self.lock().add_counter(record);
+        Ok(())
+    }
+    async fn delete_counters(&self, mint_url: &Url) -> Result<(), Self::Error> {
+        self.lock().counters.retain(|r| r.mint != mint_url.as_str());
+        Ok(())
+    }
+    async fn get_counters(&self, mint_url: &Url, pubkey: &str) -> Result<Vec<Record>, Self::Error> {
+        let mut records = self
+            .lock()
+            .counters
+            .iter()
+            .filter(|r| r.mint == mint_url.as_str() && r.pubkey == pubkey)
+            .cloned()
+            .collect::<Vec<_>>();
+        records.sort_by(|a, b| cmp_by_asc(a.ts, b.ts));
+        Ok(records)
+    }
+    async fn delete_proofs(
+        &self,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+    ) -> Result<(), Self::Error> {
+        self.lock().delete_proofs(mint_url, proofs);
+        Ok(())
+    }
+    async fn add_proofs(
+        &self,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+    ) -> Result<(), Self::Error> {
+        // all or nothing as the transaction of sqlite
+        let write = Write::AddProofs(mint_url.clone(), proofs.to_vec());
+        let mut tables = self.lock();
+        tables.check(std::slice::from_ref(&write))?;
+        tables.apply(&write);
+        Ok(())
+    }
+    async fn get_proofs_limit_unit(
+        &self,
+        mint_url: &Url,
+        unit: &str,
+    ) -> Result<ProofsExtended, Self::Error> {
+        let tables = self.lock();
+        let proofs = tables
+            .proofs_with_state(&[ProofState::Unspent])
+            .filter(|(m, p)| {
+                m == mint_url.as_str() && p.unit().unwrap_or(CURRENCY_UNIT_SAT) == unit
+            })
+            .map(|(_, p)| p.clone())
+            .collect();
+        Ok(proofs)
+    }
+    async fn get_proofs(&self, mint_url: &Url) -> Result<Map<String, ProofsExtended>, Self::Error> {
+        let tables = self.lock();
+        let mut proofs = Map::new();
+        for (_, p) in tables
+            .proofs_with_state(&[ProofState::Unspent])
+            .filter(|(m, _)| m == mint_url.as_str())
+        {
+            let k = p.unit().unwrap_or(CURRENCY_UNIT_SAT);
+            let ps: &mut Vec<_> = proofs.entry(k.to_owned()).or_default();
+            ps.push(p.clone());
+        }
+        Ok(proofs)
+    }
+    async fn get_all_proofs(
+        &self,
+    ) -> Result<Map<MintUrlWithUnitOwned, ProofsExtended>, Self::Error> {
+        self.get_all_proofs_with_state(&[ProofState::Unspent]).await
+    }
+    async fn update_proofs_state(
+        &self,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+        state: ProofState,
+        operation: Option<&str>,
+    ) -> Result<(), Self::Error> {
+        self.lock()
+            .update_proofs_state(mint_url, proofs, state, operation);
+        Ok(())
+    }
+    async fn get_all_proofs_with_state(
+        &self,
+        states: &[ProofState],
+    ) -> Result<Map<MintUrlWithUnitOwned, ProofsExtended>, Self::Error> {
+        let tables = self.lock();
+        let mut proofs = Map::new();
+        for (mint, p) in tables.proofs_with_state(states) {
+            let key = p.unit().unwrap_or(CURRENCY_UNIT_SAT);
+            let key = MintUrlWithUnit::new(mint.clone(), key).into_owned();
+
+            let ps: &mut Vec<_> = proofs.entry(key).or_default();
+            ps.push(p.clone());
+        }
+        Ok(proofs)
+    }
+    async fn get_proofs_with_state(
+        &self,
+        mint_url: &Url,
+        states: &[ProofState],
+    ) -> Result<ProofsExtended, Self::Error> {
+        let tables = self.lock();
+        let proofs = tables
+            .proofs_with_state(states)
+            .filter(|(m, _)| m == mint_url.as_str())
+            .map(|(_, p)| p.clone())
+            .collect();
+        Ok(proofs)
+    }
+    async fn delete_proofs_with_state(
+        &self,
+        states: &[ProofState],
+        unix_timestamp_ms_le: u64,
+    ) -> Result<u64, Self::Error> {
+        let mut tables = self.lock();
+        let Tables {
+            proofs, secrets, ..
+        } = &mut *tables;
+
+        let count = proofs.len();
+        proofs.retain(|(m, p)| {
+            let at = p.reserved_at.or(p.ts).unwrap_or_default();
+            let prune = states.contains(&p.state) && at <= unix_timestamp_ms_le;
+            if prune {
+                secrets.remove(&secret_key(m, p));
+            }
+            !prune
+        });
+        Ok((count - proofs.len()) as u64)
+    }
+    //
+    async fn migrate(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    //
+    // mints
+    /// overwrite it
+    async fn add_mint(&self, mint: &Mint) -> Result<(), Self::Error> {
+        let mut tables = self.lock();
+        match tables.mints.iter_mut().find(|m| m.url == mint.url) {
+            Some(m) => {
+                m.active = mint.active;
+                m.info = mint.info.clone();
+                m.pins = mint.pins.clone();
+            }
+            None => tables.mints.push(mint.clone()),
+        }
+        Ok(())
+    }
+    async fn get_mint(&self, mint_url: &str) -> Result<Option<Mint>, Self::Error> {
+        let tables = self.lock();
+        let mint = tables.mints.iter().find(|m| m.url == mint_url).cloned();
+        Ok(mint)
+    }
+    async fn get_mints(&self) -> Result<Vec<Mint>, Self::Error> {
+        let mut mints = self.lock().mints.clone();
+        mints.sort_by(|a, b| a.url.cmp(&b.url));
+        Ok(mints)
+    }
+    //
+    // tx
+    async fn delete_transactions(
+        &self,
+        status: &[TransactionStatus],
+        unix_timestamp_ms_le: u64,
+    ) -> Result<u64, Self::Error> {
+        let mut tables = self.lock();
+        let len = tables.transactions.len();
+        tables
+            .transactions
+            .retain(|tx| tx.time() > unix_timestamp_ms_le || !status.contains(&tx.status()));
+        Ok((len - tables.transactions.len()) as u64)
+    }
+    async fn add_transaction(&self, tx: &Transaction) -> Result<(), Self::Error> {
+        self.lock().add_transaction(tx);
+        Ok(())
+    }
+    async fn get_transaction(&self, txid: &str) -> Result<Option<Transaction>, Self::Error> {
+        let tables = self.lock();
+        let tx = tables
+            .transactions
+            .iter()
+            .find(|tx| tx.id() == txid)
+            .cloned();
+        Ok(tx)
+    }
+    async fn get_transactions(
+        &self,
+        status: &[TransactionStatus],
+    ) -> Result<Vec<Transaction>, Self::Error> {
+        let mut txs = self
+            .lock()
+            .transactions
+            .iter()
+            .filter(|tx| status.contains(&tx.status()))
+            .cloned()
+            .collect::<Vec<_>>();
+        txs.sort_by(|a, b| cmp_by_asc(a.time(), b.time()));
+        Ok(txs)
+    }
+    async fn get_transactions_with_offset(
+        &self,
+        offset: usize,
+        limit: usize,
+        kinds: &[TransactionKind],
+    ) -> Result<Vec<Transaction>, Self::Error> {
+        let mut txs = self
+            .lock()
+            .transactions
+            .iter()
+            .filter(|tx| kinds.contains(&tx.kind()))
+            .cloned()
+            .collect::<Vec<_>>();
+        txs.sort_by(|a, b| cmp_by_asc(b.time(), a.time()));
+        Ok(txs.into_iter().skip(offset).take(limit).collect())
+    }
+    //
+    // in-flight melts
+    async fn add_pending_melt(&self, melt: &PendingMelt) -> Result<(), Self::Error> {
+        self.lock().add_pending_melt(melt);
+        Ok(())
+    }
+    async fn delete_pending_melt(&self, mint_url: &str, quote: &str) -> Result<(), Self::Error> {
+        self.lock().delete_pending_melt(mint_url, quote);
+        Ok(())
+    }
+    async fn get_pending_melts(&self) -> Result<Vec<PendingMelt>, Self::Error> {
+        Ok(sorted_by_ctime(&self.lock().melts))
+    }
+    //
+    // write-ahead journal of swaps and mints
+    async fn add_operation(&self, op: &PendingOperation) -> Result<(), Self::Error> {
+        let mut tables = self.lock();
+        let found = tables
+            .operations
+            .iter_mut()
+            .find(|(_, o)| o.id == op.id && o.mint == op.mint);
+        match found {
+            Some((_, o)) => *o = op.clone(),
+            None => tables.operations.push((op.time, op.clone())),
+        }
+        Ok(())
+    }
+    async fn delete_operation(&self, mint_url: &str, id: &str) -> Result<(), Self::Error> {
+        self.lock().delete_operation(mint_url, id);
+        Ok(())
+    }
+    async fn get_operations(&self) -> Result<Vec<PendingOperation>, Self::Error> {
+        Ok(sorted_by_ctime(&self.lock().operations))
+    }
+    //
+    // keysets of mints
+    async fn add_keysets(&self, keysets: &[MintKeySet]) -> Result<(), Self::Error> {
+        let mut tables = self.lock();
+        for ks in keysets {
+            let found = tables
+                .keysets
+                .iter_mut()
+                .find(|(_, k)| k.keyset.id == ks.keyset.id && k.mint == ks.mint);
+            match found {
+                Some((_, k)) => *k = ks.clone(),
+                None => tables.keysets.push((ks.time, ks.clone())),
+            }
+        }
+        Ok(())
+    }
+    async fn get_keysets(&self, mint_url: &str) -> Result<Vec<MintKeySet>, Self::Error> {
+        let mut keysets = sorted_by_ctime(&self.lock().keysets);
+        keysets.retain(|ks| ks.mint == mint_url);
+        Ok(keysets)
+    }
+    //
+    async fn apply(&self, batch: &Batch) -> Result<(), Self::Error> {
+        debug!("apply: {} writes", batch.len());
+
+        let mut tables = self.lock();
+        tables.check(batch.writes())?;
+        for w in batch.writes() {
+            tables.apply(w);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tests;
+
+    #[tokio::test]
+    async fn it_works_mint() {
+        let db = MemoryStore::new();
+        tests::test_mint(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_counter() {
+        let db = MemoryStore::new();
+        tests::test_counter(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_proof() {
+        let db = MemoryStore::new();
+        tests::test_proof(&db, Some(true)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_proof_state() {
+        let db = MemoryStore::new();
+        tests::test_proof_state(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_transaction_cashu() {
+        let db = MemoryStore::new();
+        tests::test_transaction_cashu(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_transaction_ln() {
+        let db = MemoryStore::new();
+        tests::test_transaction_ln(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_pending_melt() {
+        let db = MemoryStore::new();
+        tests::test_pending_melt(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_keysets() {
+        let db = MemoryStore::new();
+        tests::test_keysets(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_operation() {
+        let db = MemoryStore::new();
+        tests::test_operation(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_batch() {
+        let db = MemoryStore::new();
+        tests::test_batch(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_batch_rollback() {
+        let db = MemoryStore::new();
+        tests::test_batch_rollback(&db).await.unwrap();
+    }
+}
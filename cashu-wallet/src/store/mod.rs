@@ -2,13 +2,24 @@ use std::error::Error as StdError;
 
 use std::collections::BTreeMap as Map;
 
-pub use crate::wallet::{MintUrl as Url, Proof, ProofExtended, Proofs, ProofsExtended, Record};
+pub use crate::wallet::{
+    MintUrl as Url, Proof, ProofExtended, ProofState, Proofs, ProofsExtended, Record,
+};
+
+use cashu::nuts::PreMint;
 
 use crate::types::Mint;
+use crate::types::MintKeySet;
+use crate::types::OperationKind;
+use crate::types::PendingMelt;
+use crate::types::PendingOperation;
 use crate::types::Transaction;
 use crate::types::TransactionKind;
 use crate::types::TransactionStatus;
 
+mod memory;
+pub use memory::{MemoryStore, MemoryStoreError};
+
 pub type MintUrlWithUnitOwned = MintUrlWithUnit<'static>;
 
 use std::borrow::Cow;
@@ -60,6 +71,130 @@ fn test_cmp_by() {
     assert_eq!(ps, desc);
 }
 
+/// a write of Batch, the same as the UnitedStore method of the name
+#[derive(Debug, Clone)]
+pub enum Write {
+    AddCounter(Record),
+    DeleteProofs(Url, ProofsExtended),
+    AddProofs(Url, ProofsExtended),
+    UpdateProofsState(Url, ProofsExtended, ProofState, Option<String>),
+    AddTransaction(Transaction),
+    AddPendingMelt(PendingMelt),
+    DeletePendingMelt(String, String),
+    DeleteOperation(String, String),
+}
+
+impl Write {
+    /// the name of UnitedStore method
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::AddCounter(_) => "add_counter",
+            Self::DeleteProofs(..) => "delete_proofs",
+            Self::AddProofs(..) => "add_proofs",
+            Self::UpdateProofsState(..) => "update_proofs_state",
+            Self::AddTransaction(_) => "add_transaction",
+            Self::AddPendingMelt(_) => "add_pending_melt",
+            Self::DeletePendingMelt(..) => "delete_pending_melt",
+            Self::DeleteOperation(..) => "delete_operation",
+        }
+    }
+}
+
+/// the unit of work applied by UnitedStore::apply: all the writes in order or nothing
+#[derive(Debug, Default, Clone)]
+pub struct Batch {
+    writes: Vec<Write>,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn writes(&self) -> &[Write] {
+        &self.writes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn add_counter(&mut self, record: &Record) -> &mut Self {
+        self.writes.push(Write::AddCounter(record.clone()));
+        self
+    }
+
+    pub fn delete_proofs(&mut self, mint_url: &Url, proofs: &[ProofExtended]) -> &mut Self {
+        if !proofs.is_empty() {
+            self.writes
+                .push(Write::DeleteProofs(mint_url.clone(), proofs.to_vec()));
+        }
+        self
+    }
+
+    pub fn add_proofs(&mut self, mint_url: &Url, proofs: &[ProofExtended]) -> &mut Self {
+        if !proofs.is_empty() {
+            self.writes
+                .push(Write::AddProofs(mint_url.clone(), proofs.to_vec()));
+        }
+        self
+    }
+
+    pub fn update_proofs_state(
+        &mut self,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+        state: ProofState,
+        operation: Option<&str>,
+    ) -> &mut Self {
+        if !proofs.is_empty() {
+            self.writes.push(Write::UpdateProofsState(
+                mint_url.clone(),
+                proofs.to_vec(),
+                state,
+                operation.map(|s| s.to_owned()),
+            ));
+        }
+        self
+    }
+
+    pub fn add_transaction(&mut self, tx: &Transaction) -> &mut Self {
+        self.writes.push(Write::AddTransaction(tx.clone()));
+        self
+    }
+
+    pub fn add_pending_melt(&mut self, melt: &PendingMelt) -> &mut Self {
+        self.writes.push(Write::AddPendingMelt(melt.clone()));
+        self
+    }
+
+    pub fn delete_pending_melt(&mut self, mint_url: &str, quote: &str) -> &mut Self {
+        self.writes.push(Write::DeletePendingMelt(
+            mint_url.to_owned(),
+            quote.to_owned(),
+        ));
+        self
+    }
+
+    pub fn delete_operation(&mut self, mint_url: &str, id: &str) -> &mut Self {
+        self.writes
+            .push(Write::DeleteOperation(mint_url.to_owned(), id.to_owned()));
+        self
+    }
+
+    /// store the counters committed under the journal and drop it
+    pub fn complete<S>(&mut self, journal: &Journal<'_, S>) -> &mut Self {
+        for r in journal.records() {
+            self.add_counter(&r);
+        }
+        self.delete_operation(journal.mint(), journal.id())
+    }
+}
+
 /// multiple mints wallet store
 #[async_trait]
 pub trait UnitedStore {
@@ -70,7 +205,7 @@ pub trait UnitedStore {
     async fn delete_counters(&self, mint_url: &Url) -> Result<(), Self::Error>;
     async fn get_counters(&self, mint_url: &Url, pubkey: &str) -> Result<Vec<Record>, Self::Error>;
     // async fn get_all_counters(&self) -> Result<Map<String, Vec<Record>>, Self::Error>;
-    // proofs
+    // proofs, the delete removes the proof of any state
     async fn delete_proofs(
         &self,
         mint_url: &Url,
@@ -88,6 +223,31 @@ pub trait UnitedStore {
         &self,
     ) -> Result<Map<MintUrlWithUnitOwned, ProofsExtended>, Self::Error>;
     //
+    // proof states, the get_proofs* above are the unspent only.
+    // the unspent clears the operation and reserved_at, the others keep the operation if None
+    async fn update_proofs_state(
+        &self,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+        state: ProofState,
+        operation: Option<&str>,
+    ) -> Result<(), Self::Error>;
+    async fn get_all_proofs_with_state(
+        &self,
+        states: &[ProofState],
+    ) -> Result<Map<MintUrlWithUnitOwned, ProofsExtended>, Self::Error>;
+    async fn get_proofs_with_state(
+        &self,
+        mint_url: &Url,
+        states: &[ProofState],
+    ) -> Result<ProofsExtended, Self::Error>;
+    // prune the proofs left the unspent(reserved_at) or added(ts) before, such as the spent
+    async fn delete_proofs_with_state(
+        &self,
+        states: &[ProofState],
+        unix_timestamp_ms_le: u64,
+    ) -> Result<u64, Self::Error>;
+    //
     async fn migrate(&self) -> Result<(), Self::Error>;
     //
     // mints
@@ -154,6 +314,23 @@ pub trait UnitedStore {
 
         Ok(remains[..take].to_vec())
     }
+    //
+    // in-flight melts
+    async fn add_pending_melt(&self, melt: &PendingMelt) -> Result<(), Self::Error>;
+    async fn delete_pending_melt(&self, mint_url: &str, quote: &str) -> Result<(), Self::Error>;
+    async fn get_pending_melts(&self) -> Result<Vec<PendingMelt>, Self::Error>;
+    //
+    // write-ahead journal of swaps and mints
+    async fn add_operation(&self, op: &PendingOperation) -> Result<(), Self::Error>;
+    async fn delete_operation(&self, mint_url: &str, id: &str) -> Result<(), Self::Error>;
+    async fn get_operations(&self) -> Result<Vec<PendingOperation>, Self::Error>;
+    //
+    // keysets of mints
+    async fn add_keysets(&self, keysets: &[MintKeySet]) -> Result<(), Self::Error>;
+    async fn get_keysets(&self, mint_url: &str) -> Result<Vec<MintKeySet>, Self::Error>;
+    //
+    // apply the writes in a transaction, the failed batch writes nothing
+    async fn apply(&self, batch: &Batch) -> Result<(), Self::Error>;
 }
 
 #[async_trait]
@@ -202,6 +379,40 @@ where
         self.as_ref().get_all_proofs().await
     }
     //
+    async fn update_proofs_state(
+        &self,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+        state: ProofState,
+        operation: Option<&str>,
+    ) -> Result<(), Self::Error> {
+        self.as_ref()
+            .update_proofs_state(mint_url, proofs, state, operation)
+            .await
+    }
+    async fn get_all_proofs_with_state(
+        &self,
+        states: &[ProofState],
+    ) -> Result<Map<MintUrlWithUnitOwned, ProofsExtended>, Self::Error> {
+        self.as_ref().get_all_proofs_with_state(states).await
+    }
+    async fn get_proofs_with_state(
+        &self,
+        mint_url: &Url,
+        states: &[ProofState],
+    ) -> Result<ProofsExtended, Self::Error> {
+        self.as_ref().get_proofs_with_state(mint_url, states).await
+    }
+    async fn delete_proofs_with_state(
+        &self,
+        states: &[ProofState],
+        unix_timestamp_ms_le: u64,
+    ) -> Result<u64, Self::Error> {
+        self.as_ref()
+            .delete_proofs_with_state(states, unix_timestamp_ms_le)
+            .await
+    }
+    //
     async fn migrate(&self) -> Result<(), Self::Error> {
         self.as_ref().migrate().await
     }
@@ -255,6 +466,35 @@ where
             .get_transactions_with_offset(offset, limit, kinds)
             .await
     }
+    //
+    // in-flight melts
+    async fn add_pending_melt(&self, melt: &PendingMelt) -> Result<(), Self::Error> {
+        self.as_ref().add_pending_melt(melt).await
+    }
+    async fn delete_pending_melt(&self, mint_url: &str, quote: &str) -> Result<(), Self::Error> {
+        self.as_ref().delete_pending_melt(mint_url, quote).await
+    }
+    async fn get_pending_melts(&self) -> Result<Vec<PendingMelt>, Self::Error> {
+        self.as_ref().get_pending_melts().await
+    }
+    async fn add_operation(&self, op: &PendingOperation) -> Result<(), Self::Error> {
+        self.as_ref().add_operation(op).await
+    }
+    async fn delete_operation(&self, mint_url: &str, id: &str) -> Result<(), Self::Error> {
+        self.as_ref().delete_operation(mint_url, id).await
+    }
+    async fn get_operations(&self) -> Result<Vec<PendingOperation>, Self::Error> {
+        self.as_ref().get_operations().await
+    }
+    async fn add_keysets(&self, keysets: &[MintKeySet]) -> Result<(), Self::Error> {
+        self.as_ref().add_keysets(keysets).await
+    }
+    async fn get_keysets(&self, mint_url: &str) -> Result<Vec<MintKeySet>, Self::Error> {
+        self.as_ref().get_keysets(mint_url).await
+    }
+    async fn apply(&self, batch: &Batch) -> Result<(), Self::Error> {
+        self.as_ref().apply(batch).await
+    }
 }
 
 use crate::wallet::RecordStore;
@@ -273,6 +513,98 @@ where
     async fn get_records(&self, mint_url: &Url, pubkey: &str) -> Result<Vec<Record>, Self::Error> {
         self.get_counters(mint_url, pubkey).await
     }
+    // not journaled, see Journal
+    async fn add_outputs(
+        &self,
+        _outputs: &[PreMint],
+        _counters: Option<(u64, u64)>,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// the RecordStore journals the outputs of an operation into UnitedStore before calling mint
+#[derive(Debug)]
+pub struct Journal<'a, S> {
+    store: &'a S,
+    op: PendingOperation,
+    // the counters committed by the wallet, stored with the batch completing the journal
+    records: std::sync::Mutex<Vec<Record>>,
+}
+
+impl<'a, S> Journal<'a, S> {
+    pub fn new(
+        store: &'a S,
+        kind: OperationKind,
+        mint_url: &Url,
+        unit: Option<&str>,
+        inputs: &[ProofExtended],
+    ) -> Self {
+        let op = PendingOperation {
+            id: hex::encode(rand::random::<[u8; 16]>()),
+            kind,
+            mint: mint_url.as_str().to_owned(),
+            unit: unit.map(|s| s.to_owned()),
+            inputs: inputs.to_vec(),
+            outputs: vec![],
+            counters: None,
+            time: crate::types::unixtime_ms(),
+        };
+
+        Self {
+            store,
+            op,
+            records: Default::default(),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.op.id
+    }
+
+    pub fn mint(&self) -> &str {
+        &self.op.mint
+    }
+
+    pub fn records(&self) -> Vec<Record> {
+        self.records.lock().map(|rs| rs.clone()).unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl<'a, T> RecordStore for &Journal<'a, T>
+where
+    T: UnitedStore + Sync + Send + 'static,
+{
+    type Error = T::Error;
+    // buffered for Batch::complete, the journaled counters are recovered if crashed before it
+    async fn add_record(&self, record: &Record) -> Result<(), Self::Error> {
+        if let Ok(mut rs) = self.records.lock() {
+            rs.retain(|r| {
+                (&r.mint, &r.pubkey, &r.keysetid)
+                    != (&record.mint, &record.pubkey, &record.keysetid)
+            });
+            rs.push(record.clone());
+        }
+        Ok(())
+    }
+    async fn delete_records(&self, mint_url: &Url) -> Result<(), Self::Error> {
+        self.store.delete_counters(mint_url).await
+    }
+    async fn get_records(&self, mint_url: &Url, pubkey: &str) -> Result<Vec<Record>, Self::Error> {
+        self.store.get_counters(mint_url, pubkey).await
+    }
+    // the retry with new outputs replaces the journal
+    async fn add_outputs(
+        &self,
+        outputs: &[PreMint],
+        counters: Option<(u64, u64)>,
+    ) -> Result<(), Self::Error> {
+        let mut op = self.op.clone();
+        op.outputs = outputs.to_vec();
+        op.counters = counters;
+        self.store.add_operation(&op).await
+    }
 }
 
 // #[cfg(test)]
@@ -303,6 +635,7 @@ pub mod tests {
             active: true,
             time: unixtime_ms(),
             info: None,
+            pins: vec![],
         };
 
         store.add_mint(&mint).await?;
@@ -319,6 +652,7 @@ pub mod tests {
             active: true,
             time: unixtime_ms(),
             info: Some(info),
+            pins: vec!["47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_owned()],
         };
 
         store.add_mint(&mint2).await?;
@@ -336,6 +670,11 @@ pub mod tests {
         let m2 = store.get_mint(&mint2.url).await?.expect("None");
         assert_eq!(m2.active, false);
 
+        mint2.pins.clear();
+        store.add_mint(&mint2).await?;
+        let m2 = store.get_mint(&mint2.url).await?.expect("None");
+        assert_eq!(m2.pins.len(), 0);
+
         let mut ms = store.get_mints().await?;
         ms.retain(|m| m.active);
         assert_eq!(ms.as_slice(), [mint.clone()].as_slice());
@@ -586,6 +925,103 @@ pub mod tests {
         Ok(())
     }
 
+    pub async fn test_proof_state<S: UnitedStore + Sync>(store: &S) -> Result<(), S::Error> {
+        let url: Url = MINT_URL.parse().unwrap();
+        let mut ps = random_proofs(&[7]);
+        for p in &mut ps {
+            p.unit = Some(CURRENCY_UNIT_SAT.to_owned());
+        }
+        let sent = random_proofs(&[8])
+            .into_iter()
+            .map(|p| p.reserve("txid"))
+            .collect::<Vec<_>>();
+        store.add_proofs(&url, &ps).await?;
+        store.add_proofs(&url, &sent).await?;
+
+        // only the unspent are selected
+        let got = store.get_proofs_limit_unit(&url, CURRENCY_UNIT_SAT).await?;
+        assert_eq!(got.sum().to_u64(), 7);
+        let all = store.get_all_proofs().await?;
+        assert_eq!(all.values().flatten().count(), ps.len());
+
+        let reserved = store
+            .get_all_proofs_with_state(&[ProofState::Reserved])
+            .await?;
+        let reserved = reserved.values().flatten().collect::<Vec<_>>();
+        assert_eq!(reserved.len(), sent.len());
+        assert!(reserved
+            .iter()
+            .all(|p| p.operation.as_deref() == Some("txid") && p.reserved_at.is_some()));
+
+        // melt: reserved -> pending -> unspent
+        let inputs = &ps[..1];
+        store
+            .update_proofs_state(&url, inputs, ProofState::Reserved, Some("quote"))
+            .await?;
+        store
+            .update_proofs_state(&url, inputs, ProofState::Pending, None)
+            .await?;
+        let pending = store
+            .get_all_proofs_with_state(&[ProofState::Pending])
+            .await?;
+        let pending = pending.values().flatten().collect::<Vec<_>>();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].raw, inputs[0].raw);
+        assert_eq!(pending[0].operation.as_deref(), Some("quote"));
+        assert!(pending[0].reserved_at.is_some());
+        let got = store.get_proofs_limit_unit(&url, CURRENCY_UNIT_SAT).await?;
+        assert_eq!(got.len(), ps.len() - 1);
+
+        store
+            .update_proofs_state(&url, inputs, ProofState::Unspent, None)
+            .await?;
+        let mut got = store.get_proofs_limit_unit(&url, CURRENCY_UNIT_SAT).await?;
+        got.sort_by(|a, b| a.as_ref().amount.cmp(&b.as_ref().amount));
+        let mut ps2 = ps.clone();
+        ps2.sort_by(|a, b| a.as_ref().amount.cmp(&b.as_ref().amount));
+        assert_eq!(got, ps2);
+
+        // the token claimed
+        let mut batch = Batch::new();
+        batch.update_proofs_state(&url, &sent, ProofState::Spent, None);
+        store.apply(&batch).await?;
+        let all = store.get_all_proofs_with_state(&ProofState::ALL).await?;
+        let all = all.values().flatten().collect::<Vec<_>>();
+        assert_eq!(all.len(), ps.len() + sent.len());
+        assert_eq!(
+            all.iter().filter(|p| p.state == ProofState::Spent).count(),
+            sent.len()
+        );
+        let spent = store
+            .get_proofs_with_state(&url, &[ProofState::Spent])
+            .await?;
+        assert_eq!(spent.len(), sent.len());
+        let url2: Url = MINT_URL2.parse().unwrap();
+        let got = store.get_proofs_with_state(&url2, &ProofState::ALL).await?;
+        assert!(got.is_empty());
+
+        // prune the spent by the time reserved
+        let at = spent[0].reserved_at.unwrap();
+        let dc = store
+            .delete_proofs_with_state(&[ProofState::Spent], at - 1)
+            .await?;
+        assert_eq!(dc, 0);
+        let dc = store
+            .delete_proofs_with_state(&[ProofState::Spent], unixtime_ms())
+            .await?;
+        assert_eq!(dc as usize, sent.len());
+        let got = store.get_proofs_with_state(&url, &ProofState::ALL).await?;
+        assert_eq!(got.len(), ps.len());
+
+        // the delete removes any state
+        store.delete_proofs(&url, &ps).await?;
+        store.delete_proofs(&url, &sent).await?;
+        let all = store.get_all_proofs_with_state(&ProofState::ALL).await?;
+        assert_eq!(all.len(), 0);
+
+        Ok(())
+    }
+
     use crate::wallet::{MintProofsGeneric, TokenV3Generic};
     fn random_tokens(amounts: &[u64]) -> TokenV3Generic<ProofsExtended> {
         let mut tokens = TokenV3Generic {
@@ -619,6 +1055,7 @@ pub mod tests {
             amount: 1,
             mint: MINT_URL.to_string(),
             unit: None,
+            fee: Some(1),
             token,
         };
 
@@ -685,6 +1122,7 @@ pub mod tests {
             hash: hash.to_owned(),
             fee: None,
             unit: None,
+            preimage: Some("00".repeat(32)),
         };
 
         println!("hash: {}, hashg: {}", hash, tx0.id(),);
@@ -735,4 +1173,228 @@ pub mod tests {
 
         Ok(())
     }
+
+    pub async fn test_pending_melt<S: UnitedStore + Sync>(store: &S) -> Result<(), S::Error> {
+        let melts = store.get_pending_melts().await?;
+        assert_eq!(melts, vec![]);
+
+        let mut melt = PendingMelt {
+            quote: "Ewh2Og86r9jsLXbgLJrdWoqgO3mjXSKV-HAYSpDz".to_owned(),
+            mint: MINT_URL.to_string(),
+            unit: Some(CURRENCY_UNIT_SAT.to_owned()),
+            amount: 2,
+            fee_reserve: 1,
+            inputs: random_proofs(&[1, 2]),
+            outputs: vec![],
+            time: unixtime_ms(),
+        };
+
+        store.add_pending_melt(&melt).await?;
+        let melts = store.get_pending_melts().await?;
+        assert_eq!(melts, vec![melt.clone()]);
+
+        // replace
+        melt.fee_reserve = 2;
+        store.add_pending_melt(&melt).await?;
+        let melts = store.get_pending_melts().await?;
+        assert_eq!(melts, vec![melt.clone()]);
+
+        store.delete_pending_melt(MINT_URL2, &melt.quote).await?;
+        let melts = store.get_pending_melts().await?;
+        assert_eq!(melts.len(), 1);
+
+        store.delete_pending_melt(MINT_URL, &melt.quote).await?;
+        let melts = store.get_pending_melts().await?;
+        assert_eq!(melts, vec![]);
+
+        Ok(())
+    }
+
+    pub async fn test_operation<S: UnitedStore + Sync>(store: &S) -> Result<(), S::Error> {
+        let ops = store.get_operations().await?;
+        assert_eq!(ops, vec![]);
+
+        let url: Url = MINT_URL.parse().unwrap();
+        let inputs = random_proofs(&[3]);
+        let journal = Journal::new(store, OperationKind::Swap, &url, None, &inputs);
+        let mut op = journal.op.clone();
+        op.counters = Some((10, 12));
+
+        store.add_operation(&op).await?;
+        let ops = store.get_operations().await?;
+        assert_eq!(ops, vec![op.clone()]);
+
+        // replace
+        op.counters = Some((12, 14));
+        store.add_operation(&op).await?;
+        let ops = store.get_operations().await?;
+        assert_eq!(ops, vec![op.clone()]);
+
+        store.delete_operation(MINT_URL2, journal.id()).await?;
+        let ops = store.get_operations().await?;
+        assert_eq!(ops.len(), 1);
+
+        store.delete_operation(journal.mint(), journal.id()).await?;
+        let ops = store.get_operations().await?;
+        assert_eq!(ops, vec![]);
+
+        Ok(())
+    }
+
+    pub async fn test_batch<S: UnitedStore + Sync>(store: &S) -> Result<(), S::Error> {
+        let url: Url = MINT_URL.parse().unwrap();
+        let inputs = random_proofs(&[7]);
+        store.add_proofs(&url, &inputs).await?;
+
+        let journal = Journal::new(store, OperationKind::Swap, &url, None, &inputs);
+        store.add_operation(&journal.op).await?;
+
+        // send
+        let keep = random_proofs(&[4]);
+        let tokens = random_tokens(&[3]);
+        let tx: Transaction = CashuTransaction::new(
+            TransactionStatus::Pending,
+            TransactionDirection::Out,
+            3,
+            MINT_URL,
+            &tokens.to_string(),
+            None,
+            None,
+        )
+        .into();
+        let record = Record {
+            mint: MINT_URL.to_owned(),
+            keysetid: "00759e3f8b06b36f".to_owned(),
+            counter: 4,
+            ts: unixtime_ms(),
+            pubkey: "pubkey".to_owned(),
+        };
+
+        let mut batch = Batch::new();
+        batch
+            .add_counter(&record)
+            .add_proofs(&url, &keep)
+            .delete_proofs(&url, &inputs)
+            .delete_proofs(&url, &[])
+            .add_transaction(&tx)
+            .delete_operation(journal.mint(), journal.id());
+        assert_eq!(batch.len(), 5);
+        store.apply(&batch).await?;
+
+        let ps = store.get_proofs_limit_unit(&url, CURRENCY_UNIT_SAT).await?;
+        assert_eq!(ps.sum().to_u64(), 4);
+        assert_eq!(store.get_transaction(tx.id()).await?, Some(tx.clone()));
+        assert_eq!(store.get_operations().await?, vec![]);
+        assert_eq!(store.get_counters(&url, "pubkey").await?, vec![record]);
+
+        // melt: reserve, then settle with the change in order
+        let mut melt = PendingMelt {
+            quote: "Ewh2Og86r9jsLXbgLJrdWoqgO3mjXSKV-HAYSpDz".to_owned(),
+            mint: MINT_URL.to_string(),
+            unit: Some(CURRENCY_UNIT_SAT.to_owned()),
+            amount: 2,
+            fee_reserve: 2,
+            inputs: ps.clone(),
+            outputs: vec![],
+            time: unixtime_ms(),
+        };
+        let mut batch = Batch::new();
+        batch
+            .add_pending_melt(&melt)
+            .delete_proofs(&url, &melt.inputs);
+        store.apply(&batch).await?;
+        assert_eq!(store.get_pending_melts().await?, vec![melt.clone()]);
+        assert_eq!(store.get_proofs(&url).await?.len(), 0);
+
+        let change = random_proofs(&[1]);
+        melt.fee_reserve = 1;
+        let mut batch = Batch::new();
+        batch
+            .add_pending_melt(&melt)
+            .add_proofs(&url, &change)
+            .delete_pending_melt(&melt.mint, &melt.quote);
+        store.apply(&batch).await?;
+        assert_eq!(store.get_pending_melts().await?, vec![]);
+        let ps = store.get_proofs_limit_unit(&url, CURRENCY_UNIT_SAT).await?;
+        assert_eq!(ps.sum().to_u64(), 1);
+
+        store.apply(&Batch::new()).await?;
+        store.delete_proofs(&url, &ps).await?;
+
+        Ok(())
+    }
+
+    pub async fn test_batch_rollback<S: UnitedStore + Sync>(store: &S) -> Result<(), S::Error> {
+        let url: Url = MINT_URL.parse().unwrap();
+        let ps = random_proofs(&[1]);
+        store.add_proofs(&url, &ps).await?;
+
+        let tx: Transaction = CashuTransaction::new(
+            TransactionStatus::Pending,
+            TransactionDirection::Out,
+            1,
+            MINT_URL,
+            "cashuA",
+            None,
+            None,
+        )
+        .into();
+
+        // the proofs exist already, the 2nd add fails and nothing is written
+        let mut batch = Batch::new();
+        batch
+            .delete_proofs(&url, &ps)
+            .add_proofs(&url, &ps)
+            .add_proofs(&url, &ps)
+            .add_transaction(&tx);
+        assert!(store.apply(&batch).await.is_err());
+
+        assert_eq!(store.get_transaction(tx.id()).await?, None);
+        let got = store.get_proofs_limit_unit(&url, CURRENCY_UNIT_SAT).await?;
+        assert_eq!(got.len(), 1);
+
+        store.delete_proofs(&url, &ps).await?;
+
+        Ok(())
+    }
+
+    pub async fn test_keysets<S: UnitedStore + Sync>(store: &S) -> Result<(), S::Error> {
+        use cashu::nuts::nut01::Keys;
+        use cashu::nuts::nut02::{Id, KeySet, KeySetInfo};
+        use cashu::nuts::CurrencyUnit;
+
+        let keysets = store.get_keysets(MINT_URL).await?;
+        assert_eq!(keysets, vec![]);
+
+        let id: Id = "009a1f293253e41e".parse().unwrap();
+        let mut keyset = MintKeySet {
+            mint: MINT_URL.to_string(),
+            keyset: KeySet {
+                id,
+                unit: CurrencyUnit::Sat,
+                keys: Keys::new(Default::default()),
+            },
+            info: Some(KeySetInfo {
+                id,
+                unit: CurrencyUnit::Sat,
+                active: true,
+                input_fee_ppk: 100,
+            }),
+            time: unixtime_ms(),
+        };
+
+        store.add_keysets(&[keyset.clone()]).await?;
+        let keysets = store.get_keysets(MINT_URL).await?;
+        assert_eq!(keysets, vec![keyset.clone()]);
+        assert_eq!(store.get_keysets(MINT_URL2).await?, vec![]);
+
+        // update the active flag
+        keyset.info.as_mut().unwrap().active = false;
+        store.add_keysets(&[keyset.clone()]).await?;
+        let keysets = store.get_keysets(MINT_URL).await?;
+        assert_eq!(keysets, vec![keyset.clone()]);
+        assert!(!keysets[0].active());
+
+        Ok(())
+    }
 }
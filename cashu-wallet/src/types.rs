@@ -1,5 +1,9 @@
 use strum::{AsRefStr, Display, EnumIs, EnumString, IntoStaticStr};
 
+use crate::wallet::ProofsExtended;
+use cashu::nuts::nut02::{KeySet, KeySetInfo};
+use cashu::nuts::PreMint;
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 //
 #[derive(Display, AsRefStr, IntoStaticStr, EnumIs, EnumString)]
@@ -112,11 +116,18 @@ impl Transaction {
 
     pub fn fee(&self) -> Option<u64> {
         match self {
-            Transaction::Cashu(_) => None,
+            Transaction::Cashu(transaction) => transaction.fee,
             Transaction::LN(transaction) => transaction.fee,
         }
     }
 
+    pub fn preimage(&self) -> Option<&str> {
+        match self {
+            Transaction::Cashu(_) => None,
+            Transaction::LN(transaction) => transaction.preimage.as_deref(),
+        }
+    }
+
     pub fn as_json(&self) -> String {
         serde_json::to_string(self).expect("json encode")
     }
@@ -149,6 +160,9 @@ pub struct CashuTransaction {
     pub token: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub unit: Option<String>,
+    // NUT-02 input fee of the swap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee: Option<u64>,
 }
 
 pub fn hashid(data: impl AsRef<[u8]>) -> String {
@@ -177,6 +191,7 @@ impl CashuTransaction {
             mint: mint.to_string(),
             token: token.to_string(),
             unit: unit.map(|s| s.to_owned()),
+            fee: None,
         };
 
         this
@@ -208,6 +223,9 @@ pub struct LNTransaction {
     pub hash: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub unit: Option<String>,
+    // payment preimage of the melt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preimage: Option<String>,
 }
 /*
 {"pr":"lnbc1m1pjslwjhsp5zyntvam8ys92t4m2qxmmva0dulqnr6l4mscnwwwdzawlq9cevx4qpp57vfpu3jffd0tyvg8fj93vggvwxqud8stvdwzer0fpha8ru5rpqnqdq4gdshx6r4ypjx2ur0wd5hgxqzjccqpjrzjqg7dvuzvu7ryfftgl0ve8ajacahmr0utenjvjy5nq3ruw8gvy6v26rq9e5qqwvqqquqqqqqqqqqqqxgq9q9qxpqysgqg4gj9vsd80ff0zcl25hsh2akg54dfhy2dez9ztgl9zvznt4lf2k860juys8tpenkaq933tf9ssns52lmcqmar6a9rjdg2nmfwxz8edgptd732x",
@@ -247,6 +265,7 @@ impl LNTransaction {
             pr: pr.to_string(),
             hash: hash.to_string(),
             unit: unit.map(|s| s.to_owned()),
+            preimage: None,
         };
         this
     }
@@ -254,6 +273,88 @@ impl LNTransaction {
         &self.hash
     }
 }
+/// the in-flight NUT-05 melt, the inputs are reserved until the payment settles or fails
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingMelt {
+    pub quote: String,
+    pub mint: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    pub amount: u64,
+    pub fee_reserve: u64,
+    // the reserved input proofs
+    pub inputs: ProofsExtended,
+    // the NUT-08 blank outputs for the change
+    pub outputs: Vec<PreMint>,
+    pub time: u64,
+}
+
+/// the kind of the operation journaled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OperationKind {
+    /// swap the proofs of wallet: send, split before melt, etc
+    Swap,
+    /// swap the received tokens
+    Receive,
+    /// NUT-04 mint
+    Mint,
+}
+
+/// the write-ahead journal of a swap or mint: the inputs, outputs and NUT-13 counters are persisted
+/// before calling mint and removed after the results stored, the ones left by a crash are
+/// replayed or rolled back by UnitedWallet::recover_operations
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingOperation {
+    pub id: String,
+    pub kind: OperationKind,
+    pub mint: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    // the input proofs, empty for mint
+    pub inputs: ProofsExtended,
+    // the outputs sent to mint
+    pub outputs: Vec<PreMint>,
+    // the NUT-13 counters [start, end) of the keyset of outputs, None without mnemonic
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counters: Option<(u64, u64)>,
+    pub time: u64,
+}
+
+/// the balance of a mint unit by the proof states, see ProofState
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Balance {
+    /// the unspent proofs could be spent now
+    pub available: u64,
+    /// the tokens sent but not claimed, the inputs of melts not sent to mint
+    pub reserved: u64,
+    /// the inputs of in-flight melts
+    pub pending: u64,
+}
+
+impl Balance {
+    pub fn total(&self) -> u64 {
+        self.available + self.reserved + self.pending
+    }
+}
+
+/// the keyset of mint cached in database
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MintKeySet {
+    pub mint: String,
+    pub keyset: KeySet,
+    // NUT-02 active and input_fee_ppk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub info: Option<KeySetInfo>,
+    pub time: u64,
+}
+
+impl MintKeySet {
+    pub fn active(&self) -> bool {
+        self.info.as_ref().map(|i| i.active).unwrap_or(true)
+    }
+}
+
 impl From<LNTransaction> for Transaction {
     fn from(val: LNTransaction) -> Self {
         Transaction::LN(val)
@@ -314,6 +415,8 @@ pub struct Nuts {
     pub nut11: NutSupported,
     #[serde(default, rename = "12")]
     pub nut12: NutSupported,
+    #[serde(default, rename = "17")]
+    pub nut17: Nut17Settings,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -324,6 +427,15 @@ pub struct PaymentMethod {
     pub min_amount: i64,
     #[serde(default)]
     pub max_amount: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<PaymentMethodOptions>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaymentMethodOptions {
+    /// NUT-05 melt the invoice without amount
+    #[serde(default)]
+    pub amountless: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -335,6 +447,17 @@ pub struct PaymentMethodSettings {
     pub disabled: bool,
 }
 
+impl PaymentMethodSettings {
+    pub fn supports_amountless(&self, method: &str, unit: &str) -> bool {
+        !self.disabled
+            && self.methods.iter().any(|m| {
+                m.method == method
+                    && m.unit == unit
+                    && m.options.as_ref().map(|o| o.amountless).unwrap_or_default()
+            })
+    }
+}
+
 // default should disabled
 impl Default for PaymentMethodSettings {
     fn default() -> Self {
@@ -350,12 +473,39 @@ pub struct NutSupported {
     pub supported: bool,
 }
 
+/// NUT-17 WebSocket subscriptions
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Nut17Settings {
+    #[serde(default)]
+    pub supported: Vec<Nut17Method>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Nut17Method {
+    pub method: String,
+    pub unit: String,
+    #[serde(default)]
+    pub commands: Vec<String>,
+}
+
+impl Nut17Settings {
+    /// command: bolt11_mint_quote, bolt11_melt_quote, proof_state
+    pub fn supports(&self, method: &str, unit: &str, command: &str) -> bool {
+        self.supported.iter().any(|m| {
+            m.method == method && m.unit == unit && m.commands.iter().any(|c| c == command)
+        })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Mint {
     pub url: String,
     pub active: bool,
     pub time: u64,
     pub info: Option<MintInfo>,
+    /// the SPKI pins of the TLS certificates, see HttpOptions::mint_pins
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pins: Vec<String>,
 }
 
 impl Mint {
@@ -365,6 +515,7 @@ impl Mint {
             info,
             active: true,
             time: unixtime_ms(),
+            pins: vec![],
         }
     }
 }
@@ -431,6 +582,8 @@ pub mod tests {
     fn test_17_mint_information_minibits_16() {
         let js: MintInfo = serde_json::from_str(INFO_MINIBITS_NUTSHELL16).unwrap();
         assert_eq!(js.name, "Minibits mint");
+        assert!(js.nuts.nut17.supports("bolt11", "sat", "bolt11_mint_quote"));
+        assert!(!js.nuts.nut17.supports("bolt11", "usd", "proof_state"));
         assert_eq!(js.nuts.nut04.disabled, false);
         assert!(js.nuts.nut04.methods.len() > 0);
         assert_eq!(js.nuts.nut05.disabled, false);
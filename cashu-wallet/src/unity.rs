@@ -1,34 +1,56 @@
 pub use crate::wallet::MintUrl as Url;
 pub use cashu;
 use cashu::nuts::nut00;
+use cashu::nuts::nut02::{Id as KeySetId, KeySetVersion};
+use cashu::nuts::nut04;
 use cashu::nuts::nut05;
+use cashu::nuts::nut07;
+use cashu::secret::Secret;
 pub use url::ParseError;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::error::Error as StdError;
 use std::sync::Arc;
 use std::sync::RwLock;
+use std::time::Duration;
+use tokio::time::Instant;
 
 use crate::store::MintUrlWithUnit;
 use crate::store::ProofsExtended;
+use crate::wallet::fee_from_ppk;
+use crate::wallet::proofs_ys;
 use crate::wallet::ClientError;
+use crate::wallet::DleqPolicy;
+use crate::wallet::MintErrorCode;
 use crate::wallet::MnemonicInfo;
 use crate::wallet::SplitProofsGeneric;
 use crate::wallet::WalletError;
 use crate::wallet::CURRENCY_UNIT_SAT;
+use crate::wallet::PAYMEN_METHOD_BOLT11;
 use crate::wallet::{AmountHelper, ProofsHelper, Token, Wallet};
 use crate::wallet::{HttpOptions, MintClient};
-use crate::wallet::{Proof, SplitProofsExtended};
+use crate::wallet::{MeltResponse, SubscriptionKind};
+use crate::wallet::{PaymentRequest, PaymentRequestPayload};
+use crate::wallet::{Proof, ProofState, SplitProofsExtended};
+use crate::wallet::{SpendingConditions, Unlocking};
 
+use crate::store::Batch;
+use crate::store::Journal;
 use crate::store::MintUrlWithUnitOwned;
 use crate::store::UnitedStore;
 
+use crate::types::unixtime_ms;
+use crate::types::Balance;
 use crate::types::Mint;
+use crate::types::MintKeySet;
+use crate::types::PendingMelt;
 use crate::types::{
     CashuTransaction, LNTransaction, Transaction, TransactionDirection, TransactionStatus,
 };
+use crate::types::{OperationKind, PendingOperation};
 
 use cashu::nuts::nut07::State;
+use cashu::types::Melted;
 use cashu::Bolt11Invoice;
 
 #[derive(Debug)]
@@ -45,6 +67,9 @@ pub enum UniError<E: StdError> {
     Client(#[from] ClientError),
     #[error("Insufficant Funds")]
     InsufficientFunds,
+    /// NUT-12 DLEQ verification failed
+    #[error("DLEQ verification failed: {0}")]
+    Dleq(String),
     /// custum error
     #[error(transparent)]
     Custom(#[from] anyhow::Error),
@@ -52,6 +77,43 @@ pub enum UniError<E: StdError> {
     Store(E),
 }
 
+impl<E: StdError> UniError<E> {
+    pub fn is_network_error(&self) -> bool {
+        if let UniError::Client(c) = self {
+            return c.is_network_error();
+        }
+        false
+    }
+    /// the request never reached the mint, see ClientError::is_unreachable
+    pub fn is_unreachable(&self) -> bool {
+        if let UniError::Client(c) = self {
+            return c.is_unreachable();
+        }
+        false
+    }
+    /// the mint error code returned
+    pub fn mint_error_code(&self) -> Option<MintErrorCode> {
+        if let UniError::Client(c) = self {
+            return c.mint_error_code();
+        }
+        None
+    }
+    /// the same request maybe succeed later, see ClientError::is_retryable
+    pub fn is_retryable(&self) -> bool {
+        if let UniError::Client(c) = self {
+            return c.is_retryable();
+        }
+        false
+    }
+    /// the mint rejected the request and it never succeed, see ClientError::is_permanent
+    pub fn is_permanent(&self) -> bool {
+        if let UniError::Client(c) = self {
+            return c.is_permanent();
+        }
+        false
+    }
+}
+
 impl<E: StdError> From<WalletError> for UniError<E> {
     fn from(value: WalletError) -> Self {
         match value {
@@ -60,6 +122,7 @@ impl<E: StdError> From<WalletError> for UniError<E> {
             WalletError::InsufficientFunds => UniError::InsufficientFunds,
             WalletError::MintUrlUnmatched => UniError::MintUrlUnmatched,
             WalletError::Custom(e) => UniError::Custom(e),
+            WalletError::Dleq(e) => UniError::Dleq(e),
         }
     }
 }
@@ -98,6 +161,8 @@ where
     http_options: Arc<HttpOptions>,
     mnemonic: Option<Arc<MnemonicInfo>>,
     wallets: RwLock<BTreeMap<String, Arc<Wallet>>>,
+    // NUT-12 DLEQ policy of mints, default is VerifyIfPresent
+    dleqs: RwLock<BTreeMap<String, DleqPolicy>>,
 }
 
 impl<S> UnitedWallet<S>
@@ -118,6 +183,7 @@ where
             mnemonic,
             http_options: Arc::new(http_options),
             wallets: Default::default(),
+            dleqs: Default::default(),
         }
     }
 
@@ -145,6 +211,40 @@ where
 
         Ok(urls)
     }
+    pub fn dleq_policy(&self, mint: &Url) -> Result<DleqPolicy, Error<S::Error>> {
+        let policy = self
+            .dleqs
+            .read()
+            .map_err(|e| format_err!("dleqs read {}", e))?
+            .get(mint.as_str())
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(policy)
+    }
+
+    /// set NUT-12 DLEQ verification policy for the mint, it works for the added and later added wallet
+    pub fn set_dleq_policy(&self, mint: &Url, policy: DleqPolicy) -> Result<(), Error<S::Error>> {
+        self.dleqs
+            .write()
+            .map_err(|e| format_err!("dleqs write {}", e))?
+            .insert(mint.as_str().to_owned(), policy);
+
+        let mut lock = self
+            .wallets
+            .write()
+            .map_err(|e| format_err!("wallets write {}", e))?;
+        if let Some(w) = lock.get_mut(mint.as_str()) {
+            if w.dleq_policy() != policy {
+                let mut wallet = w.as_ref().clone();
+                wallet.set_dleq_policy(policy);
+                *w = Arc::new(wallet);
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn contains(&self, mint: &Url) -> Result<bool, Error<S::Error>> {
         let has = self
             .wallets
@@ -226,10 +326,17 @@ where
             wallet = w;
         }
 
+        let record = self.store.get_mint(mint_url.as_str()).await?;
         if wallet.is_none() || reconnect {
-            let client = MintClient::new(mint_url.clone(), self.http_options.as_ref().clone())?;
+            let pins = record
+                .as_ref()
+                .map(|r| r.pins.as_slice())
+                .unwrap_or_default();
+            let client = self.mint_client(&mint_url, pins)?;
             let mn = self.mnemonic.clone();
-            let w = Wallet::new(client, None, None, mn, self.store(), None).await?;
+            let mut w = Wallet::new(client, None, None, mn, self.store(), None).await?;
+            w.set_dleq_policy(self.dleq_policy(&mint_url)?);
+            self.store_keysets(&w).await?;
             let w = Arc::new(w);
             wallet = Some(w);
         }
@@ -259,8 +366,8 @@ where
         }
 
         let mut mint = Mint::new(url.clone(), None);
-        let record = self.store.get_mint(mint_url.as_str()).await?;
         let store = if let Some(r) = record {
+            mint.pins = r.pins.clone();
             mint.active != r.active || r.info.is_none() || r.info.as_ref().unwrap() != &w.info
         } else {
             true
@@ -274,6 +381,143 @@ where
         Ok(has)
     }
 
+    /// the stored pins override the pins of http options
+    fn mint_client(&self, mint_url: &Url, pins: &[String]) -> Result<MintClient, Error<S::Error>> {
+        let mut options = self.http_options.as_ref().clone();
+        if !pins.is_empty() {
+            options = options.mint_pins(mint_url, pins.to_vec());
+        }
+        let client = MintClient::new(mint_url.clone(), options)?;
+        Ok(client)
+    }
+
+    /// pin the TLS certificates of the mint by the SPKI pins(see spki_pin) and reconnect it,
+    /// the empty pins unpin it
+    pub async fn set_mint_pins(
+        &self,
+        mint_url: &Url,
+        pins: Vec<String>,
+    ) -> Result<(), Error<S::Error>> {
+        let mut mint = self
+            .store
+            .get_mint(mint_url.as_str())
+            .await?
+            .ok_or_else(|| format_err!("mint not found: {}", mint_url))?;
+        mint.pins = pins;
+        self.store.add_mint(&mint).await?;
+
+        self.add_mint_with_units(mint_url.clone(), true, &[], None)
+            .await?;
+        Ok(())
+    }
+
+    async fn store_keysets(&self, wallet: &Wallet) -> Result<(), Error<S::Error>> {
+        let mint = wallet.client().url().as_str();
+        let time = unixtime_ms();
+
+        let keysets = wallet
+            .keysets()
+            .iter()
+            .map(|ks| MintKeySet {
+                mint: mint.to_owned(),
+                keyset: ks.clone(),
+                info: wallet
+                    .keyset_infos()
+                    .iter()
+                    .find(|i| i.id == ks.id)
+                    .cloned(),
+                time,
+            })
+            .collect::<Vec<_>>();
+        self.store.add_keysets(&keysets).await?;
+
+        Ok(())
+    }
+
+    /// NUT-02 refresh the keysets of the mint after the mint rotated keys, returns true if any keyset added
+    pub async fn refresh_keysets(&self, mint_url: &Url) -> Result<bool, Error<S::Error>> {
+        let w = self.get_wallet(mint_url)?;
+
+        let mut wallet = w.as_ref().clone();
+        let added = wallet.refresh_keysets().await?;
+        self.store_keysets(&wallet).await?;
+
+        self.wallets
+            .write()
+            .map_err(|e| format_err!("wallets write {}", e))?
+            .insert(mint_url.as_str().to_owned(), Arc::new(wallet));
+
+        Ok(added)
+    }
+
+    /// refresh the keysets of all mints periodically, returns the mints which keysets added
+    pub async fn refresh_keysets_all(&self) -> Result<Vec<String>, Error<S::Error>> {
+        let mut updates = vec![];
+        for url in self.mint_urls()? {
+            let mint_url: Url = url.parse()?;
+            match self.refresh_keysets(&mint_url).await {
+                Ok(true) => updates.push(url),
+                Ok(false) => {}
+                Err(e) => warn!("refresh keysets {} failed: {}", url, e),
+            }
+        }
+
+        Ok(updates)
+    }
+
+    /// swap the proofs of inactive keysets into the active keysets, returns the amount migrated
+    pub async fn migrate_inactive_proofs(&self, mint_url: &Url) -> Result<u64, Error<S::Error>> {
+        let wallet = self.get_wallet(mint_url)?;
+        self.migrate_proofs(mint_url, |id| !wallet.is_active_keyset(id))
+            .await
+    }
+
+    /// swap the proofs of legacy base64 keysets into the current keysets, returns the amount migrated
+    pub async fn migrate_legacy_proofs(&self, mint_url: &Url) -> Result<u64, Error<S::Error>> {
+        self.migrate_proofs(mint_url, |id| id.version == KeySetVersion::VersionBs)
+            .await
+    }
+
+    async fn migrate_proofs(
+        &self,
+        mint_url: &Url,
+        f: impl Fn(&KeySetId) -> bool,
+    ) -> Result<u64, Error<S::Error>> {
+        let wallet = self.get_wallet(mint_url)?;
+
+        let mut migrated = 0;
+        let mps = self.store.get_proofs(mint_url).await?;
+        for (unit, mut ps) in mps {
+            ps.retain(|p| f(&p.as_ref().keyset_id));
+            if ps.is_empty() {
+                continue;
+            }
+
+            let fee = wallet.input_fee(&ps).to_u64();
+            let amount = ps.sum().to_u64();
+            if amount <= fee {
+                continue;
+            }
+
+            let journal =
+                Journal::new(&self.store, OperationKind::Swap, mint_url, Some(&unit), &ps);
+            let res = wallet
+                .send((amount - fee).into(), &ps, Some(&unit), &journal)
+                .await;
+            let tokens = self.journaled(&journal, res).await?;
+
+            let mut batch = Batch::new();
+            batch
+                .add_proofs(mint_url, tokens.all())
+                .delete_proofs(mint_url, &ps)
+                .complete(&journal);
+            self.store.apply(&batch).await?;
+            migrated += amount - fee;
+        }
+
+        Ok(migrated)
+    }
+
     /// current mints in the UnitedWallet object
     pub async fn mints(&self) -> Result<Vec<Mint>, Error<S::Error>> {
         let mut mints = self.store.get_mints().await?;
@@ -281,18 +525,97 @@ where
         Ok(mints)
     }
 
-    /// load active mints from database::get_mints
+    /// load active mints from database::get_mints, the mints with stored keysets and info are loaded without network,
+    /// the others are connected and skipped if failed. call refresh_mints later to update them
     pub async fn load_mints_from_database(&self) -> Result<Vec<Mint>, Error<S::Error>> {
         let mints = self.mints().await?;
 
         for m in &mints {
             let mint_url = m.url.parse::<Url>()?;
-            self.add_mint_with_units(mint_url, false, &[], None).await?;
+
+            let mut wallet = None;
+            let keysets = self.store.get_keysets(mint_url.as_str()).await?;
+            if let (Some(info), false) = (&m.info, keysets.is_empty()) {
+                let client = self.mint_client(&mint_url, &m.pins)?;
+                let mn = self.mnemonic.clone();
+                let res =
+                    Wallet::from_stored(client, keysets, info.clone(), mn, self.store(), None)
+                        .await;
+                match res {
+                    Ok(mut w) => {
+                        w.set_dleq_policy(self.dleq_policy(&mint_url)?);
+                        wallet = Some(Arc::new(w));
+                    }
+                    Err(e) => warn!("load mint {} from database failed: {}", mint_url, e),
+                }
+            }
+
+            if let Err(e) = self
+                .add_mint_with_units(mint_url.clone(), false, &[], wallet)
+                .await
+            {
+                warn!("load mint {} failed: {}", mint_url, e);
+            }
         }
 
         Ok(mints)
     }
 
+    /// fetch the info and keysets of the mint, replace the wallet loaded from database
+    pub async fn refresh_mint(&self, mint_url: &Url) -> Result<bool, Error<S::Error>> {
+        let w = self.get_wallet(mint_url)?;
+
+        let mut wallet = w.as_ref().clone();
+        let info = wallet.client().get_info().await?;
+        let update_info = wallet.info() != &info;
+        wallet.set_info(info);
+
+        let added = wallet.refresh_keysets().await?;
+        self.store_keysets(&wallet).await?;
+
+        if update_info {
+            let mut mint = Mint::new(mint_url.as_str().to_owned(), Some(wallet.info().clone()));
+            if let Some(r) = self.store.get_mint(mint_url.as_str()).await? {
+                mint.active = r.active;
+                mint.pins = r.pins;
+            }
+            self.store.add_mint(&mint).await?;
+        }
+
+        self.wallets
+            .write()
+            .map_err(|e| format_err!("wallets write {}", e))?
+            .insert(mint_url.as_str().to_owned(), Arc::new(wallet));
+
+        Ok(added || update_info)
+    }
+
+    /// refresh all the mints, the unreachable mints keep the wallets loaded from database.
+    /// returns the mints updated
+    pub async fn refresh_mints(&self) -> Result<Vec<String>, Error<S::Error>> {
+        let mut updates = vec![];
+        for url in self.mint_urls()? {
+            let mint_url: Url = url.parse()?;
+            match self.refresh_mint(&mint_url).await {
+                Ok(true) => updates.push(url),
+                Ok(false) => {}
+                Err(e) => warn!("refresh mint {} failed: {}", url, e),
+            }
+        }
+
+        Ok(updates)
+    }
+
+    /// refresh_mints in background after load_mints_from_database
+    pub fn spawn_refresh_mints(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let this = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = this.refresh_mints().await {
+                warn!("refresh mints failed: {}", e);
+            }
+        })
+    }
+
     /// remove the mint from object and set it as unactive in database
     pub async fn remove_mint(&self, mint_url: &Url) -> Result<bool, Error<S::Error>> {
         let wi = self.store().get_mint(mint_url.as_str()).await?;
@@ -373,6 +696,51 @@ where
         Ok(map)
     }
 
+    /// the balance of the mint unit with the reserved and pending
+    pub async fn get_balance_with_state(
+        &self,
+        mint_url: &Url,
+        unit: Option<&str>,
+    ) -> Result<Balance, Error<S::Error>> {
+        let mu = MintUrlWithUnit::new(mint_url.as_str(), unit.unwrap_or(CURRENCY_UNIT_SAT));
+        let balance = self
+            .get_balances_with_state()
+            .await?
+            .remove(&mu)
+            .unwrap_or_default();
+        Ok(balance)
+    }
+
+    /// the balances of all mint units with the reserved and pending, the spent is ignored
+    pub async fn get_balances_with_state(
+        &self,
+    ) -> Result<BTreeMap<MintUrlWithUnitOwned, Balance>, Error<S::Error>> {
+        let mps = self
+            .store
+            .get_all_proofs_with_state(&[
+                ProofState::Unspent,
+                ProofState::Reserved,
+                ProofState::Pending,
+            ])
+            .await?;
+
+        let mut map: BTreeMap<_, Balance> = Default::default();
+        for (k, ps) in mps {
+            let b = map.entry(k).or_default();
+            for p in ps {
+                let amount = p.as_ref().amount.to_u64();
+                match p.state {
+                    ProofState::Unspent => b.available += amount,
+                    ProofState::Reserved => b.reserved += amount,
+                    ProofState::Pending => b.pending += amount,
+                    ProofState::Spent | ProofState::Unswapped => {}
+                }
+            }
+        }
+
+        Ok(map)
+    }
+
     pub async fn receive_tokens(&self, cashu_tokens: &str) -> Result<u64, Error<S::Error>> {
         let mut txs = vec![];
         self.receive_tokens_full(cashu_tokens, &mut txs).await?;
@@ -389,12 +757,38 @@ where
             .await
     }
 
+    /// receive the NUT-10 locked tokens, unlock it with the witness materials(P2PK signing keys, etc)
+    pub async fn receive_tokens_unlocking(
+        &self,
+        cashu_tokens: &str,
+        unlocking: &Unlocking,
+    ) -> Result<u64, Error<S::Error>> {
+        let cashu_tokens: Token = cashu_tokens.parse()?;
+
+        let mut txs = vec![];
+        self.receive_tokens_full_limit_unit_unlocking(&cashu_tokens, &mut txs, &[], unlocking)
+            .await?;
+        Ok(txs.iter().map(|tx| tx.amount()).sum())
+    }
+
     #[doc(hidden)]
     pub async fn receive_tokens_full_limit_unit(
         &self,
         tokens: &Token,
         txs: &mut Vec<Transaction>,
         units: &[&str],
+    ) -> Result<(), Error<S::Error>> {
+        self.receive_tokens_full_limit_unit_unlocking(tokens, txs, units, &Default::default())
+            .await
+    }
+
+    #[doc(hidden)]
+    pub async fn receive_tokens_full_limit_unit_unlocking(
+        &self,
+        tokens: &Token,
+        txs: &mut Vec<Transaction>,
+        units: &[&str],
+        unlocking: &Unlocking,
     ) -> Result<(), Error<S::Error>> {
         let is_v4 = tokens.is_token_v_4();
         let tokens = tokens.clone().into_v3()?;
@@ -412,9 +806,55 @@ where
 
             let wallet = self.get_wallet(mint_url)?;
 
-            let ps = wallet.receive_token(token, unit, &self.store).await?;
-            let ps = ps.into_extended_with_unit(unit);
-            self.store.add_proofs(&mint_url, &ps).await?;
+            let inputs = token.proofs.to_extended_with_unit(unit);
+            let journal =
+                Journal::new(&self.store, OperationKind::Receive, mint_url, unit, &inputs);
+            let res = wallet
+                .receive_token_unlocking(token, unit, unlocking, &journal)
+                .await;
+
+            // the request never reached the mint, accept the DLEQ verified tokens and swap them by check_pendings later
+            let mut batch = Batch::new();
+            let status = match res {
+                Ok(ps) => {
+                    let ps = ps.into_extended_with_unit(unit);
+                    // the token sent by self is reclaimed
+                    batch
+                        .update_proofs_state(mint_url, &inputs, ProofState::Spent, None)
+                        .add_proofs(mint_url, &ps);
+                    TransactionStatus::Success
+                }
+                Err(e) if e.is_unreachable() && token.proofs.locked().is_empty() => {
+                    if let Err(d) = wallet.verify_proofs_dleq_offline(&token.proofs) {
+                        warn!(
+                            "{} unreachable, can't receive offline: {}",
+                            mint_url.as_str(),
+                            d
+                        );
+                        return self.journaled(&journal, Err(e)).await;
+                    }
+
+                    // the same token is accepted once, it's also in the wallet if sent by self
+                    let stored = self.stored_secrets(mint_url, &ProofState::ALL).await?;
+                    if inputs.iter().any(|p| stored.contains(&p.raw.secret)) {
+                        self.complete(&journal).await?;
+                        return Err(format_err!(
+                            "{} unreachable, the token is in the wallet already",
+                            mint_url.as_str()
+                        )
+                        .into());
+                    }
+
+                    warn!("{} unreachable, receive offline: {}", mint_url.as_str(), e);
+                    let mut unswapped = inputs.clone();
+                    unswapped
+                        .iter_mut()
+                        .for_each(|p| p.state = ProofState::Unswapped);
+                    batch.add_proofs(mint_url, &unswapped);
+                    TransactionStatus::Pending
+                }
+                Err(e) => return self.journaled(&journal, Err(e)).await,
+            };
 
             let token_str = Wallet::proofs_to_token(
                 &token.proofs,
@@ -424,17 +864,21 @@ where
                 is_v4,
             )?;
 
-            let tx = CashuTransaction::new(
-                TransactionStatus::Success,
+            // the amount received without the fee, the same as the amount of send
+            let fee = wallet.input_fee(&token.proofs).to_u64();
+            let mut tx = CashuTransaction::new(
+                status,
                 TransactionDirection::In,
-                a,
+                a.saturating_sub(fee),
                 mint_url.as_str(),
                 &token_str,
                 None,
                 unit,
-            )
-            .into();
-            self.store.add_transaction(&tx).await?;
+            );
+            tx.fee = Some(fee);
+            let tx = tx.into();
+            batch.add_transaction(&tx).complete(&journal);
+            self.store.apply(&batch).await?;
 
             txs.push(tx);
         }
@@ -462,33 +906,64 @@ where
         info: Option<String>,
         allow_skip_split: bool,
     ) -> Result<Transaction, Error<S::Error>> {
-        let mut wallet = self.get_wallet_optional(mint_url)?;
+        self.send_tokens_inner(mint_url, amount, memo, unit, info, allow_skip_split, None)
+            .await
+    }
+
+    /// send the tokens locked by the NUT-10 spending conditions(NUT-11 P2PK, etc)
+    pub async fn send_tokens_with_conditions(
+        &self,
+        mint_url: &Url,
+        amount: u64,
+        conditions: &SpendingConditions,
+        memo: Option<String>,
+        unit: Option<&str>,
+        info: Option<String>,
+    ) -> Result<Transaction, Error<S::Error>> {
+        self.send_tokens_inner(mint_url, amount, memo, unit, info, false, Some(conditions))
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn send_tokens_inner(
+        &self,
+        mint_url: &Url,
+        amount: u64,
+        memo: Option<String>,
+        unit: Option<&str>,
+        info: Option<String>,
+        allow_skip_split: bool,
+        conditions: Option<&SpendingConditions>,
+    ) -> Result<Transaction, Error<S::Error>> {
+        // the fees of keysets select the proofs
+        let wallet = self.get_wallet(mint_url)?;
         let unit = unit.unwrap_or(CURRENCY_UNIT_SAT);
 
         let mut ps = self.store.get_proofs_limit_unit(mint_url, unit).await?;
-        let select = select_send_proofs(amount, &mut ps)?;
+        let select = select_send_proofs(amount, &mut ps, |p| wallet.input_fee_ppk(&p.keyset_id))?;
         let pss = &ps[..=select];
 
-        let tokens = if pss.sum().to_u64() == amount && allow_skip_split {
+        let journal = Journal::new(&self.store, OperationKind::Swap, mint_url, Some(unit), pss);
+        let mut fee = None;
+        let tokens = if pss.sum().to_u64() == amount && allow_skip_split && conditions.is_none() {
             SplitProofsExtended::new(pss.to_owned(), 0)
         } else {
-            if wallet.is_none() {
-                wallet = Some(self.get_wallet(mint_url)?);
-            }
-            wallet
-                .as_ref()
-                .unwrap()
-                .send(amount.into(), pss, Some(unit), &self.store)
-                .await?
+            fee = Some(wallet.input_fee(pss).to_u64());
+            let res = match conditions {
+                Some(sc) => {
+                    wallet
+                        .send_with_conditions(amount.into(), pss, sc, Some(unit), &journal)
+                        .await
+                }
+                None => wallet.send(amount.into(), pss, Some(unit), &journal).await,
+            };
+            self.journaled(&journal, res).await?
         };
 
-        self.store.add_proofs(mint_url, tokens.keep()).await?;
-        self.store.delete_proofs(mint_url, pss).await?;
-
         let cashu_tokens =
             Wallet::proofs_to_token(tokens.send(), mint_url.clone(), memo, Some(unit), true)?;
 
-        let mut tx: Transaction = CashuTransaction::new(
+        let mut tx = CashuTransaction::new(
             TransactionStatus::Pending,
             TransactionDirection::Out,
             amount,
@@ -496,15 +971,116 @@ where
             &cashu_tokens,
             None,
             Some(unit),
-        )
-        .into();
+        );
+        tx.fee = fee;
+        let mut tx: Transaction = tx.into();
         *tx.info_mut() = info;
 
-        self.store.add_transaction(&tx).await?;
+        // the keep proofs and the token sent are stored with the inputs spent at once,
+        // the proofs sent are reserved by the transaction until claimed
+        let sent = tokens
+            .send()
+            .iter()
+            .cloned()
+            .map(|p| p.reserve(tx.id()))
+            .collect::<Vec<_>>();
+        let mut batch = Batch::new();
+        batch
+            .add_proofs(mint_url, tokens.keep())
+            .delete_proofs(mint_url, pss)
+            .add_proofs(mint_url, &sent)
+            .add_transaction(&tx)
+            .complete(&journal);
+        self.store.apply(&batch).await?;
 
         Ok(tx)
     }
 
+    /// NUT-18 pay the payment request by a mint accepted with enough balance,
+    /// the payload is returned for the caller to deliver over `request.transports`, or the token of transaction is used in-band if no transports.
+    /// the request is kept as the info of transaction, the single use request paid is rejected unless the payment failed
+    pub async fn pay_request(
+        &self,
+        request: &PaymentRequest,
+        amount: Option<u64>,
+    ) -> Result<(PaymentRequestPayload, Transaction), Error<S::Error>> {
+        let amount = match (request.amount, amount) {
+            (Some(a), Some(b)) if a != b => {
+                return Err(format_err!("amount unmatch {}/{}", b, a).into())
+            }
+            (Some(a), _) | (None, Some(a)) => a,
+            (None, None) => return Err(format_err!("payment request has no amount").into()),
+        };
+        let unit = request
+            .unit
+            .as_ref()
+            .map(|u| u.to_string())
+            .unwrap_or_else(|| CURRENCY_UNIT_SAT.to_owned());
+
+        let info = request.to_string();
+        if request.single_use == Some(true) {
+            let txs = self
+                .store
+                .get_transactions(&[TransactionStatus::Pending, TransactionStatus::Success])
+                .await?;
+            if txs
+                .iter()
+                .any(|tx| !tx.direction().is_in() && tx.info() == Some(info.as_str()))
+            {
+                return Err(format_err!("the single use payment request is paid already").into());
+            }
+        }
+
+        // the proofs selected as send_tokens with their fees
+        let mut mint_url = None;
+        for url in self.mint_urls()? {
+            let url: Url = url.parse()?;
+            if !request.accepts_mint(&url) {
+                continue;
+            }
+
+            let wallet = self.get_wallet(&url)?;
+            let mut ps = self.store.get_proofs_limit_unit(&url, &unit).await?;
+            let select = match select_send_proofs::<S::Error>(amount, &mut ps, |p| {
+                wallet.input_fee_ppk(&p.keyset_id)
+            }) {
+                Ok(select) => select,
+                Err(_) => continue,
+            };
+
+            let selected = &ps[..=select];
+            let fee = wallet.input_fee(selected).to_u64();
+            if selected.sum().to_u64() >= amount + fee {
+                mint_url = Some(url);
+                break;
+            }
+        }
+        let mint_url = mint_url.ok_or(WalletError::InsufficientFunds)?;
+
+        let tx = self
+            .send_tokens_full(
+                &mint_url,
+                amount,
+                request.description.clone(),
+                Some(&unit),
+                Some(info),
+                false,
+            )
+            .await?;
+
+        let token: Token = tx.content().parse()?;
+        let token = token.into_v3()?;
+        let payload = PaymentRequestPayload {
+            id: request.payment_id.clone(),
+            memo: request.description.clone(),
+            mint: mint_url,
+            unit: unit.parse()?,
+            proofs: token.token.into_iter().flat_map(|t| t.proofs).collect(),
+        };
+
+        Ok((payload, tx))
+    }
+
     pub async fn prepare_one_proofs(
         &self,
         mint_url: &Url,
@@ -547,21 +1123,34 @@ where
         if count_before * denomination < amount {
             let amount = amount - count_before * denomination;
 
-            let select = select_send_proofs(amount, &mut ps)?;
+            let select =
+                select_send_proofs(amount, &mut ps, |p| wallet.input_fee_ppk(&p.keyset_id))?;
             let pss = &ps[..=select];
 
-            let tokens = wallet
+            let journal = Journal::new(
+                &self.store,
+                OperationKind::Swap,
+                mint_url,
+                currency_unit,
+                pss,
+            );
+            let res = wallet
                 .send_with_denomination(
                     amount.into(),
                     pss,
                     denomination.into(),
                     currency_unit,
-                    &self.store,
+                    &journal,
                 )
-                .await?;
+                .await;
+            let tokens = self.journaled(&journal, res).await?;
 
-            self.store.add_proofs(mint_url, tokens.all()).await?;
-            self.store.delete_proofs(mint_url, pss).await?;
+            let mut batch = Batch::new();
+            batch
+                .add_proofs(mint_url, tokens.all())
+                .delete_proofs(mint_url, pss)
+                .complete(&journal);
+            self.store.apply(&batch).await?;
 
             for i in tokens.all() {
                 if i.as_ref().amount.to_u64() == denomination {
@@ -588,8 +1177,11 @@ where
 
         let mut cushs: BTreeMap<String, Vec<Transaction>> = BTreeMap::new();
         let mut lns: BTreeMap<String, Vec<Transaction>> = BTreeMap::new();
+        let mut unswappeds = vec![];
         for tx in pendings {
-            if tx.is_cashu() {
+            if tx.is_cashu() && tx.direction().is_in() {
+                unswappeds.push(tx)
+            } else if tx.is_cashu() {
                 let txs = cushs.entry(tx.mint_url().to_owned()).or_default();
 
                 txs.push(tx)
@@ -662,7 +1254,7 @@ where
                             *tx.status_mut() = TransactionStatus::Success;
                             // println!("{:?}", tx);
 
-                            self.store.add_transaction(&*tx).await?;
+                            self.settle_sent(tx).await?;
                             update_count += 1;
                         }
 
@@ -675,23 +1267,362 @@ where
             }
         }
 
+        for tx in unswappeds.iter_mut() {
+            let mint_url = tx.mint_url().parse()?;
+            if !self.contains(&mint_url)? {
+                continue;
+            }
+
+            let res = self.swap_unswapped(tx).await;
+            match res {
+                Ok(_) => update_count += 1,
+                Err(e) => warn!("swap offline received {} failed: {}", tx.id(), e),
+            }
+        }
+
+        let melts = if lns.is_empty() {
+            vec![]
+        } else {
+            self.store.get_pending_melts().await?
+        };
         for (k, txs) in lns.iter_mut() {
             let mint_url = k.parse()?;
             // let _wallet = self.get_wallet(&mint_url)?;
 
             for tx in txs {
-                let res = self
-                    .mint_tokens(&mint_url, tx.amount(), tx.id().to_owned(), tx.unit())
-                    .await;
-                if res.is_ok() {
-                    update_count += 1;
+                if !tx.direction().is_in() {
+                    let melt = melts.iter().find(|m| m.quote == tx.id() && m.mint == *k);
+                    if let Some(m) = melt {
+                        match self.resume_pending_melt(m).await {
+                            Ok(true) => update_count += 1,
+                            Ok(false) => {}
+                            Err(e) => warn!("resume melt {} failed: {}", tx.id(), e),
+                        }
+                    }
+                    continue;
+                }
+
+                let res = self.check_mint_quote(&mint_url, tx).await;
+                match res {
+                    Ok(true) => update_count += 1,
+                    Ok(false) => {}
+                    Err(e) => warn!("check mint quote {} failed: {}", tx.id(), e),
+                }
+            }
+        }
+
+        Ok((update_count, pendings_count))
+    }
+
+    async fn get_pending_transactions_of(
+        &self,
+        mint_url: &Url,
+    ) -> Result<Vec<Transaction>, Error<S::Error>> {
+        let mut pendings = self.store.get_pending_transactions().await?;
+        pendings.retain(|tx| tx.mint_url() == mint_url.as_str());
+        Ok(pendings)
+    }
+
+    /// NUT-17 watch the pending transactions of the mint until all of them settled or timeout:
+    /// mint the paid quotes, finalize the melts and mark the claimed sent tokens as success.
+    /// fallback to poll by check_pendings_with every interval if the mint not supports WebSocket
+    pub async fn watch_pendings(
+        &self,
+        mint_url: &Url,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<(usize, usize), Error<S::Error>> {
+        let deadline = Instant::now() + timeout;
+
+        let pendings = self.get_pending_transactions_of(mint_url).await?;
+        let pendings_count = pendings.len();
+        let mut update_count = 0;
+
+        let wallet = self.get_wallet(mint_url)?;
+        let nut17 = &wallet.info.nuts.nut17;
+
+        let mut mint_quotes = BTreeMap::new();
+        let mut melt_quotes = BTreeMap::new();
+        let mut cashus = BTreeMap::new();
+        // y -> txid
+        let mut ys = BTreeMap::new();
+        // txid -> the ys not spent yet, the sent token is claimed after all of them spent
+        let mut unspent_ys = BTreeMap::new();
+        let mut polls = vec![];
+        for tx in pendings {
+            let unit = tx.unit().unwrap_or(CURRENCY_UNIT_SAT);
+            let supports =
+                |kind: SubscriptionKind| nut17.supports(PAYMEN_METHOD_BOLT11, unit, kind.as_str());
+
+            if tx.is_ln() && tx.direction().is_in() && supports(SubscriptionKind::Bolt11MintQuote) {
+                mint_quotes.insert(tx.id().to_owned(), tx);
+            } else if tx.is_ln() && supports(SubscriptionKind::Bolt11MeltQuote) {
+                melt_quotes.insert(tx.id().to_owned(), tx);
+            } else if tx.is_cashu()
+                && !tx.direction().is_in()
+                && supports(SubscriptionKind::ProofState)
+            {
+                let token: Token = tx.content().parse()?;
+                let token = token.into_v3()?;
+                let mut txys = BTreeSet::new();
+                for t in &token.token {
+                    for y in proofs_ys(&t.proofs)? {
+                        ys.insert(y.to_string(), tx.id().to_owned());
+                        txys.insert(y.to_string());
+                    }
                 }
+                unspent_ys.insert(tx.id().to_owned(), txys);
+                cashus.insert(tx.id().to_owned(), tx);
+            } else {
+                polls.push(tx);
+            }
+        }
+
+        if !polls.is_empty() {
+            let (c, _) = self.check_pendings_with(polls).await?;
+            update_count += c;
+        }
+
+        let mut subscriptions = vec![];
+        if !mint_quotes.is_empty() {
+            let filters = mint_quotes.keys().cloned().collect();
+            subscriptions.push((SubscriptionKind::Bolt11MintQuote, filters));
+        }
+        if !melt_quotes.is_empty() {
+            let filters = melt_quotes.keys().cloned().collect();
+            subscriptions.push((SubscriptionKind::Bolt11MeltQuote, filters));
+        }
+        if !ys.is_empty() {
+            let filters = ys.keys().cloned().collect();
+            subscriptions.push((SubscriptionKind::ProofState, filters));
+        }
+
+        let mut subscription = None;
+        if !subscriptions.is_empty() {
+            let subscribe = wallet.client().subscribe(&subscriptions);
+            match tokio::time::timeout_at(deadline, subscribe).await {
+                Ok(Ok(sub)) => subscription = Some(sub),
+                Ok(Err(e)) => warn!("subscribe {} failed, fallback to polling: {}", mint_url, e),
+                Err(_) => warn!("subscribe {} timeout, fallback to polling", mint_url),
+            }
+        }
+
+        while let Some(sub) = subscription.as_mut() {
+            if mint_quotes.is_empty() && melt_quotes.is_empty() && cashus.is_empty() {
+                break;
+            }
+
+            let n = match tokio::time::timeout_at(deadline, sub.recv()).await {
+                Ok(Some(n)) => n,
+                // closed or timeout
+                _ => break,
+            };
+
+            match n.kind {
+                SubscriptionKind::Bolt11MintQuote => {
+                    let quote: nut04::MintQuoteBolt11Response = match n.payload() {
+                        Ok(t) => t,
+                        Err(e) => {
+                            warn!("invalid {:?} notification of {}: {}", n.kind, mint_url, e);
+                            continue;
+                        }
+                    };
+                    if quote.state == nut04::QuoteState::Unpaid {
+                        continue;
+                    }
+
+                    if let Some(tx) = mint_quotes.get_mut(&quote.quote) {
+                        match self.check_mint_quote(mint_url, tx).await {
+                            Ok(true) => {
+                                update_count += 1;
+                                mint_quotes.remove(&quote.quote);
+                            }
+                            Ok(false) => {}
+                            Err(e) => warn!("check mint quote {} failed: {}", quote.quote, e),
+                        }
+                    }
+                }
+                SubscriptionKind::Bolt11MeltQuote => {
+                    let resp: MeltResponse = match n.payload() {
+                        Ok(t) => t,
+                        Err(e) => {
+                            warn!("invalid {:?} notification of {}: {}", n.kind, mint_url, e);
+                            continue;
+                        }
+                    };
+                    if resp.state() == nut05::QuoteState::Pending {
+                        continue;
+                    }
+
+                    let quote = resp.quote.unwrap_or_default();
+                    if !melt_quotes.contains_key(&quote) {
+                        continue;
+                    }
+
+                    let melts = self.store.get_pending_melts().await?;
+                    let melt = melts
+                        .iter()
+                        .find(|m| m.quote == quote && m.mint == mint_url.as_str());
+                    if let Some(m) = melt {
+                        match self.resume_pending_melt(m).await {
+                            Ok(true) => {
+                                update_count += 1;
+                                melt_quotes.remove(&quote);
+                            }
+                            Ok(false) => {}
+                            Err(e) => warn!("resume melt {} failed: {}", quote, e),
+                        }
+                    }
+                }
+                SubscriptionKind::ProofState => {
+                    let state: nut07::ProofState = match n.payload() {
+                        Ok(t) => t,
+                        Err(e) => {
+                            warn!("invalid {:?} notification of {}: {}", n.kind, mint_url, e);
+                            continue;
+                        }
+                    };
+                    if state.state != State::Spent {
+                        continue;
+                    }
+
+                    let y = state.y.to_string();
+                    let txid = match ys.get(&y) {
+                        Some(txid) => txid,
+                        None => continue,
+                    };
+                    let claimed = unspent_ys.get_mut(txid).map(|txys| {
+                        txys.remove(&y);
+                        txys.is_empty()
+                    });
+                    if claimed != Some(true) {
+                        continue;
+                    }
+
+                    if let Some(mut tx) = cashus.remove(txid) {
+                        *tx.status_mut() = TransactionStatus::Success;
+                        self.settle_sent(&tx).await?;
+                        update_count += 1;
+                    }
+                }
+            }
+        }
+        drop(subscription);
+
+        // the mint not supports or the connection closed
+        let mut remains = mint_quotes
+            .into_values()
+            .chain(melt_quotes.into_values())
+            .chain(cashus.into_values())
+            .collect::<Vec<_>>();
+        while !remains.is_empty() && Instant::now() < deadline {
+            let (c, _) = self.check_pendings_with(remains).await?;
+            update_count += c;
+
+            remains = self.get_pending_transactions_of(mint_url).await?;
+            if !remains.is_empty() {
+                tokio::time::sleep_until(deadline.min(Instant::now() + interval)).await;
             }
         }
 
         Ok((update_count, pendings_count))
     }
 
+    /// the token sent is claimed: store the transaction with the proofs reserved by it spent
+    async fn settle_sent(&self, tx: &Transaction) -> Result<(), Error<S::Error>> {
+        let token: Token = tx.content().parse()?;
+        let token = token.into_v3()?;
+        let unit = token.unit.as_ref().map(|s| s.as_str());
+
+        let mut batch = Batch::new();
+        for t in &token.token {
+            let ps = t.proofs.to_extended_with_unit(unit);
+            batch.update_proofs_state(&t.mint, &ps, ProofState::Spent, None);
+        }
+        batch.add_transaction(tx);
+        self.store.apply(&batch).await?;
+
+        Ok(())
+    }
+
+    /// swap the tokens received offline, the unreachable mint keeps it pending,
+    /// and the tokens rejected by mint(spent, etc) are dropped, the transaction fails if all of them rejected
+    async fn swap_unswapped(&self, tx: &mut Transaction) -> Result<(), Error<S::Error>> {
+        let tokens: Token = tx.content().parse()?;
+        let tokens = tokens.into_v3()?;
+        let unit = tokens.unit.as_ref().map(|s| s.as_str());
+
+        let mut swapped = 0;
+        for (idx, token) in tokens.token.iter().enumerate() {
+            let wallet = self.get_wallet(&token.mint)?;
+
+            let inputs = token.proofs.to_extended_with_unit(unit);
+            let mut batch = Batch::new();
+
+            // swapped by the last check interrupted by the other mints
+            let spent = self
+                .stored_secrets(&token.mint, &[ProofState::Spent])
+                .await?;
+            if inputs.iter().all(|p| spent.contains(&p.raw.secret)) {
+                swapped += 1;
+            } else {
+                let journal = Journal::new(
+                    &self.store,
+                    OperationKind::Receive,
+                    &token.mint,
+                    unit,
+                    &inputs,
+                );
+                let res = wallet.receive_token(token, unit, &journal).await;
+                match res {
+                    Ok(ps) => {
+                        let ps = ps.into_extended_with_unit(unit);
+                        batch
+                            .update_proofs_state(&token.mint, &inputs, ProofState::Spent, None)
+                            .add_proofs(&token.mint, &ps);
+                        swapped += 1;
+                    }
+                    Err(e @ WalletError::Client(ClientError::Mint(..))) => {
+                        warn!("{} rejected {}: {}", token.mint.as_str(), tx.id(), e);
+                        batch.delete_proofs(&token.mint, &inputs);
+                    }
+                    Err(e) => return self.journaled(&journal, Err(e)).await,
+                }
+                batch.complete(&journal);
+            }
+
+            // the status is stored with the proofs of the last token
+            if idx + 1 == tokens.token.len() {
+                *tx.status_mut() = if swapped > 0 {
+                    TransactionStatus::Success
+                } else {
+                    TransactionStatus::Failed
+                };
+                batch.add_transaction(&*tx);
+            }
+            self.store.apply(&batch).await?;
+        }
+
+        Ok(())
+    }
+
+    /// the balances of tokens received offline and waiting to swap
+    pub async fn get_unswapped_balances(
+        &self,
+    ) -> Result<BTreeMap<MintUrlWithUnitOwned, u64>, Error<S::Error>> {
+        let mps = self
+            .store
+            .get_all_proofs_with_state(&[ProofState::Unswapped])
+            .await?;
+
+        let map = mps
+            .into_iter()
+            .map(|(k, ps)| (k, ps.iter().map(|p| p.as_ref().amount.to_u64()).sum()))
+            .collect();
+
+        Ok(map)
+    }
+
     pub async fn check_proofs_in_database(&self) -> Result<(usize, usize), Error<S::Error>> {
         let ps = self.store.get_all_proofs().await?;
 
@@ -718,14 +1649,15 @@ where
                     .into());
                 }
 
-                for (idx, b) in state.states.into_iter().enumerate() {
-                    let is_spent = b.state == State::Spent;
-                    if is_spent {
-                        let tx = &ps[idx..=idx];
-
-                        self.store.delete_proofs(&mint_url, tx).await?;
-                        update_count += 1;
-                    }
+                let spent = ps
+                    .iter()
+                    .zip(state.states)
+                    .filter(|(_, s)| s.state == State::Spent)
+                    .map(|(p, _)| p.clone())
+                    .collect::<Vec<_>>();
+                if !spent.is_empty() {
+                    self.store.delete_proofs(&mint_url, &spent).await?;
+                    update_count += spent.len();
                 }
             }
         }
@@ -762,6 +1694,49 @@ where
         Ok(tx)
     }
 
+    /// NUT-04 check the quote state of the pending mint transaction, mint tokens only if it's paid,
+    /// returns true if the transaction updated
+    pub async fn check_mint_quote(
+        &self,
+        mint_url: &Url,
+        tx: &mut Transaction,
+    ) -> Result<bool, Error<S::Error>> {
+        let wallet = self.get_wallet(mint_url)?;
+        let quote = wallet.mint_quote_state(tx.id(), None).await?;
+
+        match quote.state {
+            nut04::QuoteState::Paid => {
+                self.mint_tokens(mint_url, tx.amount(), tx.id().to_owned(), tx.unit())
+                    .await?;
+            }
+            nut04::QuoteState::Issued => {
+                *tx.status_mut() = TransactionStatus::Success;
+                self.store.add_transaction(tx).await?;
+            }
+            nut04::QuoteState::Unpaid => {
+                let now = unixtime_ms() / 1000;
+                let expired = match quote.expiry {
+                    Some(expiry) if expiry > 0 => expiry <= now,
+                    _ => tx
+                        .content()
+                        .parse::<Bolt11Invoice>()
+                        .map(|i| i.is_expired())
+                        .unwrap_or_default(),
+                };
+                if !expired {
+                    return Ok(false);
+                }
+
+                *tx.status_mut() = TransactionStatus::Expired;
+                self.store.add_transaction(tx).await?;
+            }
+            #[allow(unreachable_patterns)]
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
     pub async fn mint_tokens(
         &self,
         mint_url: &Url,
@@ -772,27 +1747,26 @@ where
         let wallet = self.get_wallet(mint_url)?;
 
         let mut tx = self.store.get_transaction(&hash).await?;
+        let unit = tx.as_ref().and_then(|tx| tx.unit()).or(_unit);
 
+        let journal = Journal::new(&self.store, OperationKind::Mint, mint_url, unit, &[]);
         let tokens = match wallet
-            .mint_token(
-                amount.into(),
-                tx.as_ref().and_then(|tx| tx.unit()).or(_unit),
-                &hash,
-                None,
-                &self.store,
-            )
+            .mint_token(amount.into(), unit, &hash, None, &journal)
             .await
         {
             Ok(t) => t,
             Err(e) => {
-                // https://github.com/cashubtc/nutshell/blob/0.10.0/cashu/core/errors.py#L21 "invoice not paid."
-                // https://github.com/cashubtc/nutshell/blob/0.12.3/cashu/core/errors.py#L19 "invoice not paid."
-                // https://github.com/cashubtc/nutshell/blob/0.13.0/cashu/core/errors.py#L78 "Lightning invoice not paid yet."
-                // https://github.com/cashubtc/cashu-feni/blob/master/mint/mint.go#L251C27-L251C60 "Lightning invoice not paid yet."
-                let unpaid = match &e {
-                    WalletError::Client(ClientError::Mint(_ec, ed)) => ed.contains("not paid"),
-                    _ => false,
-                };
+                if !e.is_network_error() && !e.is_dleq() {
+                    self.complete(&journal).await?;
+                } else {
+                    self.keep(&journal).await?;
+                }
+
+                // 20001, or the detail "invoice not paid." of the old mints
+                let unpaid = e
+                    .mint_error_code()
+                    .map(|c| c.is_quote_not_paid())
+                    .unwrap_or_default();
 
                 if unpaid {
                     if let Some(tx) = &mut tx {
@@ -812,17 +1786,20 @@ where
             }
         };
 
+        let mut batch = Batch::new();
         for t in &tokens.token {
-            self.store.add_proofs(mint_url, &t.proofs).await?;
+            batch.add_proofs(mint_url, &t.proofs);
         }
+        batch.complete(&journal);
 
         if let Some(ref mut tx) = tx {
             if !tx.is_ln() {
+                self.store.apply(&batch).await?;
                 return Err(format_err!("the transaction is cashu").into());
             }
 
             *tx.status_mut() = TransactionStatus::Success;
-            self.store.add_transaction(tx).await?;
+            batch.add_transaction(tx);
         } else {
             // save with empty pr
             let txfake = LNTransaction::new(
@@ -838,9 +1815,10 @@ where
             )
             .into();
 
-            self.store.add_transaction(&txfake).await?;
+            batch.add_transaction(&txfake);
             tx = Some(txfake);
         }
+        self.store.apply(&batch).await?;
 
         Ok(tx.unwrap())
     }
@@ -862,7 +1840,10 @@ where
         }
 
         let unit = unit.unwrap_or(CURRENCY_UNIT_SAT);
+        let wallet = self.get_wallet(mint_url)?;
+
         // https://github.com/lightning/bolts/blob/master/11-payment-encoding.md#rationale
+        let mut amount_msat = None;
         let amount = if let Some(amount_msats) = invoice.amount_milli_satoshis() {
             // ceil
             let amount_in_invoice = amount_msats / 1000 + (amount_msats % 1000 > 0) as u64;
@@ -873,28 +1854,73 @@ where
             }
             amount_in_invoice
         } else {
-            // https://8333.space:3338 no support
+            // NUT-05 amountless, the caller specify the amount
             // melt 400: {"detail":"invoice has no amount.","code":0}
-            // if amount.is_none() {
-            return Err(format_err!("invoice has no amount.").into());
-            // }
-            // amount.unwrap()
+            let a = amount
+                .filter(|a| *a > 0)
+                .ok_or_else(|| format_err!("invoice has no amount."))?;
+            // the amount is sat, the msat option can't express the others
+            if unit != CURRENCY_UNIT_SAT {
+                return Err(format_err!("amountless invoice not supported in {}", unit).into());
+            }
+            if !wallet
+                .info
+                .nuts
+                .nut05
+                .supports_amountless(PAYMEN_METHOD_BOLT11, unit)
+            {
+                return Err(format_err!("mint not supports amountless invoice").into());
+            }
+            amount_msat = Some(a * 1000);
+            a
         };
 
-        let wallet = self.get_wallet(mint_url)?;
-        let form = wallet.request_melt(&invoice, Some(unit), None).await?;
-        let mut fee = form.fee_reserve;
+        let form = wallet
+            .request_melt(&invoice, Some(unit), None, amount_msat)
+            .await?;
+        // the mint maybe ignore the amountless option or quote another amount,
+        // the quote of the other units is converted by mint and spent as it
+        let amount = if unit == CURRENCY_UNIT_SAT {
+            if form.amount.to_u64() != amount {
+                return Err(format_err!(
+                    "melt quote amount unmatch {}/{}",
+                    form.amount.to_u64(),
+                    amount
+                )
+                .into());
+            }
+            amount
+        } else {
+            form.amount.to_u64()
+        };
+        let fee = form.fee_reserve;
         if let Some(q) = quote_response {
             *q = form.clone();
         }
 
         let amount_with_fee = amount + fee.as_ref();
 
+        // the melt inputs pay NUT-02 input fee also
+        let mut amount_to_melt = amount_with_fee;
+        for _ in 0..3 {
+            let input_fee = wallet
+                .estimate_input_fee(amount_to_melt.into(), Some(unit))
+                .await?
+                .to_u64();
+            if amount_with_fee + input_fee == amount_to_melt {
+                break;
+            }
+            amount_to_melt = amount_with_fee + input_fee;
+        }
+
         let mut ps = self.store.get_proofs_limit_unit(mint_url, unit).await?;
-        let select = select_send_proofs(amount_with_fee, &mut ps)?;
+        let select = select_send_proofs(amount_to_melt, &mut ps, |p| {
+            wallet.input_fee_ppk(&p.keyset_id)
+        })?;
         let ps = &ps[..=select];
 
         let amount_selected = ps.sum();
+        let mut input_fees = 0;
 
         // #[rustfmt::skip]
         // println!("{}+{}=>{}/{}", amount, fee, amount_with_fee, amount_selected.to_u64());
@@ -902,63 +1928,435 @@ where
         // or depents on nut08?
         // let fee_and_remains = ps.sum() - cashu::Amount::from_sat(amount);
         // or spit fisrt
-        let ps2 = if amount_selected.to_u64() > amount_with_fee {
-            let psnew = wallet
-                .send(amount_with_fee.into(), ps, Some(unit), &self.store)
-                .await?;
-            self.store.add_proofs(mint_url, &psnew.proofs).await?;
-            self.store.delete_proofs(mint_url, ps).await?;
+        let ps2 = if amount_selected.to_u64() > amount_to_melt {
+            input_fees += wallet.input_fee(ps).to_u64();
+            let journal = Journal::new(&self.store, OperationKind::Swap, mint_url, Some(unit), ps);
+            let res = wallet
+                .send(amount_to_melt.into(), ps, Some(unit), &journal)
+                .await;
+            let psnew = self.journaled(&journal, res).await?;
+
+            let mut batch = Batch::new();
+            batch
+                .add_proofs(mint_url, &psnew.proofs)
+                .delete_proofs(mint_url, ps)
+                .complete(&journal);
+            self.store.apply(&batch).await?;
             psnew
         } else {
             SplitProofsGeneric::new(ps.to_owned(), 0)
         };
 
-        let pm = wallet
-            .melt(
-                &form.quote,
+        input_fees += wallet.input_fee(ps2.send()).to_u64();
+
+        // persist the reserved inputs and blank outputs before melt, the in-flight payment could be resumed
+        let outputs = wallet
+            .melt_blank_outputs(fee, Some(unit), &self.store)
+            .await?;
+        let pending = PendingMelt {
+            quote: form.quote.clone(),
+            mint: mint_url.as_str().to_owned(),
+            unit: Some(unit.to_owned()),
+            amount,
+            fee_reserve: fee.to_u64(),
+            inputs: ps2.send().to_vec(),
+            outputs: outputs.clone(),
+            time: unixtime_ms(),
+        };
+        // the pending transaction keeps the invoice and fees for resume_pending_melt
+        let mut txln = LNTransaction::new(
+            TransactionStatus::Pending,
+            TransactionDirection::Out,
+            amount,
+            Some(fee.to_u64() + input_fees),
+            mint_url.as_str(),
+            &invoice_str,
+            &form.quote,
+            None,
+            Some(unit),
+        );
+        let mut batch = Batch::new();
+        batch
+            .add_pending_melt(&pending)
+            .update_proofs_state(
+                mint_url,
                 ps2.send(),
-                fee.into(),
-                Some(unit),
-                None,
-                &self.store,
+                ProofState::Reserved,
+                Some(&form.quote),
             )
-            .await?;
-        if let Some(remain) = pm.change {
-            let remain = remain.into_extended_with_unit(Some(unit));
-            self.store.add_proofs(mint_url, &remain).await?;
-            let ra = remain.sum();
-            if fee >= ra {
-                fee -= ra;
+            .add_transaction(&txln.clone().into());
+        self.store.apply(&batch).await?;
+
+        let res = wallet
+            .melt_with_outputs(&form.quote, ps2.send(), outputs, None)
+            .await;
+        let pm = match res {
+            Ok(pm) => pm,
+            Err(e) => {
+                // the mint maybe paid before the error, release the inputs only if unpaid
+                let unpaid = !e.is_network_error()
+                    && wallet
+                        .melt_quote_state(&form.quote, None)
+                        .await
+                        .map(|q| matches!(q.state(), nut05::QuoteState::Unpaid))
+                        .unwrap_or_default();
+                if unpaid {
+                    txln.status = TransactionStatus::Failed;
+                    let mut batch = Batch::new();
+                    self.release_pending_melt(&pending, &mut batch)?;
+                    batch.add_transaction(&txln.into());
+                    self.store.apply(&batch).await?;
+                    return Err(e.into());
+                }
+
+                // the payment maybe in flight, resume it later
+                warn!("melt {} failed, keep it pending: {}", form.quote, e);
+                let tx = txln.into();
+                let mut batch = Batch::new();
+                batch
+                    .update_proofs_state(mint_url, ps2.send(), ProofState::Pending, None)
+                    .add_transaction(&tx);
+                self.store.apply(&batch).await?;
+                return Ok(tx);
+            }
+        };
+
+        self.finalize_melt(&pending, pm, txln).await
+    }
+
+    /// update the transaction by the melt state: store the change and preimage if paid,
+    /// release the reserved proofs if unpaid, or keep it pending
+    async fn finalize_melt(
+        &self,
+        pending: &PendingMelt,
+        pm: Melted,
+        mut tx: LNTransaction,
+    ) -> Result<Transaction, Error<S::Error>> {
+        let mint_url: Url = pending.mint.parse()?;
+
+        let mut batch = Batch::new();
+        match pm.state {
+            nut05::QuoteState::Paid => {
+                if let Some(change) = pm.change {
+                    let change = change.into_extended_with_unit(pending.unit.as_deref());
+                    batch.add_proofs(&mint_url, &change);
+
+                    let ra = change.sum().to_u64();
+                    if let Some(fee) = tx.fee.as_mut() {
+                        *fee = fee.saturating_sub(ra);
+                    }
+                }
+                batch
+                    .update_proofs_state(&mint_url, &pending.inputs, ProofState::Spent, None)
+                    .delete_pending_melt(&pending.mint, &pending.quote);
+
+                tx.status = TransactionStatus::Success;
+                tx.preimage = pm.preimage;
+            }
+            nut05::QuoteState::Unpaid => {
+                self.release_pending_melt(pending, &mut batch)?;
+                tx.status = TransactionStatus::Failed;
+            }
+            _ => {
+                batch.update_proofs_state(&mint_url, &pending.inputs, ProofState::Pending, None);
+                tx.status = TransactionStatus::Pending
             }
         }
 
-        if pm.state == nut05::QuoteState::Paid {
-            self.store.delete_proofs(mint_url, ps2.send()).await?;
-            // return Err(format_err!("mint server reponse not paid").into());
+        let tx = tx.into();
+        batch.add_transaction(&tx);
+        self.store.apply(&batch).await?;
+
+        Ok(tx)
+    }
+
+    /// the payment failed, take the reserved proofs back
+    fn release_pending_melt(
+        &self,
+        pending: &PendingMelt,
+        batch: &mut Batch,
+    ) -> Result<(), Error<S::Error>> {
+        let mint_url: Url = pending.mint.parse()?;
+
+        // add them back as unspent, the inputs of the melts before the proof states are deleted
+        batch
+            .delete_proofs(&mint_url, &pending.inputs)
+            .add_proofs(&mint_url, &pending.inputs)
+            .delete_pending_melt(&pending.mint, &pending.quote);
+
+        Ok(())
+    }
+
+    /// resume the in-flight melts by polling the quote state, returns (updated, all)
+    pub async fn resume_pending_melts(&self) -> Result<(usize, usize), Error<S::Error>> {
+        let melts = self.store.get_pending_melts().await?;
+
+        let mut update_count = 0;
+        for m in &melts {
+            match self.resume_pending_melt(m).await {
+                Ok(true) => update_count += 1,
+                Ok(false) => {}
+                Err(e) => warn!("resume melt {} failed: {}", m.quote, e),
+            }
         }
 
-        // fill a hash
-        let hash = form.quote;
+        Ok((update_count, melts.len()))
+    }
 
-        let txln: Transaction = LNTransaction::new(
-            if pm.state == nut05::QuoteState::Paid {
-                TransactionStatus::Success
-            } else {
-                TransactionStatus::Failed
-            },
-            TransactionDirection::Out,
-            amount,
-            Some(fee.into()),
-            mint_url.as_str(),
-            &invoice_str,
-            &hash,
-            None,
-            Some(unit),
-        )
-        .into();
-        self.store.add_transaction(&txln).await?;
+    /// returns true if the payment settled or failed
+    pub async fn resume_pending_melt(
+        &self,
+        pending: &PendingMelt,
+    ) -> Result<bool, Error<S::Error>> {
+        let mint_url: Url = pending.mint.parse()?;
+        let wallet = match self.get_wallet_optional(&mint_url)? {
+            Some(w) => w,
+            None => return Ok(false),
+        };
+
+        let resp = wallet.melt_quote_state(&pending.quote, None).await?;
+        let pm = wallet.melted(resp, pending.outputs.clone())?;
+        if pm.state == nut05::QuoteState::Pending {
+            return Ok(false);
+        }
+
+        // the pending melts stored without the transaction by the old versions
+        let tx = match self.store.get_transaction(&pending.quote).await? {
+            Some(Transaction::LN(tx)) => tx,
+            _ => LNTransaction::new(
+                TransactionStatus::Pending,
+                TransactionDirection::Out,
+                pending.amount,
+                Some(pending.fee_reserve),
+                mint_url.as_str(),
+                "",
+                &pending.quote,
+                Some(pending.time),
+                pending.unit.as_deref(),
+            ),
+        };
+
+        self.finalize_melt(pending, pm, tx).await?;
+        Ok(true)
+    }
+
+    /// drop the journal of the operation done or rejected by mint
+    async fn complete(&self, journal: &Journal<'_, S>) -> Result<(), Error<S::Error>> {
+        let mut batch = Batch::new();
+        batch.complete(journal);
+        self.store.apply(&batch).await?;
+        Ok(())
+    }
+
+    /// keep the journal for recover_operations, the counters committed are stored still
+    async fn keep(&self, journal: &Journal<'_, S>) -> Result<(), Error<S::Error>> {
+        let mut batch = Batch::new();
+        for r in journal.records() {
+            batch.add_counter(&r);
+        }
+        if !batch.is_empty() {
+            self.store.apply(&batch).await?;
+        }
+        Ok(())
+    }
+
+    /// the journal is kept for recover_operations if the mint is unreachable, it maybe committed.
+    /// the signatures failed the DLEQ are committed also, the journal keeps their outputs until recovered without the DLEQ
+    async fn journaled<T>(
+        &self,
+        journal: &Journal<'_, S>,
+        res: Result<T, WalletError>,
+    ) -> Result<T, Error<S::Error>> {
+        match res {
+            Ok(t) => Ok(t),
+            Err(e) => {
+                if !e.is_network_error() && !e.is_dleq() {
+                    self.complete(journal).await?;
+                } else {
+                    self.keep(journal).await?;
+                }
+                Err(e.into())
+            }
+        }
+    }
+
+    async fn stored_secrets(
+        &self,
+        mint_url: &Url,
+        states: &[ProofState],
+    ) -> Result<BTreeSet<Secret>, Error<S::Error>> {
+        let ps = self.store.get_proofs_with_state(mint_url, states).await?;
+        let secrets = ps.into_iter().map(|p| p.raw.secret).collect();
+        Ok(secrets)
+    }
+
+    /// replay or roll back the operations interrupted by a crash, call it on startup after the mints loaded.
+    /// returns (resolved, all)
+    pub async fn recover_operations(&self) -> Result<(usize, usize), Error<S::Error>> {
+        // the inputs of in-flight melts are reserved, the crash maybe left them unspent
+        for m in self.store.get_pending_melts().await? {
+            let mint_url: Url = m.mint.parse()?;
+            let stored = self
+                .stored_secrets(&mint_url, &[ProofState::Unspent])
+                .await?;
+
+            let reserved = m
+                .inputs
+                .iter()
+                .filter(|p| stored.contains(&p.raw.secret))
+                .cloned()
+                .collect::<Vec<_>>();
+            if !reserved.is_empty() {
+                warn!(
+                    "reserve {} unspent inputs of melt {}",
+                    reserved.len(),
+                    m.quote
+                );
+                self.store
+                    .update_proofs_state(&mint_url, &reserved, ProofState::Reserved, Some(&m.quote))
+                    .await?;
+            }
+        }
+
+        let ops = self.store.get_operations().await?;
+
+        let mut update_count = 0;
+        for op in &ops {
+            match self.recover_operation(op).await {
+                Ok(true) => update_count += 1,
+                Ok(false) => {}
+                Err(e) => warn!("recover operation {} failed: {}", op.id, e),
+            }
+        }
+
+        Ok((update_count, ops.len()))
+    }
+
+    /// NUT-09 restore the journaled outputs and NUT-07 check the inputs: store the proofs signed but lost,
+    /// drop the inputs spent and take back the inputs if the mint not committed.
+    /// returns false if the mint is not added or the inputs are pending
+    pub async fn recover_operation(&self, op: &PendingOperation) -> Result<bool, Error<S::Error>> {
+        let mint_url: Url = op.mint.parse()?;
+        let wallet = match self.get_wallet_optional(&mint_url)? {
+            Some(w) => w,
+            None => return Ok(false),
+        };
+
+        // the inputs of receive are not ours if the mint not committed
+        let mut states = vec![];
+        if op.kind == OperationKind::Swap && !op.inputs.is_empty() {
+            states = wallet.check_proofs(&op.inputs).await?.states;
+            if states.len() != op.inputs.len() {
+                return Err(format_err!(
+                    "invalid check_proofs response {}->{}",
+                    op.inputs.len(),
+                    states.len(),
+                )
+                .into());
+            }
+            if states.iter().any(|s| s.state == State::Pending) {
+                return Ok(false);
+            }
+        }
+
+        let signed = wallet.restore_outputs(op.outputs.clone()).await?;
+        let committed = !signed.is_empty();
+        let mut outputs = vec![];
+        if !signed.is_empty() {
+            let signed_states = wallet.check_proofs(&signed).await?.states;
+            if signed_states.len() != signed.len() {
+                return Err(format_err!(
+                    "invalid check_proofs response {}->{}",
+                    signed.len(),
+                    signed_states.len(),
+                )
+                .into());
+            }
+
+            outputs = signed
+                .into_iter()
+                .zip(signed_states)
+                .filter(|(_, s)| s.state == State::Unspent)
+                .map(|(p, _)| p)
+                .collect::<Vec<_>>();
+        }
+
+        let stored = self.stored_secrets(&mint_url, &ProofState::ALL).await?;
+
+        // the proofs of the tokens sent are not ours
+        let mut known = stored.clone();
+        let mut unswappeds = vec![];
+        for tx in self.get_pending_transactions_of(&mint_url).await? {
+            if tx.is_cashu() {
+                let token: Token = tx.content().parse()?;
+                let secrets = token
+                    .into_v3()?
+                    .token
+                    .into_iter()
+                    .flat_map(|t| t.proofs)
+                    .map(|p| p.secret);
+                if !tx.direction().is_in() {
+                    known.extend(secrets);
+                } else if op.kind == OperationKind::Receive {
+                    unswappeds.push((tx, secrets.collect::<BTreeSet<_>>()));
+                }
+            }
+        }
+        outputs.retain(|p| !known.contains(&p.raw.secret));
+        if !outputs.is_empty() {
+            info!("recover {} proofs of operation {}", outputs.len(), op.id);
+        }
+
+        let mut batch = Batch::new();
+        batch.add_proofs(&mint_url, &outputs);
+
+        // the counters of the outputs are stored with the batch completing the journal, crashed before it
+        if let (Some((_, end)), Some(o)) = (op.counters, op.outputs.first()) {
+            if let Some(r) = wallet
+                .advance_counter(&o.blinded_message.keyset_id, end)
+                .await
+            {
+                batch.add_counter(&r);
+            }
+        }
+
+        // the token received offline is swapped by the operation
+        if committed && !op.inputs.is_empty() {
+            for (mut tx, secrets) in unswappeds {
+                if op.inputs.iter().all(|p| secrets.contains(&p.raw.secret)) {
+                    info!(
+                        "the offline received {} swapped by operation {}",
+                        tx.id(),
+                        op.id
+                    );
+                    *tx.status_mut() = TransactionStatus::Success;
+                    batch
+                        .update_proofs_state(&mint_url, &op.inputs, ProofState::Spent, None)
+                        .add_transaction(&tx);
+                }
+            }
+        }
+
+        if !states.is_empty() {
+            let mut spent = vec![];
+            let mut unspent = vec![];
+            for (p, s) in op.inputs.iter().zip(states) {
+                let has = stored.contains(&p.raw.secret);
+                if s.state == State::Spent && has {
+                    spent.push(p.clone());
+                } else if s.state != State::Spent && !has {
+                    unspent.push(p.clone());
+                }
+            }
+
+            batch
+                .delete_proofs(&mint_url, &spent)
+                .add_proofs(&mint_url, &unspent);
+        }
 
-        Ok(txln)
+        batch.delete_operation(&op.mint, &op.id);
+        self.store.apply(&batch).await?;
+        Ok(true)
     }
 
     /// sleepms_after_check_a_batch for (code: 429): {"detail":"Rate limit exceeded."}
@@ -1003,15 +2401,11 @@ where
             .await;
 
         if !proofs.is_empty() {
-            let ps = self.store().get_proofs(mint_url).await?;
-            let psmap = ps
-                .values()
-                .map(|v| v.iter().map(|p| (&p.raw.secret, p)))
-                .flatten()
-                .collect::<std::collections::BTreeMap<_, _>>();
+            // the reserved and pending are ours too
+            let stored = self.stored_secrets(mint_url, &ProofState::ALL).await?;
 
             // prevent duplicate store
-            proofs.retain(|p| psmap.get(&p.raw.secret).is_none());
+            proofs.retain(|p| !stored.contains(&p.raw.secret));
             self.store.add_proofs(mint_url, &proofs).await?;
         }
 
@@ -1023,33 +2417,42 @@ where
 
 // simple
 #[doc(hidden)]
+/// select the proofs[..=take] to send, the NUT-02 input fee is included by input_fee_ppk of proof's keyset
 pub fn select_send_proofs<E: StdError>(
     amount: u64,
     proofs: &mut Vec<impl AsRef<Proof>>,
+    input_fee_ppk: impl Fn(&Proof) -> u64,
 ) -> Result<usize, Error<E>> {
     if amount == 0 {
         return Err(WalletError::Custom(format_err!("send amount 0")).into());
     }
 
     let mut a = 0;
+    let mut ppk = 0;
     let mut take = 0;
 
-    let p = proofs
-        .iter()
-        .position(|p| p.as_ref().amount.to_u64() == amount);
+    // the exact one could skip swap only if no fee
+    let p = proofs.iter().position(|p| {
+        let p = p.as_ref();
+        p.amount.to_u64() == amount && input_fee_ppk(p) == 0
+    });
     if let Some(p) = p {
         proofs.swap(0, p);
     } else {
+        let mut fee = 0;
         for (idx, proof) in proofs.iter().enumerate() {
-            a += proof.as_ref().amount.to_u64();
+            let proof = proof.as_ref();
+            a += proof.amount.to_u64();
+            ppk += input_fee_ppk(proof);
+            fee = fee_from_ppk(ppk).to_u64();
 
-            if a >= amount {
+            if a >= amount + fee {
                 take = idx;
                 break;
             }
         }
 
-        if a < amount {
+        if a < amount + fee {
             return Err(WalletError::insufficant_funds().into());
         }
     }
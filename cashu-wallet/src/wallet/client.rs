@@ -1,26 +1,36 @@
 pub use reqwest::Client as HttpClient;
+use serde::{de::DeserializeOwned, Serialize};
 
 use cashu::nuts::*;
 use cashu::Amount;
 use cashu::Bolt11Invoice;
 
 use super::error::ClientError as Error;
+use super::tls::PinVerifier;
 use super::AmountHelper;
 use super::BlindedMessages;
 use super::MintUrl as Url;
 use super::ProofsHelper;
+use super::{HttpTransport, MintTransport, TransportResponse};
 
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
 use std::time::Duration;
 
 pub static CURRENCY_UNIT_SAT: &str = "sat";
 pub static PAYMEN_METHOD_BOLT11: &str = "bolt11";
+/// the proxy of HttpOptions::mint_proxy, connect the mint directly
+pub static PROXY_DIRECT: &str = "direct";
 
 /// <https://github.com/cashubtc/nuts/tree/main>
 #[derive(Debug, Clone)]
 pub struct MintClient {
     pub(super) url: Url,
-    pub(super) http: HttpClient,
+    pub(super) transport: Arc<dyn MintTransport>,
     pub(super) options: HttpOptions,
+    // the client of HttpTransport, for the deprecated http()
+    http: Option<HttpClient>,
 }
 
 /// only used when could use
@@ -32,6 +42,24 @@ pub struct HttpOptions {
     pub timeout_get_ms: Option<u64>,
     pub timeout_swap_ms: Option<u64>,
     pub timeout_melt_ms: Option<u64>,
+    /// the retry of the idempotent requests, None never retries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+    /// the proxy of all mints: socks5h://127.0.0.1:9050 for Tor, http://.. or socks5://..
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// the proxy by mint url overrides the global one, PROXY_DIRECT for no proxy
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mint_proxies: BTreeMap<String, String>,
+    /// the SOCKS5 auth by mint, Tor(IsolateSOCKSAuth) uses a separate circuit for each mint
+    #[serde(default)]
+    pub isolate_mints: bool,
+    /// the extra PEM root certificates trusted, for the mints of private CA
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub root_certificates: Vec<String>,
+    /// the SPKI pins(see spki_pin) by mint url, one of the certificates in chain must match
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mint_pins: BTreeMap<String, Vec<String>>,
 }
 
 impl HttpOptions {
@@ -83,17 +111,186 @@ impl HttpOptions {
     pub fn timeout_melt(&self) -> Option<Duration> {
         self.timeout_melt_ms.map(Duration::from_millis)
     }
+
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    pub fn proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    pub fn mint_proxy(mut self, mint: &Url, proxy: impl Into<String>) -> Self {
+        self.mint_proxies
+            .insert(mint.as_str().to_owned(), proxy.into());
+        self
+    }
+
+    pub fn isolate_mints(mut self, b: bool) -> Self {
+        self.isolate_mints = b;
+        self
+    }
+
+    pub fn root_certificate(mut self, pem: impl Into<String>) -> Self {
+        self.root_certificates.push(pem.into());
+        self
+    }
+
+    /// the empty pins removes the pinning
+    pub fn mint_pins(mut self, mint: &Url, pins: Vec<String>) -> Self {
+        if pins.is_empty() {
+            self.mint_pins.remove(mint.as_str());
+        } else {
+            self.mint_pins.insert(mint.as_str().to_owned(), pins);
+        }
+        self
+    }
+
+    pub fn pins_of(&self, mint: &Url) -> &[String] {
+        self.mint_pins
+            .get(mint.as_str())
+            .map(|ps| ps.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// the proxy to connect the mint, None is directly
+    pub fn proxy_of(&self, mint: &Url) -> Option<&str> {
+        match self.mint_proxies.get(mint.as_str()) {
+            Some(p) if p == PROXY_DIRECT => None,
+            Some(p) => Some(p.as_str()),
+            None => self.proxy.as_deref(),
+        }
+    }
+}
+
+/// the retry of the idempotent requests(keys, info, checkstate, restore and quote states)
+/// on the network errors, 429 and 502/503/504. the swap/mint/melt are never retried
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RetryPolicy {
+    /// the attempts including the first one
+    pub max_attempts: u32,
+    /// the backoff before the 2nd attempt, doubled after each attempt
+    pub backoff_ms: u64,
+    /// the max backoff, and the max Retry-After to wait, gives up if the mint asks longer
+    pub backoff_max_ms: u64,
+    /// randomize the backoff by ±percent
+    pub jitter_percent: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff_ms: 500,
+            backoff_max_ms: 10_000,
+            jitter_percent: 20,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_attempts(mut self, n: u32) -> Self {
+        self.max_attempts = n;
+        self
+    }
+
+    pub fn backoff_ms(mut self, millis: u64) -> Self {
+        self.backoff_ms = millis;
+        self
+    }
+
+    pub fn backoff_max_ms(mut self, millis: u64) -> Self {
+        self.backoff_max_ms = millis;
+        self
+    }
+
+    pub fn jitter_percent(mut self, percent: u64) -> Self {
+        self.jitter_percent = percent.min(100);
+        self
+    }
+
+    /// the wait after the attempt(1 is the first) failed, None gives up
+    pub fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt == 0 || attempt >= self.max_attempts {
+            return None;
+        }
+
+        let max = Duration::from_millis(self.backoff_max_ms);
+        if retry_after.map(|d| d > max).unwrap_or_default() {
+            return None;
+        }
+
+        let exp = (attempt - 1).min(32);
+        let mut ms = self
+            .backoff_ms
+            .saturating_mul(1 << exp)
+            .min(self.backoff_max_ms);
+        let jitter = ms * self.jitter_percent.min(100) / 100;
+        if jitter > 0 {
+            ms = ms - jitter + rand::random::<u64>() % (jitter * 2 + 1);
+        }
+
+        let backoff = Duration::from_millis(ms).min(max);
+        Some(retry_after.map_or(backoff, |d| d.max(backoff)))
+    }
+}
+
+/// NUT-05 melt response, it's the same as the melt quote state response,
+/// the state is Unpaid/Pending/Paid, the legacy mints returns paid only
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeltResponse {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paid: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<nut05::QuoteState>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payment_preimage: Option<String>,
+    // NUT-08 change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change: Option<Vec<BlindSignature>>,
+}
+
+impl MeltResponse {
+    pub fn state(&self) -> nut05::QuoteState {
+        match (&self.state, self.paid) {
+            (Some(s), _) => s.clone(),
+            (None, Some(true)) => nut05::QuoteState::Paid,
+            (None, _) => nut05::QuoteState::Unpaid,
+        }
+    }
 }
 
 impl MintClient {
     pub fn with_http(mint: Url, options: HttpOptions, http: HttpClient) -> Result<Self, Error> {
-        Ok(Self {
+        let mut this =
+            Self::with_transport(mint, options, Arc::new(HttpTransport::new(http.clone())));
+        this.http = Some(http);
+        Ok(this)
+    }
+
+    /// the mint client over the custom transport, the timeouts of options are passed to it
+    pub fn with_transport(
+        mint: Url,
+        options: HttpOptions,
+        transport: Arc<dyn MintTransport>,
+    ) -> Self {
+        Self {
             url: mint,
-            http,
+            transport,
             options,
-        })
+            http: None,
+        }
     }
 
+    /// the http client is not shared, the connections of each mint are separate
     pub fn new(mint: Url, options: HttpOptions) -> Result<Self, Error> {
         let mut h = HttpClient::builder().connection_verbose(options.connection_verbose);
 
@@ -101,11 +298,40 @@ impl MintClient {
             h = h.connect_timeout(t)
         }
 
-        Ok(Self {
-            http: h.build()?,
-            url: mint,
-            options,
-        })
+        match options.proxy_of(&mint) {
+            Some(p) => {
+                let mut proxy = reqwest::Proxy::all(p)?;
+                if options.isolate_mints {
+                    proxy = proxy.basic_auth(mint.as_str(), "cashu");
+                }
+                h = h.proxy(proxy);
+            }
+            None if mint.is_onion() => {
+                return Err(Error::Config(format!(
+                    "the onion mint requires a proxy: {}",
+                    mint
+                )))
+            }
+            // the mint is direct, ignore the system proxy also
+            None if options.proxy.is_some() => h = h.no_proxy(),
+            None => {}
+        }
+
+        for pem in &options.root_certificates {
+            h = h.add_root_certificate(reqwest::Certificate::from_pem(pem.as_bytes())?);
+        }
+
+        let pins = options.pins_of(&mint);
+        if !pins.is_empty() {
+            let verifier = Arc::new(PinVerifier::new(&options.root_certificates, pins)?);
+            let http = h.use_preconfigured_tls(verifier.client_config()).build()?;
+            let transport = HttpTransport::with_pins(http.clone(), verifier);
+            let mut this = Self::with_transport(mint, options, Arc::new(transport));
+            this.http = Some(http);
+            return Ok(this);
+        }
+
+        Self::with_http(mint, options, h.build()?)
     }
 
     pub fn url(&self) -> &Url {
@@ -116,8 +342,90 @@ impl MintClient {
         self.url.as_ref()
     }
 
-    pub fn http(&self) -> &HttpClient {
-        &self.http
+    pub fn transport(&self) -> &Arc<dyn MintTransport> {
+        &self.transport
+    }
+
+    /// the http client if the default HttpTransport is in use, None for the custom transport
+    #[deprecated(note = "the requests go through transport(), the client maybe not http")]
+    pub fn http(&self) -> Option<&HttpClient> {
+        self.http.as_ref()
+    }
+
+    /// send the idempotent request again by the retry policy of options
+    async fn retry<F, Fut>(&self, send: F) -> Result<TransportResponse, Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<TransportResponse, Error>>,
+    {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let res = send().await;
+
+            let retry_after = match &res {
+                Ok(r) if matches!(r.status, 429 | 502 | 503 | 504) => r.retry_after,
+                Err(e) if e.is_network_error() => None,
+                _ => return res,
+            };
+            let backoff = self
+                .options
+                .retry
+                .as_ref()
+                .and_then(|p| p.backoff(attempt, retry_after));
+            match backoff {
+                Some(d) => {
+                    warn!("{} retry after {:?}: {}", self.url.as_str(), d, attempt);
+                    tokio::time::sleep(d).await;
+                }
+                None => return res,
+            }
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        url: url::Url,
+        timeout: Option<Duration>,
+    ) -> Result<T, Error> {
+        let resp = self
+            .retry(|| self.transport.get(url.clone(), timeout))
+            .await?;
+
+        debug!("{}: {}", resp.status, resp.body);
+
+        Error::try_parse(&resp.body, resp.status)
+    }
+
+    async fn post<B: Serialize + ?Sized, T: DeserializeOwned>(
+        &self,
+        url: url::Url,
+        body: &B,
+        timeout: Option<Duration>,
+    ) -> Result<T, Error> {
+        let body = serde_json::to_string(body)?;
+        let resp = self.transport.post(url, body, timeout).await?;
+
+        debug!("{}: {}", resp.status, resp.body);
+
+        Error::try_parse(&resp.body, resp.status)
+    }
+
+    /// the POST only reads the mint: checkstate and restore
+    async fn post_idempotent<B: Serialize + ?Sized, T: DeserializeOwned>(
+        &self,
+        url: url::Url,
+        body: &B,
+        timeout: Option<Duration>,
+    ) -> Result<T, Error> {
+        let body = serde_json::to_string(body)?;
+        let resp = self
+            .retry(|| self.transport.post(url.clone(), body.clone(), timeout))
+            .await?;
+
+        debug!("{}: {}", resp.status, resp.body);
+
+        Error::try_parse(&resp.body, resp.status)
     }
 
     //  curl https://mint.host:3338/keys
@@ -127,19 +435,12 @@ impl MintClient {
     pub async fn get_keys(&self, id: Option<&str>) -> Result<nut01::KeysResponse, Error> {
         let mut url = self.urlraw().join("v1/keys")?;
         if let Some(id) = id {
+            // the legacy base64 keyset id is url-safe
+            let id = id.replace('+', "-").replace('/', "_");
             url = self.urlraw().join(&format!("v1/keys/{id}"))?;
         }
 
-        let mut req = self.http.get(url);
-        if let Some(t) = self.options.timeout_get() {
-            req = req.timeout(t);
-        }
-
-        let resp = req.send().await?;
-        let httpcode = resp.status().as_u16() as i32;
-        let body = resp.text().await?;
-
-        Error::try_parse(&body, httpcode)
+        self.get(url, self.options.timeout_get()).await
     }
 
     /*
@@ -155,16 +456,7 @@ impl MintClient {
     pub async fn get_keysetids(&self) -> Result<nut02::KeysetResponse, Error> {
         let url = self.urlraw().join("v1/keysets")?;
 
-        let mut req = self.http.get(url);
-        if let Some(t) = self.options.timeout_get() {
-            req = req.timeout(t);
-        }
-
-        let resp = req.send().await?;
-        let httpcode = resp.status().as_u16() as i32;
-        let body = resp.text().await?;
-
-        Error::try_parse(&body, httpcode)
+        self.get(url, self.options.timeout_get()).await
     }
 
     /// NUT-03: Swap tokens
@@ -185,17 +477,7 @@ impl MintClient {
             outputs,
         };
 
-        let mut req = self.http.post(url).json(&request);
-        if let Some(t) = self.options.timeout_split() {
-            req = req.timeout(t);
-        }
-
-        let resp = req.send().await?;
-        let httpcode = resp.status().as_u16() as i32;
-        let body = resp.text().await?;
-        debug!("{}: {}", httpcode, body);
-
-        Error::try_parse(&body, httpcode)
+        self.post(url, &request, self.options.timeout_split()).await
     }
 
     /// 04  Request minting
@@ -219,18 +501,21 @@ impl MintClient {
             unit,
         };
 
-        let mut req = self.http.post(url).json(&form);
-        if let Some(t) = self.options.timeout_get() {
-            req = req.timeout(t);
-        }
-
-        let resp = req.send().await?;
-        let httpcode = resp.status().as_u16() as i32;
-        let body = resp.text().await?;
+        self.post(url, &form, self.options.timeout_get()).await
+    }
 
-        debug!("{}: {}", httpcode, body);
+    /// 04  Check mint quote state
+    // curl -X GET https://8333.space:3338/v1/mint/quote/bolt11/xxx
+    pub async fn mint_quote_state(
+        &self,
+        quote: &str,
+        method: &str,
+    ) -> Result<nut04::MintQuoteBolt11Response, Error> {
+        let url = self
+            .urlraw()
+            .join(&format!("v1/mint/quote/{method}/{quote}"))?;
 
-        Error::try_parse(&body, httpcode)
+        self.get(url, self.options.timeout_get()).await
     }
 
     /// 04 	Minting tokens
@@ -253,20 +538,7 @@ impl MintClient {
             quote: hash,
         };
 
-        let mut req = self.http.post(url).json(&request);
-        if let Some(t) = self.options.timeout_split() {
-            req = req.timeout(t);
-        }
-
-        let resp = req.send().await?;
-
-        // let resp = self.http.post(url).json(&request).send().await?;
-        let httpcode = resp.status().as_u16() as i32;
-        let body = resp.text().await?;
-
-        debug!("{}: {}", httpcode, body);
-
-        Error::try_parse(&body, httpcode)
+        self.post(url, &request, self.options.timeout_split()).await
     }
 
     /// 05 	Melting tokens: Melt quote
@@ -276,6 +548,7 @@ impl MintClient {
         invoice: &Bolt11Invoice,
         unit: &str,
         method: &str,
+        amount_msat: Option<u64>,
     ) -> Result<nut05::MeltQuoteBolt11Response, Error> {
         let mut url = self.urlraw().join("v1/melt/quote/")?;
         url = url.join(method)?;
@@ -284,25 +557,37 @@ impl MintClient {
         pub struct Request<'a> {
             request: &'a Bolt11Invoice,
             unit: &'a str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            options: Option<Options>,
         }
+        #[derive(Debug, Serialize)]
+        pub struct Options {
+            amountless: Amountless,
+        }
+        #[derive(Debug, Serialize)]
+        pub struct Amountless {
+            amount_msat: u64,
+        }
+        // NUT-05 amountless: the amount of the invoice without amount
         let request = Request {
             request: invoice,
             unit,
+            options: amount_msat.map(|amount_msat| Options {
+                amountless: Amountless { amount_msat },
+            }),
         };
 
-        let mut req = self.http.post(url).json(&request);
-        if let Some(t) = self.options.timeout_get() {
-            req = req.timeout(t);
-        }
-
-        let resp = req.send().await?;
-
-        let httpcode = resp.status().as_u16() as i32;
-        let body = resp.text().await?;
+        self.post(url, &request, self.options.timeout_get()).await
+    }
 
-        debug!("{}: {}", httpcode, body);
+    /// 05  Check melt quote state
+    // curl -X GET https://8333.space:3338/v1/melt/quote/bolt11/xxx
+    pub async fn melt_quote_state(&self, quote: &str, method: &str) -> Result<MeltResponse, Error> {
+        let url = self
+            .urlraw()
+            .join(&format!("v1/melt/quote/{method}/{quote}"))?;
 
-        Error::try_parse(&body, httpcode)
+        self.get(url, self.options.timeout_get()).await
     }
 
     /// 05 	Melting tokens
@@ -318,7 +603,7 @@ impl MintClient {
         quote: &str,
         outputs: Option<&BlindedMessages<'_>>,
         method: &str,
-    ) -> Result<nut05::MeltBolt11Response, Error> {
+    ) -> Result<MeltResponse, Error> {
         let mut url = self.urlraw().join("v1/melt/")?;
         url = url.join(method)?;
 
@@ -371,34 +656,14 @@ impl MintClient {
         };
         // println!("{}", serde_json::to_string(&request).unwrap());
 
-        let mut req = self.http.post(url).json(&request);
-        if let Some(t) = self.options.timeout_melt() {
-            req = req.timeout(t);
-        }
-
-        let resp = req.send().await?;
-        let httpcode = resp.status().as_u16() as i32;
-        let body = resp.text().await?;
-
-        debug!("{}: {}", httpcode, body);
-
-        Error::try_parse(&body, httpcode)
+        self.post(url, &request, self.options.timeout_melt()).await
     }
 
     /// NUT-06: Mint information
     pub async fn get_info(&self) -> Result<crate::types::MintInfo, Error> {
         let url = self.urlraw().join("v1/info")?;
 
-        let mut req = self.http.get(url);
-        if let Some(t) = self.options.timeout_get() {
-            req = req.timeout(t);
-        }
-        let resp = req.send().await?;
-
-        let httpcode = resp.status().as_u16() as i32;
-        let body = resp.text().await?;
-
-        Error::try_parse(&body, httpcode)
+        self.get(url, self.options.timeout_get()).await
     }
 
     /// 07 	Token state check: Spendable check
@@ -416,19 +681,9 @@ impl MintClient {
         let request = CheckStateRequest { ys };
         // println!("{}", serde_json::to_string(&request).unwrap());
 
-        let mut req = self.http.post(url).json(&request);
         // maybe slow
-        if let Some(t) = self.options.timeout_split() {
-            req = req.timeout(t);
-        }
-
-        let resp = req.send().await?;
-        let httpcode = resp.status().as_u16() as i32;
-        let body = resp.text().await?;
-
-        // info!("{}: {}", httpcode, body);
-
-        Error::try_parse(&body, httpcode)
+        self.post_idempotent(url, &request, self.options.timeout_split())
+            .await
     }
 
     /// NUT-09: Restore signatures
@@ -446,20 +701,8 @@ impl MintClient {
             outputs: blinded_messages,
         };
 
-        let mut req = self.http.post(url).json(&request);
-        if let Some(t) = self.options.timeout_split() {
-            req = req.timeout(t);
-        }
-
-        let resp = req.send().await?;
-
-        // let resp = self.http.post(url).json(&request).send().await?;
-        let httpcode = resp.status().as_u16() as i32;
-        let body = resp.text().await?;
-
-        debug!("{}: {}", httpcode, body);
-
-        Error::try_parse(&body, httpcode)
+        self.post_idempotent(url, &request, self.options.timeout_split())
+            .await
     }
 }
 
@@ -474,4 +717,81 @@ mod tests {
 
         let _error = Error::try_parse::<u32>(err, 200).unwrap_err();
     }
+
+    #[test]
+    fn test_decode_melt_response() {
+        let legacy = r#"{"paid":true,"payment_preimage":"00","change":null}"#;
+        let resp: MeltResponse = serde_json::from_str(legacy).unwrap();
+        assert_eq!(resp.state(), nut05::QuoteState::Paid);
+
+        let legacy = r#"{"paid":false,"payment_preimage":null}"#;
+        let resp: MeltResponse = serde_json::from_str(legacy).unwrap();
+        assert_eq!(resp.state(), nut05::QuoteState::Unpaid);
+
+        let quote = r#"{"quote":"q","amount":1,"fee_reserve":2,"paid":false,"state":"PENDING","expiry":1701704757}"#;
+        let resp: MeltResponse = serde_json::from_str(quote).unwrap();
+        assert_eq!(resp.state(), nut05::QuoteState::Pending);
+    }
+
+    #[test]
+    fn test_proxy_of() {
+        let tor = "socks5h://127.0.0.1:9050";
+        let clear: Url = "https://8333.space:3338".parse().unwrap();
+        let onion: Url = "http://mintxxx.onion".parse().unwrap();
+        let local: Url = "http://127.0.0.1:3338".parse().unwrap();
+
+        let options = HttpOptions::new();
+        assert_eq!(options.proxy_of(&clear), None);
+        let err = MintClient::new(onion.clone(), options).unwrap_err();
+        assert!(err.is_config());
+        assert!(!err.is_network_error());
+
+        let options = HttpOptions::new()
+            .proxy(tor)
+            .mint_proxy(&local, PROXY_DIRECT)
+            .mint_proxy(&onion, "socks5h://127.0.0.1:9150")
+            .isolate_mints(true);
+        assert_eq!(options.proxy_of(&clear), Some(tor));
+        assert_eq!(options.proxy_of(&local), None);
+        assert_eq!(options.proxy_of(&onion), Some("socks5h://127.0.0.1:9150"));
+        assert!(MintClient::new(onion, options.clone()).is_ok());
+        assert!(MintClient::new(local, options).is_ok());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_http_deprecated() {
+        let url: Url = "https://8333.space:3338".parse().unwrap();
+        let client = MintClient::new(url.clone(), HttpOptions::new()).unwrap();
+        assert!(client.http().is_some());
+
+        let custom =
+            MintClient::with_transport(url, HttpOptions::new(), client.transport().clone());
+        assert!(custom.http().is_none());
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        let p = RetryPolicy::new()
+            .max_attempts(4)
+            .backoff_ms(100)
+            .backoff_max_ms(300)
+            .jitter_percent(0);
+
+        assert_eq!(p.backoff(1, None), Some(Duration::from_millis(100)));
+        assert_eq!(p.backoff(2, None), Some(Duration::from_millis(200)));
+        assert_eq!(p.backoff(3, None), Some(Duration::from_millis(300)));
+        assert_eq!(p.backoff(4, None), None);
+
+        // Retry-After
+        let d = Duration::from_millis(250);
+        assert_eq!(p.backoff(1, Some(d)), Some(d));
+        assert_eq!(p.backoff(1, Some(Duration::from_secs(1))), None);
+
+        let p = p.jitter_percent(50);
+        for _ in 0..100 {
+            let d = p.backoff(1, None).unwrap();
+            assert!(d >= Duration::from_millis(50) && d <= Duration::from_millis(150));
+        }
+    }
 }
@@ -0,0 +1,261 @@
+use cashu::nuts::nut00::Witness;
+use cashu::nuts::nut01::{PublicKey, SecretKey};
+use cashu::nuts::nut14::HTLCWitness;
+use cashu::nuts::Proof;
+use std::str::FromStr;
+
+pub use cashu::nuts::nut11::{Conditions, SigFlag, SpendingConditions};
+
+use super::Error;
+
+/// NUT-11 P2PK: https://github.com/cashubtc/nuts/blob/main/11.md
+///
+/// pubkeys and num_sigs for multisig, refund_keys could spend it after the locktime(unix seconds)
+pub fn p2pk_conditions(
+    pubkey: PublicKey,
+    locktime: Option<u64>,
+    pubkeys: Option<Vec<PublicKey>>,
+    refund_keys: Option<Vec<PublicKey>>,
+    num_sigs: Option<u64>,
+) -> SpendingConditions {
+    let conditions =
+        if locktime.is_none() && pubkeys.is_none() && refund_keys.is_none() && num_sigs.is_none() {
+            None
+        } else {
+            Some(Conditions {
+                locktime,
+                pubkeys,
+                refund_keys,
+                num_sigs,
+                sig_flag: SigFlag::SigInputs,
+            })
+        };
+
+    SpendingConditions::new_p2pk(pubkey, conditions)
+}
+
+/// NUT-14 HTLC: https://github.com/cashubtc/nuts/blob/main/14.md
+///
+/// hash is the hex sha256 of the preimage, pubkeys must sign with the preimage, refund_keys could spend it after the locktime(unix seconds)
+pub fn htlc_conditions(
+    hash: &str,
+    locktime: Option<u64>,
+    pubkeys: Option<Vec<PublicKey>>,
+    refund_keys: Option<Vec<PublicKey>>,
+) -> Result<SpendingConditions, Error> {
+    use bitcoin::hashes::sha256::Hash as Sha256Hash;
+
+    let data = Sha256Hash::from_str(hash).map_err(|e| format_err!("invalid htlc hash: {}", e))?;
+
+    let conditions = if locktime.is_none() && pubkeys.is_none() && refund_keys.is_none() {
+        None
+    } else {
+        Some(Conditions {
+            locktime,
+            pubkeys,
+            refund_keys,
+            num_sigs: None,
+            sig_flag: SigFlag::SigInputs,
+        })
+    };
+
+    Ok(SpendingConditions::HTLCConditions { data, conditions })
+}
+
+/// the hex sha256 hash of the HTLC preimage, the preimage is 32 bytes hex and the raw bytes are hashed
+pub fn htlc_hash(preimage: &str) -> Result<String, Error> {
+    use bitcoin::hashes::sha256::Hash as Sha256Hash;
+    use bitcoin::hashes::Hash;
+
+    let bytes = hex::decode(preimage).map_err(|e| format_err!("invalid htlc preimage: {}", e))?;
+    if bytes.len() != 32 {
+        return Err(format_err!("invalid htlc preimage length: {}", bytes.len()).into());
+    }
+
+    Ok(Sha256Hash::hash(&bytes).to_string())
+}
+
+/// parse the NUT-10 well-known secret of the proof, None for the plain random secret
+pub fn spending_conditions(proof: &Proof) -> Option<SpendingConditions> {
+    SpendingConditions::try_from(&proof.secret).ok()
+}
+
+fn conditions_of(sc: &SpendingConditions) -> Option<&Conditions> {
+    match sc {
+        SpendingConditions::P2PKConditions { conditions, .. } => conditions.as_ref(),
+        SpendingConditions::HTLCConditions { conditions, .. } => conditions.as_ref(),
+    }
+}
+
+/// the witness materials to unlock the NUT-10 locked proofs before swap them
+#[derive(Debug, Clone, Default)]
+pub struct Unlocking {
+    pub signing_keys: Vec<SecretKey>,
+    // NUT-14 HTLC preimage
+    pub preimage: Option<String>,
+}
+
+impl Unlocking {
+    pub fn new(signing_keys: Vec<SecretKey>) -> Self {
+        Self {
+            signing_keys,
+            preimage: None,
+        }
+    }
+
+    pub fn preimage(mut self, preimage: Option<String>) -> Self {
+        self.preimage = preimage;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signing_keys.is_empty() && self.preimage.is_none()
+    }
+
+    /// add the preimage to the HTLC proofs(refund after locktime doesn't need it),
+    /// and sign the locked proofs with the keys matched its pubkeys/refund_keys, returns the count of signatures
+    pub fn apply(&self, proofs: &mut [Proof]) -> Result<usize, Error> {
+        let mut count = 0;
+
+        for p in proofs {
+            let sc = match spending_conditions(p) {
+                Some(sc) => sc,
+                None => continue,
+            };
+
+            if matches!(
+                conditions_of(&sc).map(|c| &c.sig_flag),
+                Some(SigFlag::SigAll)
+            ) {
+                return Err(format_err!("unsupported SIG_ALL spending conditions").into());
+            }
+
+            let mut pubkeys = sc.pubkeys().unwrap_or_default();
+            pubkeys.extend(sc.refund_keys().unwrap_or_default());
+            let keys = self
+                .signing_keys
+                .iter()
+                .filter(|k| pubkeys.contains(&k.public_key()))
+                .collect::<Vec<_>>();
+
+            if let SpendingConditions::HTLCConditions { data, .. } = &sc {
+                if let Some(preimage) = &self.preimage {
+                    if htlc_hash(preimage)? != data.to_string() {
+                        return Err(format_err!("htlc preimage unmatched: {}", data).into());
+                    }
+                    p.add_preimage(preimage.clone());
+                } else if p.witness.is_none() && !keys.is_empty() {
+                    // the refund signatures are in the HTLC witness also, sign_p2pk creates the P2PK one
+                    p.witness = Some(Witness::HTLCWitness(HTLCWitness {
+                        preimage: String::new(),
+                        signatures: None,
+                    }));
+                }
+            }
+
+            for k in keys {
+                p.sign_p2pk(k.clone())
+                    .map_err(|e| format_err!("p2pk sign failed: {}", e))?;
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cashu::nuts::nut02::Id as KeySetId;
+    use cashu::secret::Secret;
+
+    fn locked_proof(sc: &SpendingConditions) -> Proof {
+        use cashu::nuts::nut10;
+
+        let secret: Secret = nut10::Secret::from(sc.clone()).try_into().unwrap();
+        let keysetid: KeySetId = "009a1f293253e41e".parse().unwrap();
+        Proof::new(
+            1.into(),
+            keysetid,
+            secret,
+            SecretKey::generate().public_key(),
+        )
+    }
+
+    #[test]
+    fn test_p2pk_unlocking() {
+        let key = SecretKey::generate();
+        let other = SecretKey::generate();
+
+        let sc = p2pk_conditions(key.public_key(), None, None, None, None);
+        let mut proofs = vec![locked_proof(&sc)];
+        assert!(spending_conditions(&proofs[0]).is_some());
+
+        assert_eq!(Unlocking::new(vec![other]).apply(&mut proofs).unwrap(), 0);
+        assert!(proofs[0].witness.is_none());
+
+        assert_eq!(Unlocking::new(vec![key]).apply(&mut proofs).unwrap(), 1);
+        assert!(proofs[0].verify_p2pk().is_ok());
+    }
+
+    #[test]
+    fn test_htlc_unlocking() {
+        let preimage =
+            "b5a3a2ac8a3b8e4d8c1d0a5b9e6f7c3d2a1b0c9d8e7f6a5b4c3d2e1f0a9b8c7d".to_owned();
+        let hash = htlc_hash(&preimage).unwrap();
+        let key = SecretKey::generate();
+
+        let sc = htlc_conditions(&hash, None, Some(vec![key.public_key()]), None).unwrap();
+        let mut proofs = vec![locked_proof(&sc)];
+
+        let wrong = Unlocking::new(vec![]).preimage(Some("00".to_owned()));
+        assert!(wrong.apply(&mut proofs).is_err());
+        let wrong = Unlocking::new(vec![]).preimage(Some("00".repeat(32)));
+        assert!(wrong.apply(&mut proofs).is_err());
+
+        let unlocking = Unlocking::new(vec![key]).preimage(Some(preimage.clone()));
+        assert_eq!(unlocking.apply(&mut proofs).unwrap(), 1);
+        match &proofs[0].witness {
+            Some(Witness::HTLCWitness(w)) => assert_eq!(w.preimage, preimage),
+            w => panic!("{:?}", w),
+        }
+    }
+
+    #[test]
+    fn test_htlc_refund_unlocking() {
+        let hash = htlc_hash(&"00".repeat(32)).unwrap();
+        let key = SecretKey::generate();
+        let refund = SecretKey::generate();
+
+        // the locktime passed, the refund key signs without the preimage
+        let sc = htlc_conditions(
+            &hash,
+            Some(1),
+            Some(vec![key.public_key()]),
+            Some(vec![refund.public_key()]),
+        )
+        .unwrap();
+        let mut proofs = vec![locked_proof(&sc)];
+
+        assert_eq!(Unlocking::new(vec![refund]).apply(&mut proofs).unwrap(), 1);
+        match &proofs[0].witness {
+            Some(Witness::HTLCWitness(w)) => {
+                assert!(w.preimage.is_empty());
+                assert_eq!(w.signatures.as_ref().map(|s| s.len()), Some(1));
+            }
+            w => panic!("{:?}", w),
+        }
+    }
+
+    #[test]
+    fn test_htlc_hash() {
+        // sha256 of the 32 zero bytes, not of the hex string
+        assert_eq!(
+            htlc_hash(&"00".repeat(32)).unwrap(),
+            "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925"
+        );
+        assert!(htlc_hash("00").is_err());
+        assert!(htlc_hash("zz").is_err());
+    }
+}
@@ -4,6 +4,7 @@ pub use bip39::Mnemonic;
 use bitcoin::bip32::ExtendedPrivKey;
 use cashu::nuts::nut02::Id as KeySetId;
 use cashu::nuts::nut02::KeySet;
+use cashu::nuts::nut02::KeySetVersion;
 use cashu::nuts::BlindedMessage;
 use cashu::nuts::PreMint;
 use cashu::Amount;
@@ -44,6 +45,7 @@ use crate::types::unixtime_ms;
 use std::convert::Infallible;
 use std::sync::Arc;
 
+use super::SpendingConditions;
 use super::CURRENCY_UNIT_SAT;
 
 #[derive(Debug, Default)]
@@ -63,6 +65,10 @@ impl Counter {
     pub fn keyset<'s, 'l: 's>(&'s self, keysets: &'l [KeySet]) -> &'l KeySet {
         &keysets[self.keysetidx]
     }
+    // none if the keyset added by refreshing after the keysets cloned
+    pub fn keyset_optional<'s, 'l: 's>(&'s self, keysets: &'l [KeySet]) -> Option<&'l KeySet> {
+        keysets.get(self.keysetidx)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -93,6 +99,51 @@ impl MnemonicInfo {
     // }
 }
 
+/// NUT-13 keyset_id_int = int.from_bytes(keyset_id_bytes, "big") % (2^31 - 1),
+/// the legacy base64 keyset id is decoded as bytes like nutshell
+pub fn keyset_id_int(keysetid: &KeySetId) -> anyhow::Result<u32> {
+    use base64::{engine::general_purpose, Engine};
+
+    let id = keysetid.to_string();
+    let bytes = match keysetid.version {
+        KeySetVersion::VersionBs => general_purpose::STANDARD.decode(&id)?,
+        _ => hex::decode(&id)?,
+    };
+
+    let m = 2u64.pow(31) - 1;
+    let int = bytes.iter().fold(0u64, |r, b| (r * 256 + *b as u64) % m);
+    Ok(int as u32)
+}
+
+/// m / 129372' / 0' / keyset_k_int' / counter' / secret||r
+fn derive_secret(
+    mi: &MnemonicInfo,
+    keysetid: KeySetId,
+    counter: u64,
+) -> anyhow::Result<(cashu::secret::Secret, cashu::nuts::nut01::SecretKey)> {
+    use bitcoin::bip32::{ChildNumber, DerivationPath};
+    use cashu::nuts::nut01::SecretKey;
+    use cashu::secret::Secret;
+    use cashu::SECP256K1;
+
+    if keysetid.version != KeySetVersion::VersionBs {
+        let secret = Secret::from_xpriv(mi.key, keysetid, counter as _)?;
+        let r = SecretKey::from_xpriv(mi.key, keysetid, counter as _)?;
+        return Ok((secret, r));
+    }
+
+    let path: DerivationPath =
+        format!("m/129372'/0'/{}'/{}'", keyset_id_int(&keysetid)?, counter).parse()?;
+    let xpriv = mi.key.derive_priv(&SECP256K1, &path)?;
+
+    let secret = xpriv.derive_priv(&SECP256K1, &[ChildNumber::from_normal_idx(0)?])?;
+    let r = xpriv.derive_priv(&SECP256K1, &[ChildNumber::from_normal_idx(1)?])?;
+
+    let secret = Secret::new(hex::encode(secret.private_key.secret_bytes()));
+    let r = SecretKey::from(r.private_key);
+    Ok((secret, r))
+}
+
 /// m / 129372' / 0' / keyset_k_int' / counter' / secret||r
 /// m / 129372' / 0'
 fn get_keys(mnemonic: &Mnemonic) -> anyhow::Result<(ExtendedPrivKey, String)> {
@@ -136,13 +187,44 @@ impl ManagerBox {
             let lock = self.manager.as_ref().unwrap().lock().await;
             lock.counters
                 .iter()
-                .map(|c| c.keyset(keysets))
+                .filter_map(|c| c.keyset_optional(keysets))
                 .find(|k| k.unit.as_str() == unit)
         }
         .ok_or_else(|| Error::Custom(format_err!("counters not find suitable keyset")))?;
 
         Ok(ks)
     }
+    /// add the counters for new keysets and sort by the active flags
+    pub async fn update_keysets(&self, keysets: &[KeySet], active: impl Fn(&KeySetId) -> bool) {
+        if let Some(lock) = self.manager.as_ref() {
+            let mut lock = lock.lock().await;
+            lock.update_keysets(keysets, active);
+        }
+    }
+    /// move the counter of the keyset to the recovered one if behind, returns the record to store
+    pub async fn advance(
+        &self,
+        keysetid: &KeySetId,
+        keysets: &[KeySet],
+        counter: u64,
+    ) -> Option<Record> {
+        let mut lock = self.manager.as_ref()?.lock().await;
+        let pubkey = lock.mnemonic.as_ref()?.pubkey.to_owned();
+        let c = lock
+            .counters
+            .iter_mut()
+            .find(|c| c.keyset_optional(keysets).map(|k| &k.id) == Some(keysetid))?;
+        if c.record.counter >= counter {
+            return None;
+        }
+
+        c.record.counter = counter;
+        c.state = c.state.max(counter);
+        if c.record.pubkey.is_empty() {
+            c.record.pubkey = pubkey;
+        }
+        Some(c.record.clone())
+    }
     pub async fn maybe_lock<'s>(&'s self) -> ManagerGuard {
         let mut guard = None;
         if let Some(lock) = self.manager.as_ref() {
@@ -248,6 +330,39 @@ impl Manager {
         self
     }
 
+    /// the counters of active keysets first, the keysets without info should be treated as active
+    pub fn actives(mut self, keysets: &[KeySet], active: impl Fn(&KeySetId) -> bool) -> Self {
+        self.sort_by_active(keysets, active);
+        self
+    }
+
+    fn sort_by_active(&mut self, keysets: &[KeySet], active: impl Fn(&KeySetId) -> bool) {
+        // stable, and the legacy keysets are the last
+        self.counters
+            .sort_by_key(|c| match c.keyset_optional(keysets) {
+                Some(k) => (!active(&k.id), k.id.version == KeySetVersion::VersionBs),
+                None => (true, true),
+            });
+    }
+
+    /// keysets only append, so the index of counters are still valid
+    pub fn update_keysets(&mut self, keysets: &[KeySet], active: impl Fn(&KeySetId) -> bool) {
+        for ks in keysets {
+            let has = self
+                .counters
+                .iter()
+                .any(|c| c.keyset_optional(keysets).map(|k| k.id == ks.id) == Some(true));
+            if !has {
+                let r = Record::new(self.mint_url.as_str(), ks.id.to_string(), None);
+                if let Some(c) = r.to_counter(keysets) {
+                    self.counters.push(c);
+                }
+            }
+        }
+
+        self.sort_by_active(keysets, active);
+    }
+
     // lock for write state only success use the counter
     pub fn start_count<'s, 'l: 's>(
         &'s mut self,
@@ -258,7 +373,7 @@ impl Manager {
         let counter = self
             .counters
             .iter_mut()
-            .find(|c| c.keyset(keysets).unit.as_str() == unit)
+            .find(|c| c.keyset_optional(keysets).map(|k| k.unit.as_str()) == Some(unit))
             .ok_or_else(|| format_err!("counters not find suitable keyset"))?;
 
         let mc = ManagerCounter {
@@ -297,6 +412,11 @@ impl<'a> ManagerCounter<'a> {
         self.counter.record.counter
     }
 
+    /// the counters [before, now) used for the outputs, None without mnemonic
+    pub fn range(&self) -> Option<(u64, u64)> {
+        self.mnemonic.map(|_| (self.before(), self.now()))
+    }
+
     pub fn record(&self) -> &Record {
         &self.counter.record
     }
@@ -348,8 +468,7 @@ impl<'a> ManagerCounter<'a> {
         let secret;
         let blinding_factor;
         if let Some(mi) = &self.mnemonic {
-            secret = Secret::from_xpriv(mi.key, keyset.id, count as _)?;
-            blinding_factor = SecretKey::from_xpriv(mi.key, keyset.id, count as _)?;
+            (secret, blinding_factor) = derive_secret(mi, keyset.id, count)?;
         } else {
             secret = Secret::generate();
             blinding_factor = SecretKey::generate();
@@ -381,6 +500,44 @@ impl<'a> ManagerCounter<'a> {
 
         Ok(pre_mint)
     }
+
+    /// NUT-10 well-known secret can't derive from mnemonic, so the counter is unused
+    pub fn generate_with_conditions(
+        &self,
+        amount: Amount,
+        conditions: &SpendingConditions,
+    ) -> anyhow::Result<PreMint> {
+        use cashu::dhke::blind_message;
+        use cashu::nuts::nut01::SecretKey;
+        use cashu::nuts::nut10;
+        use cashu::secret::Secret;
+
+        let keyset = self.keyset;
+
+        let secret: Secret = nut10::Secret::from(conditions.clone()).try_into()?;
+        let blinding_factor = SecretKey::generate();
+
+        debug!(
+            "{} {} {} {}",
+            self.counter.record.mint,
+            amount,
+            keyset.id,
+            secret.as_str(),
+        );
+
+        let (blinded, r) = blind_message(&secret.to_bytes(), Some(blinding_factor))?;
+
+        let blinded_message = BlindedMessage::new(amount, keyset.id, blinded);
+
+        let pre_mint = PreMint {
+            blinded_message,
+            secret,
+            r,
+            amount,
+        };
+
+        Ok(pre_mint)
+    }
 }
 
 pub type RecordStoreFake = ();
@@ -394,6 +551,12 @@ pub trait RecordStore {
     async fn add_record(&self, record: &Record) -> Result<(), Self::Error>;
     async fn delete_records(&self, mint_url: &Url) -> Result<(), Self::Error>;
     async fn get_records(&self, mint_url: &Url, pubkey: &str) -> Result<Vec<Record>, Self::Error>;
+    // the outputs and counters [start, end) going to mint, journal them if want to recover after crash
+    async fn add_outputs(
+        &self,
+        outputs: &[PreMint],
+        counters: Option<(u64, u64)>,
+    ) -> Result<(), Self::Error>;
 }
 
 #[async_trait]
@@ -412,4 +575,27 @@ impl RecordStore for () {
     ) -> Result<Vec<Record>, Self::Error> {
         Ok(vec![])
     }
+    async fn add_outputs(
+        &self,
+        _outputs: &[PreMint],
+        _counters: Option<(u64, u64)>,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyset_id_int() {
+        // https://github.com/cashubtc/nuts/blob/main/tests/13-tests.md
+        let id: KeySetId = "009a1f293253e41e".parse().unwrap();
+        assert_eq!(keyset_id_int(&id).unwrap(), 864559728);
+
+        let id: KeySetId = "I2yN+iRYfkzT".parse().unwrap();
+        assert_eq!(id.version, KeySetVersion::VersionBs);
+        assert_eq!(keyset_id_int(&id).unwrap(), 832192937);
+    }
 }
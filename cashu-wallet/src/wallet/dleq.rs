@@ -0,0 +1,117 @@
+use cashu::nuts::nut01::{Keys, PublicKey};
+use cashu::nuts::{BlindSignature, Proof};
+
+use super::AmountHelper;
+use super::Error;
+
+/// NUT-12 DLEQ: https://github.com/cashubtc/nuts/blob/main/12.md
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DleqPolicy {
+    /// the signatures and proofs without DLEQ are rejected, the mints not support NUT-12 are refused before spending
+    /// the signatures of the requests committed by mint are recovered with the failure logged, the melt change is dropped
+    Required,
+    /// only verify the DLEQ returned by mint or included in tokens
+    #[default]
+    VerifyIfPresent,
+    /// skip the verification
+    Off,
+}
+
+impl DleqPolicy {
+    pub fn is_off(&self) -> bool {
+        *self == Self::Off
+    }
+}
+
+fn amount_key(keys: &Keys, proof: &Proof) -> Result<PublicKey, Error> {
+    let a = keys
+        .amount_key(proof.amount)
+        .ok_or_else(|| format_err!("not found amount key: {}", proof.amount.to_u64()))?;
+    Ok(a.to_owned())
+}
+
+/// verify the mint's BlindSignature(Bob to Alice), mint_key is the keyset key of the amount
+pub fn verify_signature_dleq(
+    policy: DleqPolicy,
+    promise: &BlindSignature,
+    mint_key: PublicKey,
+    blinded_secret: PublicKey,
+) -> Result<(), Error> {
+    if policy.is_off() {
+        return Ok(());
+    }
+
+    if promise.dleq.is_none() {
+        if policy == DleqPolicy::Required {
+            return Err(Error::Dleq(format!(
+                "mint returns signature without dleq: {} {}",
+                promise.keyset_id,
+                promise.amount.to_u64()
+            )));
+        }
+        return Ok(());
+    }
+
+    promise.verify_dleq(mint_key, blinded_secret).map_err(|e| {
+        Error::Dleq(format!(
+            "{} {}: {}",
+            promise.keyset_id,
+            promise.amount.to_u64(),
+            e
+        ))
+    })
+}
+
+/// verify the DLEQ of the proof(Alice to Carol), keys is the keyset's keys of the proof
+pub fn verify_proof_dleq(policy: DleqPolicy, proof: &Proof, keys: &Keys) -> Result<(), Error> {
+    if policy.is_off() {
+        return Ok(());
+    }
+
+    if proof.dleq.is_none() {
+        if policy == DleqPolicy::Required {
+            return Err(Error::Dleq(format!(
+                "proof without dleq: {} {}",
+                proof.keyset_id,
+                proof.amount.to_u64()
+            )));
+        }
+        return Ok(());
+    }
+
+    let a = amount_key(keys, proof)?;
+    proof.verify_dleq(a).map_err(|e| {
+        Error::Dleq(format!(
+            "{} {}: {}",
+            proof.keyset_id,
+            proof.amount.to_u64(),
+            e
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cashu::nuts::nut01::SecretKey;
+    use cashu::nuts::nut02::Id as KeySetId;
+    use cashu::secret::Secret;
+
+    #[test]
+    fn test_policy_without_dleq() {
+        let keysetid: KeySetId = "009a1f293253e41e".parse().unwrap();
+        let proof = Proof::new(
+            1.into(),
+            keysetid,
+            Secret::generate(),
+            SecretKey::generate().public_key(),
+        );
+        let keys = Keys::new(Default::default());
+
+        assert!(verify_proof_dleq(DleqPolicy::Off, &proof, &keys).is_ok());
+        assert!(verify_proof_dleq(DleqPolicy::VerifyIfPresent, &proof, &keys).is_ok());
+
+        let err = verify_proof_dleq(DleqPolicy::Required, &proof, &keys).unwrap_err();
+        assert!(err.is_dleq());
+    }
+}
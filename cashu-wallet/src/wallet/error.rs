@@ -20,6 +20,9 @@ pub enum WalletError {
     Custom(#[from] anyhow::Error),
     #[error("Insufficant Funds")]
     InsufficientFunds,
+    /// NUT-12 DLEQ verification failed
+    #[error("DLEQ verification failed: {0}")]
+    Dleq(String),
     // /// Proofs required
     // #[error("Proofs required in token")]
     // ProofsRequired,
@@ -45,6 +48,41 @@ impl WalletError {
         }
         false
     }
+    /// the mint is unreachable: connect failed or timeout
+    pub fn is_network_error(&self) -> bool {
+        if let WalletError::Client(c) = self {
+            return c.is_network_error();
+        }
+        false
+    }
+    /// the request never reached the mint: connect failed, see ClientError::is_unreachable
+    pub fn is_unreachable(&self) -> bool {
+        if let WalletError::Client(c) = self {
+            return c.is_unreachable();
+        }
+        false
+    }
+    /// the mint error code returned
+    pub fn mint_error_code(&self) -> Option<MintErrorCode> {
+        if let WalletError::Client(c) = self {
+            return c.mint_error_code();
+        }
+        None
+    }
+    /// the same request maybe succeed later, see ClientError::is_retryable
+    pub fn is_retryable(&self) -> bool {
+        if let WalletError::Client(c) = self {
+            return c.is_retryable();
+        }
+        false
+    }
+    /// the mint rejected the request and it never succeed, see ClientError::is_permanent
+    pub fn is_permanent(&self) -> bool {
+        if let WalletError::Client(c) = self {
+            return c.is_permanent();
+        }
+        false
+    }
 }
 
 #[derive(Debug)]
@@ -57,22 +95,66 @@ pub enum ClientError {
     Json(serde_json::Error),
     /// reqwest error
     Reqwest(reqwest::Error),
-    /// mint returns Error: <code, detail/error>
-    Mint(i32, String),
+    /// NUT-17 WebSocket error
+    WebSocket(tokio_tungstenite::tungstenite::Error),
+    /// custom transport error
+    Transport(anyhow::Error),
+    /// the options of client can't reach the mint: the onion mint without proxy, the websocket over proxy or with pins.
+    /// the request is never sent, so it's not a network error
+    Config(String),
+    /// none of the TLS certificates of mint matches the pins: <server name and the pin got>
+    PinMismatch(String),
+    /// mint returns Error: <code, the raw code sent by mint, detail/error>
+    Mint(MintErrorCode, i32, String),
     /// unknown http response
     UnknownResponse(i32, String),
 }
 
 impl ClientError {
     pub fn is_outputs_already_signed_before(&self) -> bool {
-        // 11000 could not verify proofs.
-        // 11000 outputs have already been signed before.
-        if let ClientError::Mint(_c, d) = self {
-            if d.contains("outputs have already been signed before") {
-                return true;
-            }
+        self.mint_error_code()
+            .map(|c| c.is_outputs_already_signed())
+            .unwrap_or_default()
+    }
+    /// the error code if the mint returns Error
+    pub fn mint_error_code(&self) -> Option<MintErrorCode> {
+        match self {
+            ClientError::Mint(c, _, _) => Some(*c),
+            _ => None,
+        }
+    }
+    /// the same request maybe succeed later: the mint is unreachable, busy or the quote is not ready
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::Mint(c, _, _) => c.is_retryable(),
+            // 429 Too Many Requests and the gateway errors
+            ClientError::UnknownResponse(code, _) => matches!(code, 429 | 502 | 503 | 504),
+            _ => self.is_network_error(),
+        }
+    }
+    /// the mint rejected the request by a known NUT error code, retry never succeed
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            ClientError::Mint(c, _, _) => c.is_permanent(),
+            _ => false,
+        }
+    }
+    /// the mint is unreachable: connect failed or timeout
+    pub fn is_network_error(&self) -> bool {
+        match self {
+            ClientError::Reqwest(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+            // the custom transport failed to deliver
+            ClientError::Transport(_) => true,
+            _ => false,
+        }
+    }
+    /// the request never reached the mint: connect refused or the DNS failed.
+    /// the mint maybe committed on the timeouts and the failed transports
+    pub fn is_unreachable(&self) -> bool {
+        match self {
+            ClientError::Reqwest(e) => e.is_connect(),
+            _ => false,
         }
-        false
     }
 }
 
@@ -94,6 +176,12 @@ impl From<reqwest::Error> for ClientError {
     }
 }
 
+impl From<tokio_tungstenite::tungstenite::Error> for ClientError {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> ClientError {
+        Self::WebSocket(e)
+    }
+}
+
 impl std::error::Error for ClientError {}
 
 impl fmt::Display for ClientError {
@@ -104,7 +192,11 @@ impl fmt::Display for ClientError {
             Url(err) => write!(f, "{}", err),
             Json(err) => write!(f, "{}", err),
             Reqwest(err) => write!(f, "{}", err),
-            Mint(code, err) => write!(f, "{} {}", code, err),
+            WebSocket(err) => write!(f, "{}", err),
+            Transport(err) => write!(f, "{}", err),
+            Config(err) => write!(f, "invalid client config: {}", err),
+            PinMismatch(got) => write!(f, "certificate pin mismatch: {}", got),
+            Mint(_, code, err) => write!(f, "{} {}", code, err),
             UnknownResponse(code, body) => {
                 write!(f, "mint returns unknown response(code: {}): {}", code, body)
             }
@@ -131,7 +223,8 @@ impl ClientError {
 
         let detail = json.detail.take().or_else(|| json.error.take());
 
-        let e = Self::Mint(json.code, detail.unwrap_or_else(|| body.to_owned()));
+        let detail = detail.unwrap_or_else(|| body.to_owned());
+        let e = Self::Mint(MintErrorCode::new(json.code, &detail), json.code, detail);
 
         Ok(e)
     }
@@ -150,6 +243,175 @@ impl ClientError {
     }
 }
 
+/// the NUT error codes, https://github.com/cashubtc/nuts/blob/main/error_codes.md
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+//
+#[derive(EnumIs)]
+pub enum MintErrorCode {
+    /// 10002 blinded message of output already signed
+    OutputsAlreadySigned,
+    /// 10003 token could not be verified
+    TokenNotVerified,
+    /// 11001 token is already spent
+    TokenAlreadySpent,
+    /// 11002 transaction is not balanced (inputs != outputs)
+    TransactionUnbalanced,
+    /// 11005 unit in request is not supported
+    UnitUnsupported,
+    /// 11006 amount outside of limit range
+    AmountOutOfLimit,
+    /// 11007 duplicate inputs provided
+    DuplicateInputs,
+    /// 11008 duplicate outputs provided
+    DuplicateOutputs,
+    /// 11009 inputs/outputs of multiple units
+    MultipleUnits,
+    /// 11010 inputs and outputs not of same unit
+    UnitMismatch,
+    /// 11011 amountless invoice is not supported
+    AmountlessInvoiceUnsupported,
+    /// 11012 amount in request does not equal invoice
+    AmountMismatch,
+    /// 12001 keyset is not known
+    KeysetNotFound,
+    /// 12002 keyset is inactive, cannot sign messages
+    KeysetInactive,
+    /// 20001 quote request is not paid
+    QuoteNotPaid,
+    /// 20002 tokens have already been issued for quote
+    TokensAlreadyIssued,
+    /// 20003 minting is disabled
+    MintingDisabled,
+    /// 20004 lightning payment failed
+    LightningPaymentFailed,
+    /// 20005 quote is pending
+    QuotePending,
+    /// 20006 invoice already paid
+    InvoiceAlreadyPaid,
+    /// 20007 quote is expired
+    QuoteExpired,
+    /// 20008 signature for mint request invalid
+    MintSignatureInvalid,
+    /// 20009 pubkey required for mint quote
+    MintPubkeyRequired,
+    /// the code not in NUT, or the mint not follows it
+    Unknown(i32),
+}
+
+impl MintErrorCode {
+    /// the code, the detail is used by the mints not follows the NUT error codes
+    pub fn new(code: i32, detail: &str) -> Self {
+        let c = Self::from_code(code);
+        if c.is_unknown() {
+            return Self::from_detail(detail).unwrap_or(c);
+        }
+        c
+    }
+
+    pub fn from_code(code: i32) -> Self {
+        use MintErrorCode::*;
+
+        match code {
+            10002 => OutputsAlreadySigned,
+            10003 => TokenNotVerified,
+            11001 => TokenAlreadySpent,
+            11002 => TransactionUnbalanced,
+            11005 => UnitUnsupported,
+            11006 => AmountOutOfLimit,
+            11007 => DuplicateInputs,
+            11008 => DuplicateOutputs,
+            11009 => MultipleUnits,
+            11010 => UnitMismatch,
+            11011 => AmountlessInvoiceUnsupported,
+            11012 => AmountMismatch,
+            12001 => KeysetNotFound,
+            12002 => KeysetInactive,
+            20001 => QuoteNotPaid,
+            20002 => TokensAlreadyIssued,
+            20003 => MintingDisabled,
+            20004 => LightningPaymentFailed,
+            20005 => QuotePending,
+            20006 => InvoiceAlreadyPaid,
+            20007 => QuoteExpired,
+            20008 => MintSignatureInvalid,
+            20009 => MintPubkeyRequired,
+            c => Unknown(c),
+        }
+    }
+
+    // the old nutshell returns 0/11000/20000 with the detail
+    fn from_detail(detail: &str) -> Option<Self> {
+        use MintErrorCode::*;
+
+        // https://github.com/cashubtc/nutshell/blob/0.12.3/cashu/core/errors.py#L19 "invoice not paid."
+        // https://github.com/cashubtc/nutshell/blob/0.13.0/cashu/core/errors.py#L78 "Lightning invoice not paid yet."
+        // https://github.com/cashubtc/cashu-feni/blob/master/mint/mint.go#L251C27-L251C60 "Lightning invoice not paid yet."
+        let c = if detail.contains("outputs have already been signed before") {
+            OutputsAlreadySigned
+        } else if detail.contains("not paid") {
+            QuoteNotPaid
+        } else if detail.contains("already spent") {
+            TokenAlreadySpent
+        } else {
+            return None;
+        };
+        Some(c)
+    }
+
+    pub fn code(&self) -> i32 {
+        use MintErrorCode::*;
+
+        match self {
+            OutputsAlreadySigned => 10002,
+            TokenNotVerified => 10003,
+            TokenAlreadySpent => 11001,
+            TransactionUnbalanced => 11002,
+            UnitUnsupported => 11005,
+            AmountOutOfLimit => 11006,
+            DuplicateInputs => 11007,
+            DuplicateOutputs => 11008,
+            MultipleUnits => 11009,
+            UnitMismatch => 11010,
+            AmountlessInvoiceUnsupported => 11011,
+            AmountMismatch => 11012,
+            KeysetNotFound => 12001,
+            KeysetInactive => 12002,
+            QuoteNotPaid => 20001,
+            TokensAlreadyIssued => 20002,
+            MintingDisabled => 20003,
+            LightningPaymentFailed => 20004,
+            QuotePending => 20005,
+            InvoiceAlreadyPaid => 20006,
+            QuoteExpired => 20007,
+            MintSignatureInvalid => 20008,
+            MintPubkeyRequired => 20009,
+            Unknown(c) => *c,
+        }
+    }
+
+    /// the quote is not ready: the invoice not paid or the payment in flight
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::QuoteNotPaid | Self::QuotePending)
+    }
+
+    /// all the known codes except the retryable
+    pub fn is_permanent(&self) -> bool {
+        !self.is_retryable() && !self.is_unknown()
+    }
+}
+
+impl From<i32> for MintErrorCode {
+    fn from(code: i32) -> Self {
+        Self::from_code(code)
+    }
+}
+
+impl fmt::Display for MintErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -159,11 +421,12 @@ mod tests {
         let input = "{\"code\":0,\"error\":\"Lightning invoice not paid yet.\"}";
         let data = ClientError::from_body(input)?;
         let data = match data {
-            Mint(code, desc) => (code, desc),
+            Mint(code, _, desc) => (code, desc),
             _ => panic!("{}", data),
         };
 
-        assert_eq!(data.0, 0);
+        assert_eq!(data.0, MintErrorCode::QuoteNotPaid);
+        assert_eq!(data.0.code(), 20001);
         assert_eq!(data.1, "Lightning invoice not paid yet.");
         Ok(())
     }
@@ -174,11 +437,45 @@ mod tests {
 
         let data = ClientError::from_body(input)?;
         let data = match data {
-            Mint(code, desc) => (code, desc),
+            Mint(code, _, desc) => (code, desc),
             _ => panic!("{}", data),
         };
-        assert_eq!(data.0, 11001);
+        assert_eq!(data.0, MintErrorCode::TokenAlreadySpent);
         assert_eq!(data.1, "Token already spent.");
         Ok(())
     }
+
+    #[test]
+    fn test_mint_error_code() -> anyhow::Result<()> {
+        let input = r#"{"detail":"outputs have already been signed before.","code":11000}"#;
+        let e = ClientError::from_body(input)?;
+        assert!(e.is_outputs_already_signed_before());
+        assert!(e.is_permanent());
+        // the code sent by mint is kept
+        assert_eq!(
+            e.to_string(),
+            "11000 outputs have already been signed before."
+        );
+
+        let input = r#"{"detail":"Quote is pending.","code":20005}"#;
+        let e = ClientError::from_body(input)?;
+        assert_eq!(e.mint_error_code(), Some(MintErrorCode::QuotePending));
+        assert!(e.is_retryable());
+        assert!(!e.is_permanent());
+
+        let input = r#"{"detail":"keyset 009a1f293253e41e is not known.","code":12001}"#;
+        let e = ClientError::from_body(input)?;
+        assert!(e.mint_error_code().unwrap().is_keyset_not_found());
+        assert_eq!(e.to_string(), "12001 keyset 009a1f293253e41e is not known.");
+
+        let input = r#"{"detail":"something wrong","code":0}"#;
+        let e = ClientError::from_body(input)?;
+        assert_eq!(e.mint_error_code(), Some(MintErrorCode::Unknown(0)));
+        assert!(!e.is_retryable());
+        assert!(!e.is_permanent());
+
+        let e = ClientError::UnknownResponse(429, "Rate limit exceeded.".to_owned());
+        assert!(e.is_retryable());
+        Ok(())
+    }
 }
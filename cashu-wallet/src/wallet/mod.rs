@@ -1,4 +1,5 @@
 use crate::types::MintInfo;
+use crate::types::MintKeySet;
 use cashu::dhke::unblind_message;
 use cashu::nuts::nut01::Keys;
 use cashu::nuts::nut02::KeySet;
@@ -13,9 +14,14 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 mod client;
+mod conditions;
 mod counter;
+mod dleq;
 mod error;
+mod tls;
 mod token;
+mod transport;
+mod ws;
 
 pub use cashu::nuts::{PreMintSecrets, Proof, Proofs};
 pub use token::{
@@ -24,7 +30,11 @@ pub use token::{
     //
     MintUrl,
     //
+    PaymentRequest,
+    PaymentRequestPayload,
+    //
     ProofExtended,
+    ProofState,
     ProofsExtended,
     ProofsHelper,
     //
@@ -33,11 +43,18 @@ pub use token::{
     TokenV3Extened,
     TokenV3Generic,
     TokenV4,
+    Transport,
+    TransportKind,
 };
 
 pub use client::*;
+pub use conditions::*;
 pub use counter::*;
+pub use dleq::*;
 pub use error::*;
+pub use tls::{spki_der, spki_pin};
+pub use transport::*;
+pub use ws::*;
 
 /// helper for Amount
 pub trait AmountHelper {
@@ -91,8 +108,11 @@ impl<'a> Serialize for BlindedMessages<'a> {
 pub struct Wallet {
     pub(super) client: MintClient,
     pub(super) keysets: Vec<KeySet>,
+    // NUT-02 keysets with input_fee_ppk
+    pub(super) keyset_infos: Vec<KeySetInfo>,
     pub(super) info: MintInfo,
     pub(super) counter: ManagerBox,
+    pub(super) dleq: DleqPolicy,
 }
 
 impl Wallet {
@@ -108,19 +128,59 @@ impl Wallet {
             let ks = client.get_keys(None).await?;
             keysets = Some(ks.keysets);
         }
+        // the fees and active flags
+        let keyset_infos = client.get_keysetids().await?.keysets;
 
         if info.is_none() {
             let mi = client.get_info().await?;
             info = Some(mi);
         }
 
-        // old base64 keysetid can't convert as u64, or remove?
-        keysets
-            .as_mut()
-            .map(|ks| ks.retain(|k| k.id.version != nut02::KeySetVersion::VersionBs));
-        // .map(|ks| ks.sort_by_key(|k| k.id.version != nut02::KeySetVersion::VersionBs));
+        Self::with_keysets(
+            client,
+            keysets.unwrap(),
+            keyset_infos,
+            info.unwrap(),
+            mnemonic,
+            store,
+            records,
+        )
+        .await
+    }
+
+    /// build the wallet from the keysets and mint info stored in database without network
+    pub async fn from_stored(
+        client: MintClient,
+        keysets: Vec<MintKeySet>,
+        info: MintInfo,
+        mnemonic: Option<Arc<MnemonicInfo>>,
+        store: impl RecordStore,
+        records: Option<Vec<Record>>,
+    ) -> Result<Self, Error> {
+        let keyset_infos = keysets.iter().filter_map(|ks| ks.info.clone()).collect();
+        let keysets = keysets.into_iter().map(|ks| ks.keyset).collect();
 
-        let keysets = keysets.unwrap();
+        Self::with_keysets(
+            client,
+            keysets,
+            keyset_infos,
+            info,
+            mnemonic,
+            store,
+            records,
+        )
+        .await
+    }
+
+    pub async fn with_keysets(
+        client: MintClient,
+        keysets: Vec<KeySet>,
+        keyset_infos: Vec<KeySetInfo>,
+        info: MintInfo,
+        mnemonic: Option<Arc<MnemonicInfo>>,
+        store: impl RecordStore,
+        records: Option<Vec<Record>>,
+    ) -> Result<Self, Error> {
         if keysets.len() < 1 {
             return Err(format_err!("empty keysets").into());
         }
@@ -128,8 +188,10 @@ impl Wallet {
         let mut this = Self {
             client,
             keysets,
-            info: info.unwrap(),
+            keyset_infos,
+            info,
             counter: Default::default(),
+            dleq: Default::default(),
         };
 
         this.update_mnmonic(mnemonic, store, records).await?;
@@ -157,7 +219,8 @@ impl Wallet {
 
         let counter = Manager::new(self.client.url())
             .mnemonic(mnemonic)
-            .records(records, &self.keysets);
+            .records(records, &self.keysets)
+            .actives(&self.keysets, |id| self.is_active_keyset(id));
 
         let has = std::mem::replace(
             &mut self.counter.manager,
@@ -167,10 +230,172 @@ impl Wallet {
         Ok(has.is_some())
     }
 
+    /// the counters used by the journaled outputs, see ManagerBox::advance
+    pub async fn advance_counter(&self, keysetid: &KeySetId, counter: u64) -> Option<Record> {
+        self.counter.advance(keysetid, &self.keysets, counter).await
+    }
+
     pub fn client(&self) -> &MintClient {
         &self.client
     }
 
+    pub fn keyset_infos(&self) -> &[KeySetInfo] {
+        &self.keyset_infos
+    }
+
+    pub fn keysets(&self) -> &[KeySet] {
+        &self.keysets
+    }
+
+    pub fn info(&self) -> &MintInfo {
+        &self.info
+    }
+
+    pub fn set_info(&mut self, info: MintInfo) {
+        self.info = info;
+    }
+
+    /// NUT-02 active flag of the keyset, the keyset without info is treated as active
+    pub fn is_active_keyset(&self, keysetid: &Id) -> bool {
+        self.keyset_infos
+            .iter()
+            .find(|ks| &ks.id == keysetid)
+            .map(|ks| ks.active)
+            .unwrap_or(true)
+    }
+
+    /// fetch the keysets of mint: add the new active keysets and update the active flags and fees,
+    /// the new outputs use the active keyset after refreshed. returns true if any keyset added
+    pub async fn refresh_keysets(&mut self) -> Result<bool, Error> {
+        let infos = self.client.get_keysetids().await?.keysets;
+
+        let mut added = false;
+        for info in infos.iter().filter(|ks| ks.active) {
+            if self.keysets.iter().any(|ks| ks.id == info.id) {
+                continue;
+            }
+
+            let keys = self.client.get_keys(Some(&info.id.to_string())).await?;
+            for ks in keys.keysets.into_iter().filter(|ks| ks.id == info.id) {
+                self.keysets.push(ks);
+                added = true;
+            }
+        }
+        self.keyset_infos = infos;
+
+        self.counter
+            .update_keysets(&self.keysets, |id| self.is_active_keyset(id))
+            .await;
+
+        Ok(added)
+    }
+
+    /// NUT-02 input_fee_ppk of the keyset, 0 if unknown
+    pub fn input_fee_ppk(&self, keysetid: &Id) -> u64 {
+        self.keyset_infos
+            .iter()
+            .find(|ks| &ks.id == keysetid)
+            .map(|ks| ks.input_fee_ppk)
+            .unwrap_or_default()
+    }
+
+    /// NUT-02 fees = ceil(sum(input_fee_ppk) / 1000)
+    pub fn input_fee(&self, proofs: impl ProofsHelper) -> Amount {
+        let ppk = proofs
+            .as_slice()
+            .iter()
+            .map(|p| self.input_fee_ppk(&p.as_ref().keyset_id))
+            .sum::<u64>();
+        fee_from_ppk(ppk)
+    }
+
+    /// the input fee of the proofs that would be created for the amount by the keyset of unit
+    pub async fn estimate_input_fee(
+        &self,
+        amount: Amount,
+        unit: Option<&str>,
+    ) -> Result<Amount, Error> {
+        let keyset = self.keyset0(unit).await?;
+        let ppk = self.input_fee_ppk(&keyset.id) * amount.split().len() as u64;
+        Ok(fee_from_ppk(ppk))
+    }
+
+    pub fn dleq_policy(&self) -> DleqPolicy {
+        self.dleq
+    }
+
+    pub fn set_dleq_policy(&mut self, policy: DleqPolicy) {
+        self.dleq = policy;
+    }
+
+    /// the required DLEQ of signatures is verified after the mint spent the inputs,
+    /// refuse the mint not supports NUT-12 before calling it
+    pub fn check_dleq_supported(&self) -> Result<(), Error> {
+        if self.dleq == DleqPolicy::Required && !self.info.nuts.nut12.supported {
+            return Err(Error::Dleq(format!(
+                "{} not supports NUT-12, the signatures without DLEQ are rejected",
+                self.client.url().as_str()
+            )));
+        }
+        Ok(())
+    }
+
+    /// verify the DLEQ included in the proofs by the cached keysets without contacting the mint,
+    /// the DLEQ is required ignore the policy
+    pub fn verify_proofs_dleq_offline(&self, proofs: impl ProofsHelper) -> Result<(), Error> {
+        for p in proofs.as_slice() {
+            let p = p.as_ref();
+
+            let keyset = self
+                .keysets
+                .iter()
+                .find(|ks| ks.id == p.keyset_id)
+                .ok_or_else(|| Error::Dleq(format!("not found cached keyset: {}", p.keyset_id)))?;
+
+            verify_proof_dleq(DleqPolicy::Required, p, &keyset.keys)?;
+        }
+
+        Ok(())
+    }
+
+    /// verify the DLEQ included in the proofs, the keys of unknown keyset will fetch from mint
+    pub async fn verify_proofs_dleq(&self, proofs: impl ProofsHelper) -> Result<(), Error> {
+        if self.dleq.is_off() {
+            return Ok(());
+        }
+
+        let mut fetched: Vec<KeySet> = vec![];
+        for p in proofs.as_slice() {
+            let p = p.as_ref();
+
+            let known = self
+                .keysets
+                .iter()
+                .chain(fetched.iter())
+                .any(|ks| ks.id == p.keyset_id);
+
+            if !known {
+                let ks = self.client.get_keys(Some(&p.keyset_id.to_string())).await?;
+                let ks = ks
+                    .keysets
+                    .into_iter()
+                    .find(|ks| ks.id == p.keyset_id)
+                    .ok_or_else(|| format_err!("not found keyset: {}", p.keyset_id))?;
+                fetched.push(ks);
+            }
+
+            let keyset = self
+                .keysets
+                .iter()
+                .chain(fetched.iter())
+                .find(|ks| ks.id == p.keyset_id)
+                .unwrap();
+            verify_proof_dleq(self.dleq, p, &keyset.keys)?;
+        }
+
+        Ok(())
+    }
+
     pub async fn keyset0(&self, unit: Option<&str>) -> Result<&KeySet, Error> {
         self.counter.keyset0(unit, &self.keysets).await
     }
@@ -195,17 +420,38 @@ impl Wallet {
             .await?)
     }
 
-    /// Mint Proofs
+    /// Check mint quote state: unpaid, paid, issued
+    pub async fn mint_quote_state(
+        &self,
+        quote: &str,
+        method: Option<&str>,
+    ) -> Result<nut04::MintQuoteBolt11Response, Error> {
+        let resp = self
+            .client
+            .mint_quote_state(quote, method.unwrap_or(PAYMEN_METHOD_BOLT11))
+            .await?;
+        Ok(resp)
+    }
+
+    /// Mint Proofs, the outputs are passed to store.add_outputs before calling mint
     pub async fn mint<'s, 'l: 's>(
         &'l self,
         amount: Amount,
         counter: &'s mut ManagerCounter<'l>,
         hash: &'l str,
         method: Option<&'l str>,
+        store: impl RecordStore,
     ) -> Result<ProofsExtended, Error> {
+        self.check_dleq_supported()?;
+
         let outputs = PreMintSecretsHyper::split_amount(amount, counter)?;
         let blinds = BlindedMessages::new(&outputs);
 
+        store
+            .add_outputs(&outputs, counter.range())
+            .await
+            .map_err(|e| Error::Custom(e.into()))?;
+
         let mint_res = self
             .client
             .mint(&blinds, hash, method.unwrap_or(PAYMEN_METHOD_BOLT11))
@@ -215,6 +461,7 @@ impl Wallet {
             outputs,
             mint_res.signatures,
             &counter.keyset().keys,
+            self.dleq,
         )?;
         Ok(ps.into_extended_with_unit(Some(counter.keyset().unit.as_str())))
     }
@@ -226,12 +473,12 @@ impl Wallet {
         unit: Option<&str>,
         hash: &str,
         method: Option<&str>,
-        store: impl RecordStore,
+        store: impl RecordStore + Copy,
     ) -> Result<TokenV3Extened, Error> {
         let mut lock = self.counter.maybe_lock().await;
         let mut counter = lock.start_count(unit, &self.keysets)?;
 
-        let proofs = self.mint(amount, &mut counter, hash, method).await?;
+        let proofs = self.mint(amount, &mut counter, hash, method, store).await?;
         counter.commit(store).await?;
 
         let token = TokenV3Generic::new(
@@ -255,39 +502,7 @@ impl Wallet {
         &self,
         proofs: impl ProofsHelper,
     ) -> Result<nut07::CheckStateResponse, Error> {
-        let proofs = proofs.as_slice();
-        let mut ys = Vec::with_capacity(proofs.len());
-        for p in proofs {
-            let p = p.as_ref();
-            // println!("{:?}", p);
-            let ss = p.secret.as_str();
-
-            // let secret = if ss.len() == 64 {
-            //     ss.parse::<SecretKey>()
-            // } else {
-            //     use base64::{alphabet, engine::general_purpose};
-
-            //     let decode_config = general_purpose::GeneralPurposeConfig::new()
-            //         .with_decode_padding_mode(base64::engine::DecodePaddingMode::Indifferent);
-            //     let decoded =
-            //         general_purpose::GeneralPurpose::new(&alphabet::STANDARD, decode_config)
-            //             .decode(ss)
-            //             .map_err(|e| Error::Custom(e.into()))?;
-            //     SecretKey::from_slice(&decoded)
-            // }
-            // .map_err(|e| Error::Custom(e.into()))?;
-            // let s = secret.to_string();
-
-            // base64 secret string is compat also..
-            let y = cashu::dhke::hash_to_curve(ss.as_bytes())?;
-            // let y = cashu::dhke::hash_to_curve(secret.as_secret_bytes())?;
-            // let y = hash_to_curve_pre1(secret.as_secret_bytes())?;
-
-            // println!("p: {}", secret.public_key());
-            // println!("y: {}", y);
-            ys.push(y);
-        }
-
+        let ys = proofs_ys(proofs)?;
         let status = self.client.check_state(&ys).await?;
 
         Ok(status)
@@ -316,6 +531,18 @@ impl Wallet {
         token: &MintProofs,
         unit: Option<&str>,
         store: impl RecordStore,
+    ) -> Result<ProofsExtended, Error> {
+        self.receive_token_unlocking(token, unit, &Default::default(), store)
+            .await
+    }
+
+    /// Receive token belongs this url, the locked proofs unlocked by the witness materials before swap
+    pub async fn receive_token_unlocking(
+        &self,
+        token: &MintProofs,
+        unit: Option<&str>,
+        unlocking: &Unlocking,
+        store: impl RecordStore,
     ) -> Result<ProofsExtended, Error> {
         let mut ps = vec![];
 
@@ -332,6 +559,19 @@ impl Wallet {
             return Ok(ps);
         }
 
+        self.check_dleq_supported()?;
+        self.verify_proofs_dleq(&token.proofs).await?;
+
+        let mut inputs = token.proofs.clone();
+        if !unlocking.is_empty() {
+            unlocking.apply(&mut inputs)?;
+        }
+
+        let fee = self.input_fee(&token.proofs);
+        if fee >= amount {
+            return Err(format_err!("the input fee {} exceeds the amount {}", fee, amount).into());
+        }
+
         let mut lock = self.counter.maybe_lock().await;
         let mut counter = lock.start_count(unit, &self.keysets)?;
 
@@ -343,10 +583,12 @@ impl Wallet {
 
         let (outputs, swap_response) = try_to_call_swap(
             self.client(),
-            &token.proofs,
+            &inputs,
             amount,
             0.into(),
+            fee,
             0.into(),
+            None,
             &mut counter,
             store,
         )
@@ -361,6 +603,7 @@ impl Wallet {
             outputs.messages,
             swap_response.signatures,
             &counter.keyset().keys,
+            self.dleq,
         )?;
 
         Ok(ps.into_extended_with_unit(Some(counter.keyset().unit.as_str())))
@@ -385,12 +628,47 @@ impl Wallet {
         denomination: Amount,
         currency_unit: Option<&str>,
         store: impl RecordStore,
+    ) -> Result<SplitProofsExtended, Error> {
+        self.send_full(amount, proofs, denomination, None, currency_unit, store)
+            .await
+    }
+
+    /// Send: the send proofs locked by the NUT-10 spending conditions, it always need swap
+    pub async fn send_with_conditions(
+        &self,
+        amount: Amount,
+        proofs: impl ProofsHelper + Copy,
+        conditions: &SpendingConditions,
+        currency_unit: Option<&str>,
+        store: impl RecordStore,
+    ) -> Result<SplitProofsExtended, Error> {
+        self.send_full(
+            amount,
+            proofs,
+            0.into(),
+            Some(conditions),
+            currency_unit,
+            store,
+        )
+        .await
+    }
+
+    async fn send_full(
+        &self,
+        amount: Amount,
+        proofs: impl ProofsHelper + Copy,
+        denomination: Amount,
+        conditions: Option<&SpendingConditions>,
+        currency_unit: Option<&str>,
+        store: impl RecordStore,
     ) -> Result<SplitProofsExtended, Error> {
         let amount_available = proofs.sum();
+        let fee = self.input_fee(proofs);
 
-        if amount_available < amount {
+        if amount_available < amount + fee {
             return Err(Error::insufficant_funds());
         }
+        self.check_dleq_supported()?;
 
         // no need to split, buts could use to merge many small proofs to large 2^N proofs
         // if amount_available.eq(&amount)
@@ -419,7 +697,9 @@ impl Wallet {
             proofs,
             amount_to_keep,
             amount,
+            fee,
             denomination,
+            conditions,
             &mut counter,
             store,
         )
@@ -433,6 +713,7 @@ impl Wallet {
             outputs.messages,
             swap_response.signatures,
             &counter.keyset().keys,
+            self.dleq,
         )?;
 
         let split = SplitProofsExtended::new(
@@ -451,6 +732,7 @@ impl Wallet {
         invoice: &Bolt11Invoice,
         unit: Option<&str>,
         method: Option<&str>,
+        amount_msat: Option<u64>,
     ) -> Result<nut05::MeltQuoteBolt11Response, Error> {
         let resp = self
             .client
@@ -458,11 +740,25 @@ impl Wallet {
                 invoice,
                 unit.unwrap_or(CURRENCY_UNIT_SAT),
                 method.unwrap_or(PAYMEN_METHOD_BOLT11),
+                amount_msat,
             )
             .await?;
         Ok(resp)
     }
 
+    /// Check melt quote state: unpaid, pending, paid
+    pub async fn melt_quote_state(
+        &self,
+        quote: &str,
+        method: Option<&str>,
+    ) -> Result<MeltResponse, Error> {
+        let resp = self
+            .client
+            .melt_quote_state(quote, method.unwrap_or(PAYMEN_METHOD_BOLT11))
+            .await?;
+        Ok(resp)
+    }
+
     pub async fn melt(
         &self,
         quote: &str,
@@ -472,8 +768,18 @@ impl Wallet {
         method: Option<&str>,
         store: impl RecordStore,
     ) -> Result<Melted, Error> {
-        let keyset0 = self.keyset0(unit).await?;
+        let outputs = self.melt_blank_outputs(fee_reserve, unit, store).await?;
+        self.melt_with_outputs(quote, proofs, outputs, method).await
+    }
 
+    /// NUT-08 blank outputs for the change of fee_reserve, the counter is committed,
+    /// the caller should persist them before melt if want to resume the in-flight payment
+    pub async fn melt_blank_outputs(
+        &self,
+        fee_reserve: Amount,
+        unit: Option<&str>,
+        store: impl RecordStore,
+    ) -> Result<Vec<PreMint>, Error> {
         let mut lock = self.counter.maybe_lock().await;
         let mut counter = lock.start_count(unit, &self.keysets)?;
         let mut outputs = PreMintSecretsHyper::split_blank(fee_reserve, &mut counter)?;
@@ -482,6 +788,24 @@ impl Wallet {
         for p in &mut outputs {
             p.amount = 1.into();
         }
+
+        // skip some is work
+        counter.commit(store).await?;
+
+        Ok(outputs)
+    }
+
+    /// the state maybe Pending if the lightning payment is in flight
+    pub async fn melt_with_outputs(
+        &self,
+        quote: &str,
+        proofs: impl ProofsHelper,
+        outputs: Vec<PreMint>,
+        method: Option<&str>,
+    ) -> Result<Melted, Error> {
+        if !outputs.is_empty() {
+            self.check_dleq_supported()?;
+        }
         let blinds = BlindedMessages::new(&outputs);
 
         let melt_response = self
@@ -494,21 +818,44 @@ impl Wallet {
             )
             .await?;
 
-        let change_proofs = match melt_response.change {
-            Some(change) => {
-                let ps = process_swap_response(outputs, change, &keyset0.keys)?;
-                Some(ps)
-            }
-            None => None,
-        };
+        self.melted(melt_response, outputs)
+    }
 
-        // skip some is work
-        counter.commit(store).await?;
+    /// construct the change proofs by the blank outputs
+    pub fn melted(
+        &self,
+        melt_response: MeltResponse,
+        outputs: Vec<PreMint>,
+    ) -> Result<Melted, Error> {
+        let state = melt_response.state();
 
-        let state = if melt_response.paid {
-            nut05::QuoteState::Paid
-        } else {
-            nut05::QuoteState::Unpaid
+        let change_proofs = match melt_response.change {
+            Some(change) if !change.is_empty() => {
+                let keysetid = outputs
+                    .first()
+                    .map(|o| o.blinded_message.keyset_id)
+                    .ok_or_else(|| format_err!("mint returns change without blank outputs"))?;
+                let keyset = self
+                    .keysets
+                    .iter()
+                    .find(|ks| ks.id == keysetid)
+                    .ok_or_else(|| format_err!("not found keyset: {}", keysetid))?;
+
+                // the payment is done whatever, the change failed the DLEQ is dropped
+                match process_swap_response(outputs, change, &keyset.keys, self.dleq) {
+                    Ok(ps) => Some(ps),
+                    Err(e) if e.is_dleq() => {
+                        warn!(
+                            "{} drops the melt change: {}",
+                            self.client.url().as_str(),
+                            e
+                        );
+                        None
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            _ => None,
         };
 
         let melted = Melted {
@@ -529,7 +876,13 @@ impl Wallet {
     ) -> Result<String, Error> {
         let unit = unit.unwrap_or(CURRENCY_UNIT_SAT).parse()?;
 
-        if v4 {
+        // the legacy base64 keyset id can't be encoded as bytes in V4
+        let legacy = proofs
+            .as_slice()
+            .iter()
+            .any(|p| p.as_ref().keyset_id.version == KeySetVersion::VersionBs);
+
+        if v4 && !legacy {
             let t = TokenV4::new(url, proofs, memo, Some(unit))?;
             Ok(t.to_string())
         } else {
@@ -538,6 +891,51 @@ impl Wallet {
         }
     }
 
+    /// NUT-09 restore the signatures of the outputs sent to mint before, such as the journaled outputs
+    /// of an interrupted swap, the outputs not signed are skipped and the spent proofs are included
+    pub async fn restore_outputs(
+        &self,
+        mut outputs: Vec<PreMint>,
+    ) -> Result<ProofsExtended, Error> {
+        if outputs.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let blinds = BlindedMessages::new(&outputs);
+        let resp = self.client.restore(&blinds).await?;
+        let signatures = if resp.signatures.is_empty() {
+            resp.promises
+        } else {
+            resp.signatures
+        };
+
+        outputs.retain(|x| resp.outputs.contains(&x.blinded_message));
+        let keysetid = match outputs.first() {
+            Some(o) => o.blinded_message.keyset_id,
+            None => return Ok(vec![]),
+        };
+        let keyset = self
+            .keysets
+            .iter()
+            .find(|ks| ks.id == keysetid)
+            .ok_or_else(|| format_err!("not found keyset: {}", keysetid))?;
+
+        // the mint committed already, the proofs failed the DLEQ are lost if skipped so the failure is reported only
+        let ps = match process_swap_response::<Proof>(
+            outputs.clone(),
+            signatures.clone(),
+            &keyset.keys,
+            self.dleq,
+        ) {
+            Err(e) if e.is_dleq() => {
+                warn!("{} restores the outputs: {}", self.client.url().as_str(), e);
+                process_swap_response(outputs, signatures, &keyset.keys, DleqPolicy::Off)?
+            }
+            res => res?,
+        };
+        Ok(ps.into_extended_with_unit(Some(keyset.unit.as_str())))
+    }
+
     /// sleepms_after_check_a_batch for (code: 429): {"detail":"Rate limit exceeded."}
     /// 1. brefore call api f: (url, keysets.len(), idx, keysetid, unit, before, batch, now, pre_mints, None..) -> exit
     /// 2. after call api f: (url, keysets.len(), idx, keysetid, unit, before, batch, now, pre_mints, api-outputs, api-signatures, None) -> exit
@@ -574,10 +972,7 @@ impl Wallet {
         let mut life = vec![];
         let keysetids = if keysetids.is_empty() {
             let keysets = self.client.get_keysetids().await?.keysets;
-            keysets
-                .iter()
-                .filter(|ks| ks.id.version != KeySetVersion::VersionBs)
-                .for_each(|k| life.push(k.id.to_string()));
+            keysets.iter().for_each(|k| life.push(k.id.to_string()));
             &life[..]
         } else {
             keysetids
@@ -662,7 +1057,12 @@ impl Wallet {
                 // #[rustfmt::skip]
                 // info!("{}~{}-{}: got signatures {}:\n{}", counter.before(), counter.now(), batch_size, signatures.len(), serde_json::to_string(&signatures).unwrap());
 
-                let ps = process_swap_response::<ProofExtended>(outputs, signatures, &keyset.keys)?;
+                let ps = process_swap_response::<ProofExtended>(
+                    outputs,
+                    signatures,
+                    &keyset.keys,
+                    self.dleq,
+                )?;
                 let mut states = vec![];
                 if ps.len() >= 1 {
                     states = self.check_proofs(&ps).await?.states;
@@ -744,13 +1144,20 @@ async fn try_to_call_swap<'s, 'l: 's>(
     proofs: impl ProofsHelper + Copy,
     keep: Amount,
     send: Amount,
+    fee: Amount,
     denomination: Amount,
+    conditions: Option<&SpendingConditions>,
     counter: &'s mut ManagerCounter<'l>,
     store: impl RecordStore,
 ) -> Result<(PreMintSecretsHyper, SwapResponse), Error> {
     for i in (0..3).rev() {
-        let outputs = PreMintSecretsHyper::split_amount2(keep, send, denomination, counter)?;
+        let outputs =
+            PreMintSecretsHyper::split_amount2(keep, send, fee, denomination, conditions, counter)?;
         let blinds = BlindedMessages::new(&outputs.messages);
+        store
+            .add_outputs(&outputs.messages, counter.range())
+            .await
+            .map_err(|e| Error::Custom(e.into()))?;
         let swap_response = client.swap(proofs, &blinds).await;
 
         if counter.mnemonic().is_some() {
@@ -856,17 +1263,28 @@ impl PreMintSecretsHyper {
         amount: Amount,
         counter: &mut ManagerCounter,
     ) -> Result<Vec<PreMint>, Error> {
-        Self::split_amount2(amount, 0.into(), 0.into(), counter).map(|s| s.messages)
+        Self::split_amount2(amount, 0.into(), 0.into(), 0.into(), None, counter).map(|s| s.messages)
     }
 
     // send
     /// Create BlindedMessages with amount and denomination(used to split send, default is 0, meaning using random spilt)
+    ///
+    /// the send outputs use NUT-10 well-known secrets if conditions is some
+    ///
+    /// the NUT-02 input fee is paid by the keep outputs
     pub fn split_amount2(
         amount_keep: Amount,
         amount_send: Amount,
+        fee: Amount,
         denomination: Amount,
+        conditions: Option<&SpendingConditions>,
         counter: &mut ManagerCounter,
     ) -> Result<Self, Error> {
+        if amount_keep < fee {
+            return Err(Error::insufficant_funds());
+        }
+        let amount_keep = amount_keep - fee;
+
         let splited_keep = amount_keep.split();
         let splited_keep_len = splited_keep.len();
 
@@ -900,21 +1318,50 @@ impl PreMintSecretsHyper {
 
         let capacity = splited_keep.len() + splited_send.len();
         let mut secrets = Vec::with_capacity(capacity);
-        for amount in splited_keep.into_iter().chain(splited_send.into_iter()) {
+        for amount in splited_keep.into_iter() {
             let c = counter.count();
             let p = counter.generate(c, amount)?;
             secrets.push(p);
         }
+        for amount in splited_send.into_iter() {
+            let p = match conditions {
+                Some(sc) => counter.generate_with_conditions(amount, sc)?,
+                None => {
+                    let c = counter.count();
+                    counter.generate(c, amount)?
+                }
+            };
+            secrets.push(p);
+        }
 
         Ok(Self::new(secrets, splited_keep_len))
     }
 }
 
-/// generate Proofs from swaps response
+/// NUT-07 Y = hash_to_curve(secret) of the proofs
+pub fn proofs_ys(proofs: impl ProofsHelper) -> Result<Vec<PublicKey>, Error> {
+    let proofs = proofs.as_slice();
+    let mut ys = Vec::with_capacity(proofs.len());
+    for p in proofs {
+        // base64 secret string is compat also..
+        let y = cashu::dhke::hash_to_curve(p.as_ref().secret.as_str().as_bytes())?;
+        ys.push(y);
+    }
+
+    Ok(ys)
+}
+
+/// NUT-02 fees = ceil(sum(input_fee_ppk) / 1000)
+pub fn fee_from_ppk(ppk: u64) -> Amount {
+    ((ppk + 999) / 1000).into()
+}
+
+/// generate Proofs from swaps response, verify the DLEQ of signatures by the policy
 pub fn process_swap_response<P: From<Proof>>(
     pre_secrets: Vec<PreMint>,
     promises: Vec<BlindSignature>,
     keys: &Keys,
+    dleq: DleqPolicy,
 ) -> Result<Vec<P>, Error> {
     let pre_secrets = pre_secrets;
     if pre_secrets.len() < promises.len() {
@@ -933,6 +1380,8 @@ pub fn process_swap_response<P: From<Proof>>(
             .ok_or_else(|| format_err!("not found amount key: {}", promise.amount.to_u64()))?
             .to_owned();
 
+        verify_signature_dleq(dleq, &promise, a, pre_secret.blinded_message.blinded_secret)?;
+
         let r = pre_secret.r;
         let c = unblind_message(&promise.c, &r, &a)?;
 
@@ -995,4 +1444,13 @@ mod tests {
         println!("receive spent {}: {:?}", ps.len(), r);
         assert_eq!(ps.len(), 0);
     }
+
+    #[test]
+    fn test_fee_from_ppk() {
+        assert_eq!(fee_from_ppk(0), Amount::ZERO);
+        assert_eq!(fee_from_ppk(1), 1.into());
+        assert_eq!(fee_from_ppk(1000), 1.into());
+        assert_eq!(fee_from_ppk(1001), 2.into());
+        assert_eq!(fee_from_ppk(100 * 25), 3.into());
+    }
 }
@@ -0,0 +1,204 @@
+use base64::{engine::general_purpose, Engine};
+use bitcoin_hashes::{sha256, Hash};
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+
+use super::error::ClientError as Error;
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// the SPKI pin of the DER X.509 certificate: base64 sha256 of the SubjectPublicKeyInfo, the same as HPKP pin-sha256
+pub fn spki_pin(cert: &[u8]) -> Option<String> {
+    let spki = spki_der(cert)?;
+    let hash = sha256::Hash::hash(spki);
+    Some(general_purpose::STANDARD.encode(hash))
+}
+
+// the tag, header length and content length of the DER TLV
+fn der_tlv(input: &[u8]) -> Option<(u8, usize, usize)> {
+    let tag = *input.first()?;
+    let first = *input.get(1)? as usize;
+    let (hl, len) = if first < 0x80 {
+        (2, first)
+    } else {
+        let n = first & 0x7f;
+        if n == 0 || n > 4 {
+            return None;
+        }
+        let mut len = 0usize;
+        for i in 0..n {
+            len = (len << 8) | *input.get(2 + i)? as usize;
+        }
+        (2 + n, len)
+    };
+    if input.len() < hl + len {
+        return None;
+    }
+    Some((tag, hl, len))
+}
+
+/// the DER SubjectPublicKeyInfo of the DER X.509 certificate
+pub fn spki_der(cert: &[u8]) -> Option<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    const VERSION: u8 = 0xa0;
+
+    // Certificate ::= SEQUENCE { tbsCertificate SEQUENCE, .. }
+    let (_, hl, _) = der_tlv(cert).filter(|t| t.0 == SEQUENCE)?;
+    let tbs = &cert[hl..];
+    let (_, hl, len) = der_tlv(tbs).filter(|t| t.0 == SEQUENCE)?;
+    let mut rest = &tbs[hl..hl + len];
+
+    // [0] version is optional
+    if rest.first() == Some(&VERSION) {
+        let (_, hl, len) = der_tlv(rest)?;
+        rest = &rest[hl + len..];
+    }
+    // serialNumber, signature, issuer, validity, subject
+    for _ in 0..5 {
+        let (_, hl, len) = der_tlv(rest)?;
+        rest = &rest[hl + len..];
+    }
+
+    let (tag, hl, len) = der_tlv(rest)?;
+    (tag == SEQUENCE).then(|| &rest[..hl + len])
+}
+
+/// the webpki roots and the extra PEM root certificates
+fn root_store(roots_pem: &[String]) -> Result<RootCertStore, Error> {
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+        )
+    }));
+
+    for pem in roots_pem {
+        let certs = rustls_pemfile::certs(&mut pem.as_bytes())
+            .map_err(|e| Error::Config(format!("invalid root certificate: {}", e)))?;
+        for der in certs {
+            roots
+                .add(&Certificate(der))
+                .map_err(|e| Error::Config(format!("invalid root certificate: {:?}", e)))?;
+        }
+    }
+
+    Ok(roots)
+}
+
+/// verify the chain by the roots, then one of the certificates must match the pins
+pub(crate) struct PinVerifier {
+    inner: WebPkiVerifier,
+    pins: Vec<String>,
+}
+
+impl PinVerifier {
+    pub fn new(roots_pem: &[String], pins: &[String]) -> Result<Self, Error> {
+        let roots = root_store(roots_pem)?;
+        Ok(Self {
+            inner: WebPkiVerifier::new(roots, None),
+            pins: pins.to_vec(),
+        })
+    }
+
+    pub fn client_config(self: &Arc<Self>) -> ClientConfig {
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(self.clone())
+            .with_no_client_auth()
+    }
+}
+
+const MISMATCH: &str = "certificate pin mismatch: ";
+
+/// the TLS handshake failure hidden in the reqwest error, the mismatch is carried by the rustls error of its connection
+pub(crate) fn handshake_failure(e: &(dyn StdError + 'static)) -> Option<String> {
+    let mut next = Some(e);
+    while let Some(e) = next {
+        // io::Error::source skips the inner rustls error
+        let tls = e.downcast_ref::<rustls::Error>().or_else(|| {
+            e.downcast_ref::<std::io::Error>()
+                .and_then(|io| io.get_ref())
+                .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+        });
+        if let Some(tls) = tls {
+            return Some(match tls {
+                rustls::Error::General(msg) if msg.starts_with(MISMATCH) => {
+                    msg[MISMATCH.len()..].to_owned()
+                }
+                other => other.to_string(),
+            });
+        }
+        next = e.source();
+    }
+    None
+}
+
+impl fmt::Debug for PinVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinVerifier")
+            .field("pins", &self.pins)
+            .finish()
+    }
+}
+
+impl ServerCertVerifier for PinVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+
+        let matched = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(|c| spki_pin(&c.0))
+            .any(|p| self.pins.contains(&p));
+        if matched {
+            return Ok(verified);
+        }
+
+        let got = spki_pin(&end_entity.0).unwrap_or_default();
+        Err(rustls::Error::General(format!(
+            "{}{:?} {}",
+            MISMATCH, server_name, got
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_root_and_handshake_failure() {
+        let invalid = "-----BEGIN CERTIFICATE-----\nnot base64\n-----END CERTIFICATE-----\n";
+        let err = PinVerifier::new(&[invalid.to_owned()], &[]).unwrap_err();
+        assert!(err.is_config(), "{}", err);
+
+        let mismatch = rustls::Error::General(format!("{}localhost abc=", MISMATCH));
+        let io = std::io::Error::new(std::io::ErrorKind::InvalidData, mismatch);
+        assert_eq!(handshake_failure(&io).unwrap(), "localhost abc=");
+
+        let io = std::io::Error::new(std::io::ErrorKind::InvalidData, rustls::Error::DecryptError);
+        assert!(handshake_failure(&io).is_some());
+
+        let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        assert!(handshake_failure(&refused).is_none());
+    }
+}
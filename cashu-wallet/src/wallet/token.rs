@@ -5,10 +5,42 @@ use cashu::{
     Amount,
 };
 use serde::Deserialize;
+use strum::{AsRefStr, Display, EnumIs, EnumString, IntoStaticStr};
 use url::Url;
 
 pub type ProofsExtended = Vec<ProofExtended>;
 
+/// the lifecycle of a stored proof, only the unspent are selected to spend and counted as available
+#[derive(
+    Debug, Default, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+//
+#[derive(Display, AsRefStr, IntoStaticStr, EnumIs, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ProofState {
+    #[default]
+    Unspent,
+    /// locked by the wallet for an operation: the token sent but not claimed, the inputs of melt before paying
+    Reserved,
+    /// the mint is spending it: the inputs of an in-flight melt
+    Pending,
+    /// kept with the operation after the mint spent it
+    Spent,
+    /// received offline and not swapped yet, the sender still can spend it
+    Unswapped,
+}
+
+impl ProofState {
+    pub const ALL: [ProofState; 5] = [
+        ProofState::Unspent,
+        ProofState::Reserved,
+        ProofState::Pending,
+        ProofState::Spent,
+        ProofState::Unswapped,
+    ];
+}
+
 // for store to kvdb with unit
 #[derive(Debug, Clone, Eq, Serialize, Deserialize)]
 pub struct ProofExtended {
@@ -18,6 +50,13 @@ pub struct ProofExtended {
     pub ts: Option<u64>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub unit: Option<String>,
+    #[serde(default, skip_serializing_if = "ProofState::is_unspent")]
+    pub state: ProofState,
+    /// the operation reserved it: the transaction id of send, the quote of melt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operation: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserved_at: Option<u64>,
     // backup json string for kvdb delete: the order of feilds changed(v1pre vs v1)
     #[serde(skip)]
     pub js: String,
@@ -31,12 +70,24 @@ impl ProofExtended {
         self.js = js;
         self
     }
+    /// reserved by the operation from now
+    pub fn reserve(mut self, operation: &str) -> Self {
+        self.state = ProofState::Reserved;
+        self.operation = Some(operation.to_owned());
+        self.reserved_at = Some(unixtime_ms());
+        self
+    }
 }
 
 // skip js for test kvdb
 impl PartialEq for ProofExtended {
     fn eq(&self, other: &Self) -> bool {
-        self.raw == other.raw && self.ts == other.ts && self.unit == other.unit
+        self.raw == other.raw
+            && self.ts == other.ts
+            && self.unit == other.unit
+            && self.state == other.state
+            && self.operation == other.operation
+            && self.reserved_at == other.reserved_at
     }
 }
 
@@ -52,6 +103,9 @@ impl From<Proof> for ProofExtended {
             raw,
             ts: None,
             unit: None,
+            state: ProofState::Unspent,
+            operation: None,
+            reserved_at: None,
             js: String::new(),
         }
     }
@@ -97,6 +151,13 @@ pub trait ProofsHelper: Sized + serde::Serialize {
             raw: self.as_slice(),
         }
     }
+    /// the proofs locked by NUT-10 spending conditions(NUT-11 P2PK, NUT-14 HTLC)
+    fn locked(&self) -> Vec<&Self::Proof> {
+        self.as_slice()
+            .iter()
+            .filter(|p| super::spending_conditions(p.as_ref()).is_some())
+            .collect()
+    }
     fn to_extended(&self) -> ProofsExtended;
     fn into_extended(self) -> ProofsExtended {
         self.to_extended()
@@ -483,6 +544,97 @@ impl std::str::FromStr for Token {
     }
 }
 
+/// NUT-18 payment request transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    /// NIP-17 direct message to the nprofile
+    Nostr,
+    /// HTTP POST to the url
+    Post,
+}
+
+/// NUT-18 payment request transport
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transport {
+    #[serde(rename = "t")]
+    pub kind: TransportKind,
+    /// nprofile or url
+    #[serde(rename = "a")]
+    pub target: String,
+    #[serde(rename = "g", default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<Vec<String>>>,
+}
+
+/// NUT-18 payment request: <https://github.com/cashubtc/nuts/blob/main/18.md>
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    /// Payment id
+    #[serde(rename = "i", default, skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<String>,
+    #[serde(rename = "a", default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<u64>,
+    #[serde(rename = "u", default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<CurrencyUnit>,
+    #[serde(rename = "s", default, skip_serializing_if = "Option::is_none")]
+    pub single_use: Option<bool>,
+    /// accepted mints, any mint if none
+    #[serde(rename = "m", default, skip_serializing_if = "Option::is_none")]
+    pub mints: Option<Vec<MintUrl>>,
+    #[serde(rename = "d", default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// in-band if empty
+    #[serde(rename = "t", default)]
+    pub transports: Vec<Transport>,
+}
+
+impl PaymentRequest {
+    pub fn accepts_mint(&self, mint: &MintUrl) -> bool {
+        self.mints
+            .as_ref()
+            .map(|ms| ms.contains(mint))
+            .unwrap_or(true)
+    }
+}
+
+impl fmt::Display for PaymentRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use serde::ser::Error;
+        let mut data = Vec::new();
+        ciborium::into_writer(self, &mut data).map_err(|e| fmt::Error::custom(e.to_string()))?;
+
+        let encode_config = general_purpose::GeneralPurposeConfig::new().with_encode_padding(false);
+        let encoded = GeneralPurpose::new(&alphabet::URL_SAFE, encode_config).encode(data);
+        write!(f, "creqA{}", encoded)
+    }
+}
+
+impl std::str::FromStr for PaymentRequest {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_prefix("creqA").ok_or(Error::UnsupportedToken)?;
+        let decode_config = general_purpose::GeneralPurposeConfig::new()
+            .with_decode_padding_mode(base64::engine::DecodePaddingMode::Indifferent);
+        let decoded = GeneralPurpose::new(&alphabet::URL_SAFE, decode_config).decode(s)?;
+        let request = ciborium::from_reader(&decoded[..])?;
+
+        Ok(request)
+    }
+}
+
+/// NUT-18 the json sent to the transport of payment request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentRequestPayload {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    pub mint: MintUrl,
+    pub unit: CurrencyUnit,
+    pub proofs: Proofs,
+}
+
 /// wrap Url for compat all
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct MintUrl {
@@ -493,6 +645,14 @@ impl MintUrl {
     pub fn as_str(&self) -> &str {
         self.raw.as_str()
     }
+
+    /// the Tor hidden service, only reachable over the Tor proxy
+    pub fn is_onion(&self) -> bool {
+        self.raw
+            .host_str()
+            .map(|h| h.ends_with(".onion"))
+            .unwrap_or_default()
+    }
 }
 
 // https://8333.space:3338 -> https://8333.space:3338/
@@ -564,6 +724,46 @@ pub mod tests {
     #[allow(unused_imports)]
     use super::*;
 
+    #[test]
+    fn test_payment_request() {
+        let request = PaymentRequest {
+            payment_id: Some("b7a90176".to_owned()),
+            amount: Some(10),
+            unit: Some(CurrencyUnit::Sat),
+            single_use: None,
+            mints: Some(vec!["https://8333.space:3338".parse().unwrap()]),
+            description: Some("coffee".to_owned()),
+            transports: vec![Transport {
+                kind: TransportKind::Post,
+                target: "https://api.example.com/payment".to_owned(),
+                tags: None,
+            }],
+        };
+
+        let s = request.to_string();
+        assert!(s.starts_with("creqA"));
+
+        let request2: PaymentRequest = s.parse().unwrap();
+        assert_eq!(request2, request);
+        assert!(request2.accepts_mint(&"https://8333.space:3338/".parse().unwrap()));
+        assert!(!request2.accepts_mint(&"https://mint.minibits.cash/Bitcoin".parse().unwrap()));
+
+        assert!("cashuBxxx".parse::<PaymentRequest>().is_err());
+    }
+
+    #[test]
+    fn test_payment_request_without_transports() {
+        let request = PaymentRequest {
+            amount: Some(1),
+            ..Default::default()
+        };
+
+        let request2: PaymentRequest = request.to_string().parse().unwrap();
+        assert_eq!(request2, request);
+        assert!(request2.transports.is_empty());
+        assert!(request2.accepts_mint(&"https://8333.space:3338".parse().unwrap()));
+    }
+
     #[test]
     fn test_mint_url_path_root() {
         let u = "https://8333.space:3338";
@@ -648,6 +848,17 @@ pub mod tests {
         assert_ne!(up.as_str(), up2.as_str());
     }
 
+    #[test]
+    fn test_onion_mint_url() {
+        let u = "http://mintgzv4mk5lnvjcq3pk6p3mqe6b5cdw2l3qyo2kjvmhdq4gbthg2ead.onion";
+        let mu: MintUrl = u.parse().unwrap();
+        assert!(mu.is_onion());
+        assert_eq!(serde_json::to_string(&mu).unwrap(), format!("\"{}\"", u));
+
+        let mu: MintUrl = "https://8333.space:3338".parse().unwrap();
+        assert!(!mu.is_onion());
+    }
+
     #[test]
     fn test_token() {
         let v4 = r#"cashuBo2FteCJodHRwczovL21pbnQubWluaWJpdHMuY2FzaC9CaXRjb2luYXVjc2F0YXSBomFpSABQBVDwSUFGYXCCo2FhAmFzeEAxMGE5YjNlOWE5NmJmYjhlMGE2ZGJlMTY3YzA3YzhlYmUxYWQ0MjZhNTZmOGE4MjU4MDM2ODQ4ZmNlMzAzMDYxYWNYIQKVA4ylDqbmnxzWfDlVnrgvVxzDGCrQGjoHeMfrCsFFt6NhYQhhc3hAZGI5YjM5YjBkNDZhNWM0ZmY4ODc2OGRhNTI4MWE0ZmJmNjcyYzE1MTZiODU0NjE0OGU2NmI5N2NlYmQyY2RlOGFjWCED1RAJOBqPXGmpp0m1q5-MYiGf8s5q3klYdZ0PCcCfgiw"#;
@@ -0,0 +1,278 @@
+use reqwest::header::{CONTENT_TYPE, RETRY_AFTER};
+
+use super::error::ClientError as Error;
+use super::tls::{handshake_failure, PinVerifier};
+use super::HttpClient;
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// the raw response of the mint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportResponse {
+    /// http status code
+    pub status: i32,
+    /// json body
+    pub body: String,
+    /// the Retry-After header of 429/503, the HTTP-date is ignored
+    pub retry_after: Option<Duration>,
+}
+
+impl TransportResponse {
+    pub fn new(status: i32, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: body.into(),
+            retry_after: None,
+        }
+    }
+
+    pub fn retry_after(mut self, d: Duration) -> Self {
+        self.retry_after = Some(d);
+        self
+    }
+}
+
+/// the transport behind MintClient, it carries the NUT requests to the mint and returns the raw responses.
+///
+/// the default is HttpTransport over reqwest, others could be in-process mock, Tor-routed client,
+/// proxy through the app's networking stack or recording/replay
+#[async_trait]
+pub trait MintTransport: fmt::Debug + Send + Sync {
+    /// GET the url
+    async fn get(
+        &self,
+        url: url::Url,
+        timeout: Option<Duration>,
+    ) -> Result<TransportResponse, Error>;
+
+    /// POST the json body to the url
+    async fn post(
+        &self,
+        url: url::Url,
+        body: String,
+        timeout: Option<Duration>,
+    ) -> Result<TransportResponse, Error>;
+}
+
+/// the default transport over http(s)
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    http: HttpClient,
+    pins: Option<Arc<PinVerifier>>,
+}
+
+impl HttpTransport {
+    pub fn new(http: HttpClient) -> Self {
+        Self { http, pins: None }
+    }
+
+    // the http client is configured with the verifier
+    pub(crate) fn with_pins(http: HttpClient, pins: Arc<PinVerifier>) -> Self {
+        Self {
+            http,
+            pins: Some(pins),
+        }
+    }
+
+    pub fn http(&self) -> &HttpClient {
+        &self.http
+    }
+
+    async fn send(
+        &self,
+        mut req: reqwest::RequestBuilder,
+        timeout: Option<Duration>,
+    ) -> Result<TransportResponse, Error> {
+        if let Some(t) = timeout {
+            req = req.timeout(t);
+        }
+
+        let resp = match req.send().await {
+            Ok(resp) => resp,
+            Err(e) => {
+                // every TLS handshake failure of the pinned client is a pin mismatch, not an unreachable mint
+                if self.pins.is_some() {
+                    if let Some(got) = handshake_failure(&e) {
+                        return Err(Error::PinMismatch(got));
+                    }
+                }
+                return Err(e.into());
+            }
+        };
+        let status = resp.status().as_u16() as i32;
+        let retry_after = resp
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let body = resp.text().await?;
+
+        Ok(TransportResponse {
+            status,
+            body,
+            retry_after,
+        })
+    }
+}
+
+#[async_trait]
+impl MintTransport for HttpTransport {
+    async fn get(
+        &self,
+        url: url::Url,
+        timeout: Option<Duration>,
+    ) -> Result<TransportResponse, Error> {
+        self.send(self.http.get(url), timeout).await
+    }
+
+    async fn post(
+        &self,
+        url: url::Url,
+        body: String,
+        timeout: Option<Duration>,
+    ) -> Result<TransportResponse, Error> {
+        let req = self
+            .http
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body);
+        self.send(req, timeout).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::{HttpOptions, MintClient, MintErrorCode, RetryPolicy};
+    use std::sync::{Arc, Mutex};
+
+    /// replay the canned responses by path and record the requests
+    #[derive(Debug, Default)]
+    struct Replay {
+        responses: Vec<(&'static str, TransportResponse)>,
+        requests: Mutex<Vec<(String, Option<String>)>>,
+    }
+
+    impl Replay {
+        fn reply(&self, url: &url::Url, body: Option<String>) -> Result<TransportResponse, Error> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((url.path().to_owned(), body));
+
+            self.responses
+                .iter()
+                .find(|(p, _)| *p == url.path())
+                .map(|(_, r)| r.clone())
+                .ok_or_else(|| Error::Transport(format_err!("not found: {}", url.path())))
+        }
+    }
+
+    #[async_trait]
+    impl MintTransport for Replay {
+        async fn get(
+            &self,
+            url: url::Url,
+            _timeout: Option<Duration>,
+        ) -> Result<TransportResponse, Error> {
+            self.reply(&url, None)
+        }
+
+        async fn post(
+            &self,
+            url: url::Url,
+            body: String,
+            _timeout: Option<Duration>,
+        ) -> Result<TransportResponse, Error> {
+            self.reply(&url, Some(body))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_with_transport() {
+        let keysets = r#"{"keysets":[{"id":"009a1f293253e41e","unit":"sat","active":true,"input_fee_ppk":0}]}"#;
+        let spent = r#"{"detail":"Token already spent.","code":11001}"#;
+        let replay = Arc::new(Replay {
+            responses: vec![
+                ("/v1/keysets", TransportResponse::new(200, keysets)),
+                ("/v1/checkstate", TransportResponse::new(400, spent)),
+            ],
+            ..Default::default()
+        });
+
+        let url = "https://mint.example/".parse().unwrap();
+        let client = MintClient::with_transport(url, HttpOptions::new(), replay.clone());
+
+        let ks = client.get_keysetids().await.unwrap();
+        assert_eq!(ks.keysets.len(), 1);
+        assert!(ks.keysets[0].active);
+
+        let err = client.check_state(&[]).await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Mint(MintErrorCode::TokenAlreadySpent, 11001, _)
+        ));
+
+        let err = client.get_info().await.unwrap_err();
+        assert!(err.is_transport());
+        assert!(err.is_network_error());
+
+        let requests = replay.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[1].0, "/v1/checkstate");
+        assert_eq!(requests[1].1.as_deref(), Some(r#"{"Ys":[]}"#));
+    }
+
+    #[tokio::test]
+    async fn test_client_retry() {
+        let limited = TransportResponse::new(429, r#"{"detail":"Rate limit exceeded."}"#)
+            .retry_after(Duration::from_millis(10));
+        let replay = Arc::new(Replay {
+            responses: vec![
+                ("/v1/keysets", limited.clone()),
+                ("/v1/checkstate", limited.clone()),
+                ("/v1/swap", limited),
+            ],
+            ..Default::default()
+        });
+
+        let url: crate::wallet::MintUrl = "https://mint.example/".parse().unwrap();
+        let policy = RetryPolicy::new().max_attempts(3).backoff_ms(1);
+        let options = HttpOptions::new().retry(policy);
+        let client = MintClient::with_transport(url.clone(), options, replay.clone());
+
+        let err = client.get_keysetids().await.unwrap_err();
+        assert!(err.is_retryable());
+        let err = client.check_state(&[]).await.unwrap_err();
+        assert!(err.is_retryable());
+        let inputs: Vec<crate::wallet::Proof> = vec![];
+        assert!(client.swap(inputs, &Default::default()).await.is_err());
+
+        // the swap is never retried
+        let paths = |replay: &Replay| {
+            let requests = replay.requests.lock().unwrap();
+            requests.iter().map(|r| r.0.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            paths(&replay),
+            [
+                "/v1/keysets",
+                "/v1/keysets",
+                "/v1/keysets",
+                "/v1/checkstate",
+                "/v1/checkstate",
+                "/v1/checkstate",
+                "/v1/swap",
+            ]
+        );
+
+        // no policy no retry
+        replay.requests.lock().unwrap().clear();
+        let client = MintClient::with_transport(url, HttpOptions::new(), replay.clone());
+        assert!(client.get_keysetids().await.is_err());
+        assert_eq!(paths(&replay), ["/v1/keysets"]);
+    }
+}
@@ -0,0 +1,340 @@
+use futures_util::{SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+
+use super::error::ClientError as Error;
+use super::error::MintErrorCode;
+use super::MintClient;
+
+use std::collections::BTreeMap;
+
+/// NUT-17 WebSocket subscriptions: https://github.com/cashubtc/nuts/blob/main/17.md
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionKind {
+    /// filters are the mint quote ids
+    Bolt11MintQuote,
+    /// filters are the melt quote ids
+    Bolt11MeltQuote,
+    /// filters are the Ys of proofs
+    ProofState,
+}
+
+impl SubscriptionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Bolt11MintQuote => "bolt11_mint_quote",
+            Self::Bolt11MeltQuote => "bolt11_melt_quote",
+            Self::ProofState => "proof_state",
+        }
+    }
+}
+
+/// the notification pushed by mint, the payload is the same as the response of quote state or checkstate
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub sub_id: String,
+    pub kind: SubscriptionKind,
+    pub payload: serde_json::Value,
+}
+
+impl Notification {
+    pub fn payload<T: serde::de::DeserializeOwned>(&self) -> Result<T, Error> {
+        let t = serde_json::from_value(self.payload.clone())?;
+        Ok(t)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Request<'a> {
+    jsonrpc: &'a str,
+    id: u64,
+    method: &'a str,
+    params: Params<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct Params<'a> {
+    kind: SubscriptionKind,
+    #[serde(rename = "subId")]
+    sub_id: &'a str,
+    filters: &'a [String],
+}
+
+// response or notification
+#[derive(Debug, Deserialize)]
+struct Response {
+    #[serde(default)]
+    id: Option<u64>,
+    #[serde(default)]
+    result: Option<serde_json::Value>,
+    #[serde(default)]
+    error: Option<ResponseError>,
+    #[serde(default)]
+    params: Option<NotificationParams>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseError {
+    code: i32,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NotificationParams {
+    #[serde(rename = "subId")]
+    sub_id: String,
+    payload: serde_json::Value,
+}
+
+/// the subscriptions are closed when dropped
+#[derive(Debug)]
+pub struct Subscription {
+    kinds: BTreeMap<String, SubscriptionKind>,
+    rx: mpsc::UnboundedReceiver<Notification>,
+    task: JoinHandle<()>,
+}
+
+impl Subscription {
+    pub fn sub_ids(&self) -> impl Iterator<Item = (&str, SubscriptionKind)> {
+        self.kinds.iter().map(|(k, v)| (k.as_str(), *v))
+    }
+
+    /// returns None if the connection closed
+    pub async fn recv(&mut self) -> Option<Notification> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl MintClient {
+    /// ws(s)://mint.host/v1/ws
+    pub fn ws_url(&self) -> Result<url::Url, Error> {
+        let mut url = self.urlraw().join("v1/ws")?;
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        url.set_scheme(scheme)
+            .map_err(|_| Error::UnknownResponse(0, format!("invalid ws url: {}", url)))?;
+        Ok(url)
+    }
+
+    /// NUT-17 subscribe the kinds with filters, returns after the mint accepts all of them
+    pub async fn subscribe(
+        &self,
+        subscriptions: &[(SubscriptionKind, Vec<String>)],
+    ) -> Result<Subscription, Error> {
+        let url = self.ws_url()?;
+
+        // the websocket connects directly, never leak the mint traffic out of the proxy
+        if self.options.proxy_of(&self.url).is_some() {
+            return Err(Error::Config(
+                "NUT-17 websocket is not supported over proxy".to_owned(),
+            ));
+        }
+        // the websocket trusts the webpki roots only
+        if !self.options.pins_of(&self.url).is_empty() {
+            return Err(Error::Config(
+                "NUT-17 websocket is not supported with pins".to_owned(),
+            ));
+        }
+
+        let connect = tokio_tungstenite::connect_async(url.as_str());
+        let (mut ws, _) = match self.options.timeout_connect() {
+            Some(t) => tokio::time::timeout(t, connect).await.map_err(|_| {
+                Error::WebSocket(tokio_tungstenite::tungstenite::Error::Io(
+                    std::io::ErrorKind::TimedOut.into(),
+                ))
+            })??,
+            None => connect.await?,
+        };
+
+        let mut kinds = BTreeMap::new();
+        for (idx, (kind, filters)) in subscriptions.iter().enumerate() {
+            let sub_id = format!("{}-{}", kind.as_str(), idx);
+            let request = Request {
+                jsonrpc: "2.0",
+                id: idx as u64,
+                method: "subscribe",
+                params: Params {
+                    kind: *kind,
+                    sub_id: &sub_id,
+                    filters,
+                },
+            };
+
+            let js = serde_json::to_string(&request)?;
+            ws.send(Message::Text(js)).await?;
+            kinds.insert(sub_id, *kind);
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        // the notifications maybe come before all the responses
+        let acks = async {
+            let mut accepted = 0;
+            while accepted < kinds.len() {
+                let msg = ws.next().await.ok_or(Error::WebSocket(
+                    tokio_tungstenite::tungstenite::Error::ConnectionClosed,
+                ))??;
+                let text = match msg {
+                    Message::Text(text) => text,
+                    Message::Close(_) => {
+                        return Err(Error::WebSocket(
+                            tokio_tungstenite::tungstenite::Error::ConnectionClosed,
+                        ))
+                    }
+                    _ => continue,
+                };
+
+                let js: Response = serde_json::from_str(&text)?;
+                if let Some(e) = js.error {
+                    let code = MintErrorCode::new(e.code, &e.message);
+                    return Err(Error::Mint(code, e.code, e.message));
+                }
+                if js.id.is_some() && js.result.is_some() {
+                    accepted += 1;
+                } else if let Some(n) = js.params.and_then(|p| notification(&kinds, p)) {
+                    let _ = tx.send(n);
+                }
+            }
+            Ok::<_, Error>(())
+        };
+        // the mint accepts the socket but never replies
+        match self.options.timeout_get() {
+            Some(t) => tokio::time::timeout(t, acks).await.map_err(|_| {
+                Error::WebSocket(tokio_tungstenite::tungstenite::Error::Io(
+                    std::io::ErrorKind::TimedOut.into(),
+                ))
+            })??,
+            None => acks.await?,
+        }
+
+        let kinds2 = kinds.clone();
+        let task = tokio::spawn(async move {
+            while let Some(msg) = ws.next().await {
+                let text = match msg {
+                    Ok(Message::Text(text)) => text,
+                    Ok(Message::Close(_)) => break,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        warn!("ws recv failed: {}", e);
+                        break;
+                    }
+                };
+
+                let js = match serde_json::from_str::<Response>(&text) {
+                    Ok(js) => js,
+                    Err(e) => {
+                        warn!("ws invalid message {}: {}", text, e);
+                        continue;
+                    }
+                };
+
+                if let Some(n) = js.params.and_then(|p| notification(&kinds2, p)) {
+                    if tx.send(n).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Subscription { kinds, rx, task })
+    }
+}
+
+fn notification(
+    kinds: &BTreeMap<String, SubscriptionKind>,
+    params: NotificationParams,
+) -> Option<Notification> {
+    let kind = kinds.get(&params.sub_id)?;
+    Some(Notification {
+        sub_id: params.sub_id,
+        kind: *kind,
+        payload: params.payload,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::HttpOptions;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_subscribe_with_stub() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // the stub mint accepts the subscription and pushes a paid mint quote
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+
+            let msg = ws.next().await.unwrap().unwrap();
+            let js: serde_json::Value = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+            assert_eq!(js["method"], "subscribe");
+            assert_eq!(js["params"]["kind"], "bolt11_mint_quote");
+            assert_eq!(js["params"]["filters"][0], "quote0");
+            let sub_id = js["params"]["subId"].as_str().unwrap().to_owned();
+
+            let resp = format!(
+                r#"{{"jsonrpc":"2.0","result":{{"status":"OK","subId":"{}"}},"id":{}}}"#,
+                sub_id, js["id"]
+            );
+            ws.send(Message::Text(resp)).await.unwrap();
+
+            let noti = format!(
+                r#"{{"jsonrpc":"2.0","method":"subscribe","params":{{"subId":"{}","payload":{{"quote":"quote0","request":"lnbc","state":"PAID","expiry":0}}}}}}"#,
+                sub_id
+            );
+            ws.send(Message::Text(noti)).await.unwrap();
+            let _ = ws.next().await;
+        });
+
+        let url = format!("http://{}/", addr).parse().unwrap();
+        let client = MintClient::new(url, HttpOptions::new()).unwrap();
+        assert_eq!(client.ws_url().unwrap().scheme(), "ws");
+
+        let mut sub = client
+            .subscribe(&[(SubscriptionKind::Bolt11MintQuote, vec!["quote0".to_owned()])])
+            .await
+            .unwrap();
+
+        let n = sub.recv().await.unwrap();
+        assert_eq!(n.kind, SubscriptionKind::Bolt11MintQuote);
+        assert_eq!(n.payload["quote"], "quote0");
+        assert_eq!(n.payload["state"], "PAID");
+
+        drop(sub);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_ack_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // the stub mint accepts the socket but never replies the subscription
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            while let Some(Ok(_)) = ws.next().await {}
+        });
+
+        let url = format!("http://{}/", addr).parse().unwrap();
+        let client = MintClient::new(url, HttpOptions::new().timeout_get_ms(200)).unwrap();
+
+        let err = client
+            .subscribe(&[(SubscriptionKind::Bolt11MintQuote, vec!["quote0".to_owned()])])
+            .await
+            .unwrap_err();
+        assert!(err.is_web_socket());
+
+        server.abort();
+    }
+}
@@ -0,0 +1,134 @@
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::rand::thread_rng;
+use bitcoin::secp256k1::{All, PublicKey, Scalar, Secp256k1, SecretKey};
+
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+fn secp() -> &'static Secp256k1<All> {
+    static SECP: OnceLock<Secp256k1<All>> = OnceLock::new();
+    SECP.get_or_init(Secp256k1::new)
+}
+
+/// NUT-00 hash_to_curve
+pub fn hash_to_curve(message: &[u8]) -> Option<PublicKey> {
+    const DOMAIN_SEPARATOR: &[u8] = b"Secp256k1_HashToCurve_Cashu_";
+
+    let msg_to_hash = sha256::Hash::hash(&[DOMAIN_SEPARATOR, message].concat());
+
+    for counter in 0u32..2u32.pow(16) {
+        let mut bytes = msg_to_hash.to_byte_array().to_vec();
+        bytes.extend_from_slice(&counter.to_le_bytes());
+        let hash = sha256::Hash::hash(&bytes);
+
+        let mut point = [0u8; 33];
+        point[0] = 0x02;
+        point[1..].copy_from_slice(hash.as_byte_array());
+        if let Ok(p) = PublicKey::from_slice(&point) {
+            return Some(p);
+        }
+    }
+    None
+}
+
+/// NUT-00 Y = hash_to_curve(secret), the hex used by NUT-07
+pub fn secret_y(secret: &str) -> Option<String> {
+    hash_to_curve(secret.as_bytes()).map(|y| y.to_string())
+}
+
+/// NUT-12 e = hash(R1, R2, A, C_) of the uncompressed keys in hex
+fn hash_e(keys: [&PublicKey; 4]) -> [u8; 32] {
+    let e = keys
+        .iter()
+        .map(|k| hex::encode(k.serialize_uncompressed()))
+        .collect::<String>();
+    sha256::Hash::hash(e.as_bytes()).to_byte_array()
+}
+
+/// C_ = kB_ with the NUT-12 DLEQ (e, s)
+pub fn sign(k: &SecretKey, blinded: &PublicKey) -> Option<(PublicKey, [u8; 32], [u8; 32])> {
+    let c = blinded.mul_tweak(secp(), &Scalar::from(*k)).ok()?;
+
+    let r = SecretKey::new(&mut thread_rng());
+    let r1 = r.public_key(secp());
+    let r2 = blinded.mul_tweak(secp(), &Scalar::from(r)).ok()?;
+    let a = k.public_key(secp());
+
+    let e = hash_e([&r1, &r2, &a, &c]);
+    // s = r + e*k
+    let s = k
+        .mul_tweak(&Scalar::from_be_bytes(e).ok()?)
+        .ok()?
+        .add_tweak(&Scalar::from(r))
+        .ok()?;
+
+    Some((c, e, s.secret_bytes()))
+}
+
+/// C == k*hash_to_curve(secret)
+pub fn verify(k: &SecretKey, secret: &str, c: &PublicKey) -> bool {
+    hash_to_curve(secret.as_bytes())
+        .and_then(|y| y.mul_tweak(secp(), &Scalar::from(*k)).ok())
+        .map(|kc| &kc == c)
+        .unwrap_or_default()
+}
+
+/// the private keys of keyset by amount 2^0..2^(max_order-1)
+pub fn generate_keys(max_order: u32) -> BTreeMap<u64, SecretKey> {
+    let mut rng = thread_rng();
+    (0..max_order)
+        .map(|i| (2u64.pow(i), SecretKey::new(&mut rng)))
+        .collect()
+}
+
+pub fn public_keys(keys: &BTreeMap<u64, SecretKey>) -> BTreeMap<u64, PublicKey> {
+    keys.iter()
+        .map(|(a, k)| (*a, k.public_key(secp())))
+        .collect()
+}
+
+/// NUT-02 keyset id v1: "00" + sha256(keys sorted by amount)[..7]
+pub fn keyset_id(keys: &BTreeMap<u64, PublicKey>) -> String {
+    let bytes = keys
+        .values()
+        .flat_map(|k| k.serialize())
+        .collect::<Vec<u8>>();
+    let hash = sha256::Hash::hash(&bytes);
+    format!("00{}", &hex::encode(hash.as_byte_array())[..14])
+}
+
+pub fn random_hex() -> String {
+    hex::encode(SecretKey::new(&mut thread_rng()).secret_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_to_curve() {
+        // https://github.com/cashubtc/nuts/blob/main/tests/00-tests.md
+        let secret = [0u8; 32];
+        let y = hash_to_curve(&secret).unwrap();
+        assert_eq!(
+            y.to_string(),
+            "024cce997d3b518f739663b757deaec95bcd9473c30a14ac2fd04023a739d1a725"
+        );
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let k = SecretKey::new(&mut thread_rng());
+        let secret = random_hex();
+
+        // blind with r = 1: B_ = Y + G, C = C_ - K
+        let one = SecretKey::from_slice(&[[0u8; 31].as_slice(), &[1]].concat()).unwrap();
+        let y = hash_to_curve(secret.as_bytes()).unwrap();
+        let blinded = y.combine(&one.public_key(secp())).unwrap();
+
+        let (c_, _e, _s) = sign(&k, &blinded).unwrap();
+        let c = c_.combine(&k.public_key(secp()).negate(secp())).unwrap();
+        assert!(verify(&k, &secret, &c));
+        assert!(!verify(&k, &random_hex(), &c));
+    }
+}
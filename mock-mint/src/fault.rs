@@ -0,0 +1,447 @@
+use cashu_wallet::store::{
+    Batch, MintUrlWithUnitOwned, ProofExtended, ProofState, ProofsExtended, Record, UnitedStore,
+    Url,
+};
+use cashu_wallet::types::{
+    Mint, MintKeySet, PendingMelt, PendingOperation, Transaction, TransactionStatus,
+};
+use cashu_wallet::UniError;
+
+use crate::mint::MintError;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// the fault of the mock mint server for the next request of a path, see MockMint::inject_fault
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// 429 {"detail":"Rate limit exceeded."} with Retry-After: 1 without handling the request, the same as nutshell
+    RateLimit,
+    /// reply the error without handling the request
+    Error(MintError),
+    /// never reply and the request is not handled, the client gets a timeout
+    Timeout,
+    /// handle the request but never reply: the mint committed and the client gets a timeout.
+    /// the timeouts of HttpOptions should be set, or the client waits forever
+    TimeoutAfterCommit,
+    /// handle the request but reply the error: the mint committed and the client gets the error
+    ErrorAfterCommit(MintError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FaultyStoreError<E> {
+    /// the injected error of an operation, the later operations succeed
+    #[error("store fails at {0}")]
+    Injected(&'static str),
+    /// the process crashed at the operation, all the operations fail until restart
+    #[error("store crashed at {0}")]
+    Crashed(&'static str),
+    #[error("{0}")]
+    Store(E),
+}
+
+impl<E: std::error::Error> From<FaultyStoreError<E>> for UniError<FaultyStoreError<E>> {
+    fn from(err: FaultyStoreError<E>) -> Self {
+        Self::Store(err)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Points {
+    // operation -> (the nth call from now, crash)
+    points: BTreeMap<&'static str, (usize, bool)>,
+    crashed: Option<&'static str>,
+}
+
+/// the store wrapper injects errors and crashes at the named points, the name of point is
+/// the UnitedStore method such as add_proofs, delete_proofs, add_transaction, add_pending_melt.
+/// the writes of a batch are the points also, and the batch fails as a whole at any of them.
+///
+/// every state of wallet is persisted by the store, so the crash before an operation is the same
+/// as the process died there: the crashed store rejects the later operations of the wallet,
+/// the restarted store is the disk after reboot for a new UnitedWallet
+#[derive(Debug)]
+pub struct FaultyStore<S> {
+    store: Arc<S>,
+    points: Arc<Mutex<Points>>,
+}
+
+impl<S> Clone for FaultyStore<S> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            points: self.points.clone(),
+        }
+    }
+}
+
+impl<S> FaultyStore<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store: Arc::new(store),
+            points: Default::default(),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.store
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Points> {
+        self.points.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// the nth(1 is the next) call of the operation returns an error once
+    pub fn fail_at(&self, op: &'static str, nth: usize) {
+        self.lock().points.insert(op, (nth.max(1), false));
+    }
+
+    /// the process crashes at the nth(1 is the next) call of the operation,
+    /// the operation and all the later are not executed until restart
+    pub fn crash_at(&self, op: &'static str, nth: usize) {
+        self.lock().points.insert(op, (nth.max(1), true));
+    }
+
+    /// the operation crashed at
+    pub fn crashed(&self) -> Option<&'static str> {
+        self.lock().crashed
+    }
+
+    /// reboot after crash, the points not reached are removed also
+    pub fn restart(&self) {
+        let mut points = self.lock();
+        points.points.clear();
+        points.crashed = None;
+    }
+
+    fn hit<E>(&self, op: &'static str) -> Result<(), FaultyStoreError<E>> {
+        let mut points = self.lock();
+        if let Some(at) = points.crashed {
+            return Err(FaultyStoreError::Crashed(at));
+        }
+
+        let crash = match points.points.get_mut(op) {
+            Some((nth, crash)) => {
+                *nth -= 1;
+                if *nth > 0 {
+                    return Ok(());
+                }
+                *crash
+            }
+            None => return Ok(()),
+        };
+        points.points.remove(op);
+
+        if crash {
+            warn!("faulty store crashed at {}", op);
+            points.crashed = Some(op);
+            Err(FaultyStoreError::Crashed(op))
+        } else {
+            warn!("faulty store fails at {}", op);
+            Err(FaultyStoreError::Injected(op))
+        }
+    }
+}
+
+#[async_trait]
+impl<S> UnitedStore for FaultyStore<S>
+where
+    S: UnitedStore + Send + Sync,
+{
+    type Error = FaultyStoreError<S::Error>;
+
+    async fn add_counter(&self, record: &Record) -> Result<(), Self::Error> {
+        self.hit("add_counter")?;
+        self.store
+            .add_counter(record)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn delete_counters(&self, mint_url: &Url) -> Result<(), Self::Error> {
+        self.hit("delete_counters")?;
+        self.store
+            .delete_counters(mint_url)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn get_counters(&self, mint_url: &Url, pubkey: &str) -> Result<Vec<Record>, Self::Error> {
+        self.hit("get_counters")?;
+        self.store
+            .get_counters(mint_url, pubkey)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn delete_proofs(
+        &self,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+    ) -> Result<(), Self::Error> {
+        self.hit("delete_proofs")?;
+        self.store
+            .delete_proofs(mint_url, proofs)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn add_proofs(
+        &self,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+    ) -> Result<(), Self::Error> {
+        self.hit("add_proofs")?;
+        self.store
+            .add_proofs(mint_url, proofs)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn get_proofs_limit_unit(
+        &self,
+        mint_url: &Url,
+        unit: &str,
+    ) -> Result<ProofsExtended, Self::Error> {
+        self.hit("get_proofs_limit_unit")?;
+        self.store
+            .get_proofs_limit_unit(mint_url, unit)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn get_proofs(
+        &self,
+        mint_url: &Url,
+    ) -> Result<BTreeMap<String, ProofsExtended>, Self::Error> {
+        self.hit("get_proofs")?;
+        self.store
+            .get_proofs(mint_url)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn get_all_proofs(
+        &self,
+    ) -> Result<BTreeMap<MintUrlWithUnitOwned, ProofsExtended>, Self::Error> {
+        self.hit("get_all_proofs")?;
+        self.store
+            .get_all_proofs()
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn update_proofs_state(
+        &self,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+        state: ProofState,
+        operation: Option<&str>,
+    ) -> Result<(), Self::Error> {
+        self.hit("update_proofs_state")?;
+        self.store
+            .update_proofs_state(mint_url, proofs, state, operation)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn get_all_proofs_with_state(
+        &self,
+        states: &[ProofState],
+    ) -> Result<BTreeMap<MintUrlWithUnitOwned, ProofsExtended>, Self::Error> {
+        self.hit("get_all_proofs_with_state")?;
+        self.store
+            .get_all_proofs_with_state(states)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn get_proofs_with_state(
+        &self,
+        mint_url: &Url,
+        states: &[ProofState],
+    ) -> Result<ProofsExtended, Self::Error> {
+        self.hit("get_proofs_with_state")?;
+        self.store
+            .get_proofs_with_state(mint_url, states)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn delete_proofs_with_state(
+        &self,
+        states: &[ProofState],
+        unix_timestamp_ms_le: u64,
+    ) -> Result<u64, Self::Error> {
+        self.hit("delete_proofs_with_state")?;
+        self.store
+            .delete_proofs_with_state(states, unix_timestamp_ms_le)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    //
+    async fn migrate(&self) -> Result<(), Self::Error> {
+        self.hit("migrate")?;
+        self.store.migrate().await.map_err(FaultyStoreError::Store)
+    }
+    //
+    // mints
+    async fn add_mint(&self, mint: &Mint) -> Result<(), Self::Error> {
+        self.hit("add_mint")?;
+        self.store
+            .add_mint(mint)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn get_mint(&self, mint_url: &str) -> Result<Option<Mint>, Self::Error> {
+        self.hit("get_mint")?;
+        self.store
+            .get_mint(mint_url)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn get_mints(&self) -> Result<Vec<Mint>, Self::Error> {
+        self.hit("get_mints")?;
+        self.store
+            .get_mints()
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    //
+    // tx
+    async fn delete_transactions(
+        &self,
+        status: &[TransactionStatus],
+        unix_timestamp_ms_le: u64,
+    ) -> Result<u64, Self::Error> {
+        self.hit("delete_transactions")?;
+        self.store
+            .delete_transactions(status, unix_timestamp_ms_le)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn add_transaction(&self, tx: &Transaction) -> Result<(), Self::Error> {
+        self.hit("add_transaction")?;
+        self.store
+            .add_transaction(tx)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn get_transaction(&self, txid: &str) -> Result<Option<Transaction>, Self::Error> {
+        self.hit("get_transaction")?;
+        self.store
+            .get_transaction(txid)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn get_transactions(
+        &self,
+        status: &[TransactionStatus],
+    ) -> Result<Vec<Transaction>, Self::Error> {
+        self.hit("get_transactions")?;
+        self.store
+            .get_transactions(status)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    //
+    // in-flight melts
+    async fn add_pending_melt(&self, melt: &PendingMelt) -> Result<(), Self::Error> {
+        self.hit("add_pending_melt")?;
+        self.store
+            .add_pending_melt(melt)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn delete_pending_melt(&self, mint_url: &str, quote: &str) -> Result<(), Self::Error> {
+        self.hit("delete_pending_melt")?;
+        self.store
+            .delete_pending_melt(mint_url, quote)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn get_pending_melts(&self) -> Result<Vec<PendingMelt>, Self::Error> {
+        self.hit("get_pending_melts")?;
+        self.store
+            .get_pending_melts()
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    //
+    // write-ahead journal of swaps and mints
+    async fn add_operation(&self, op: &PendingOperation) -> Result<(), Self::Error> {
+        self.hit("add_operation")?;
+        self.store
+            .add_operation(op)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn delete_operation(&self, mint_url: &str, id: &str) -> Result<(), Self::Error> {
+        self.hit("delete_operation")?;
+        self.store
+            .delete_operation(mint_url, id)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn get_operations(&self) -> Result<Vec<PendingOperation>, Self::Error> {
+        self.hit("get_operations")?;
+        self.store
+            .get_operations()
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    //
+    // keysets of mints
+    async fn add_keysets(&self, keysets: &[MintKeySet]) -> Result<(), Self::Error> {
+        self.hit("add_keysets")?;
+        self.store
+            .add_keysets(keysets)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    async fn get_keysets(&self, mint_url: &str) -> Result<Vec<MintKeySet>, Self::Error> {
+        self.hit("get_keysets")?;
+        self.store
+            .get_keysets(mint_url)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+    //
+    async fn apply(&self, batch: &Batch) -> Result<(), Self::Error> {
+        self.hit("apply")?;
+        for w in batch.writes() {
+            self.hit(w.name())?;
+        }
+        self.store
+            .apply(batch)
+            .await
+            .map_err(FaultyStoreError::Store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Nothing;
+
+    #[test]
+    fn test_points() {
+        let store = FaultyStore::new(Nothing);
+        let hit = |op| store.hit::<()>(op);
+
+        store.fail_at("add_proofs", 2);
+        assert!(hit("add_proofs").is_ok());
+        assert!(matches!(
+            hit("add_proofs"),
+            Err(FaultyStoreError::Injected("add_proofs"))
+        ));
+        assert!(hit("add_proofs").is_ok());
+
+        store.crash_at("delete_proofs", 1);
+        assert!(hit("add_proofs").is_ok());
+        assert!(matches!(
+            hit("delete_proofs"),
+            Err(FaultyStoreError::Crashed("delete_proofs"))
+        ));
+        assert!(matches!(
+            hit("get_proofs"),
+            Err(FaultyStoreError::Crashed("delete_proofs"))
+        ));
+        assert_eq!(store.crashed(), Some("delete_proofs"));
+
+        store.restart();
+        assert!(hit("get_proofs").is_ok());
+        assert_eq!(store.crashed(), None);
+    }
+}
@@ -0,0 +1,24 @@
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde;
+#[macro_use]
+extern crate async_trait;
+
+/// NUT-00 BDHKE and NUT-12 DLEQ of the mint side
+pub mod bdhke;
+
+/// fake Lightning node for the quotes
+pub mod lightning;
+
+/// inject the network faults of mint and the store errors/crashes of wallet
+mod fault;
+pub use fault::*;
+
+/// in-memory mint state and NUT handlers
+mod mint;
+pub use mint::*;
+
+/// serve the mock mint over local http
+mod server;
+pub use server::*;
@@ -0,0 +1,57 @@
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::rand::{thread_rng, RngCore};
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use lightning_invoice::{Bolt11Invoice, Currency, InvoiceBuilder, PaymentSecret};
+
+use std::time::Duration;
+
+/// the fake Lightning node of mock mint, it signs the invoices but never routes payments
+#[derive(Debug, Clone)]
+pub struct FakeNode {
+    key: SecretKey,
+}
+
+impl Default for FakeNode {
+    fn default() -> Self {
+        Self {
+            key: SecretKey::new(&mut thread_rng()),
+        }
+    }
+}
+
+impl FakeNode {
+    /// returns the bolt11 invoice and its preimage(hex), the invoice without amount if amount_msat is None
+    pub fn invoice(&self, amount_msat: Option<u64>, expiry: Duration) -> (String, String) {
+        let mut preimage = [0u8; 32];
+        let mut payment_secret = [0u8; 32];
+        thread_rng().fill_bytes(&mut preimage);
+        thread_rng().fill_bytes(&mut payment_secret);
+        let payment_hash = sha256::Hash::hash(&preimage);
+
+        let builder = InvoiceBuilder::new(Currency::Bitcoin)
+            .description("cashu mock mint".to_owned())
+            .payment_hash(payment_hash)
+            .payment_secret(PaymentSecret(payment_secret))
+            .current_timestamp()
+            .min_final_cltv_expiry_delta(144)
+            .expiry_time(expiry);
+
+        let secp = Secp256k1::new();
+        let sign = |hash: &Message| secp.sign_ecdsa_recoverable(hash, &self.key);
+        let invoice = match amount_msat {
+            Some(a) => builder.amount_milli_satoshis(a).build_signed(sign),
+            None => builder.build_signed(sign),
+        }
+        .expect("build fake invoice");
+
+        (invoice.to_string(), hex::encode(preimage))
+    }
+}
+
+/// the amount(msat) and payment_hash(hex) of the invoice
+pub fn decode_invoice(invoice: &str) -> Result<(Option<u64>, String), String> {
+    let invoice: Bolt11Invoice = invoice.parse().map_err(|e| e.to_string())?;
+    let payment_hash = hex::encode(invoice.payment_hash().as_byte_array());
+
+    Ok((invoice.amount_milli_satoshis(), payment_hash))
+}
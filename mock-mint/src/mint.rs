@@ -0,0 +1,1036 @@
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use serde_json::{json, Value};
+
+use crate::bdhke;
+use crate::fault::Fault;
+use crate::lightning::{decode_invoice, FakeNode};
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const UNIT_SAT: &str = "sat";
+
+/// the error response of mint, the detail is the same as nutshell
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MintError {
+    pub code: i32,
+    pub detail: String,
+}
+
+impl MintError {
+    pub fn new(code: i32, detail: impl Into<String>) -> Self {
+        Self {
+            code,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for MintError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.code, self.detail)
+    }
+}
+
+impl std::error::Error for MintError {}
+
+/// Blinded Message [NUT-00]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlindedMessage {
+    pub amount: u64,
+    pub id: String,
+    #[serde(rename = "B_")]
+    pub b: String,
+}
+
+/// Proof [NUT-00], the spending conditions are not verified by mock mint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proof {
+    pub amount: u64,
+    pub id: String,
+    pub secret: String,
+    #[serde(rename = "C")]
+    pub c: String,
+}
+
+/// Blind Signature [NUT-00] with DLEQ [NUT-12]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlindSignature {
+    pub amount: u64,
+    pub id: String,
+    #[serde(rename = "C_")]
+    pub c: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dleq: Option<Dleq>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dleq {
+    pub e: String,
+    pub s: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SwapRequest {
+    pub inputs: Vec<Proof>,
+    pub outputs: Vec<BlindedMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct MintQuoteRequest {
+    pub amount: u64,
+    pub unit: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct MintRequest {
+    pub quote: String,
+    pub outputs: Vec<BlindedMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct MeltQuoteRequest {
+    pub request: String,
+    pub unit: String,
+    #[serde(default)]
+    pub options: Option<MeltOptions>,
+}
+
+/// NUT-05 amountless
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct MeltOptions {
+    pub amountless: Amountless,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Amountless {
+    pub amount_msat: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct MeltRequest {
+    pub quote: String,
+    pub inputs: Vec<Proof>,
+    #[serde(default)]
+    pub outputs: Option<Vec<BlindedMessage>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CheckStateRequest {
+    #[serde(rename = "Ys")]
+    pub ys: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct RestoreRequest {
+    pub outputs: Vec<BlindedMessage>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum MintQuoteState {
+    Unpaid,
+    Paid,
+    Issued,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum MeltQuoteState {
+    Unpaid,
+    Pending,
+    Paid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ProofState {
+    Unspent,
+    Pending,
+    Spent,
+}
+
+/// NUT-04 mint quote
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MintQuote {
+    pub quote: String,
+    pub request: String,
+    pub amount: u64,
+    pub unit: String,
+    // legacy
+    pub paid: bool,
+    pub state: MintQuoteState,
+    pub expiry: u64,
+}
+
+impl MintQuote {
+    fn set_state(&mut self, state: MintQuoteState) {
+        self.paid = state != MintQuoteState::Unpaid;
+        self.state = state;
+    }
+}
+
+/// NUT-05 melt quote, the melt response also
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MeltQuote {
+    pub quote: String,
+    pub amount: u64,
+    pub fee_reserve: u64,
+    pub unit: String,
+    // legacy
+    pub paid: bool,
+    pub state: MeltQuoteState,
+    pub expiry: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_preimage: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<Vec<BlindSignature>>,
+    #[serde(skip)]
+    pub request: String,
+    #[serde(skip)]
+    payment_hash: String,
+    // the Ys of inputs and the amount of them excludes input fee
+    #[serde(skip)]
+    inputs: Vec<String>,
+    #[serde(skip)]
+    inputs_amount: u64,
+    #[serde(skip)]
+    outputs: Vec<BlindedMessage>,
+}
+
+impl MeltQuote {
+    fn set_state(&mut self, state: MeltQuoteState) {
+        self.paid = state == MeltQuoteState::Paid;
+        self.state = state;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeySetInfo {
+    pub id: String,
+    pub unit: String,
+    pub active: bool,
+    pub input_fee_ppk: u64,
+}
+
+/// the options of mock mint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockMintOptions {
+    /// NUT-02 input_fee_ppk of the keysets
+    pub input_fee_ppk: u64,
+    /// NUT-05 fee_reserve of the melt quotes, the fake payment costs no fee so it's returned as NUT-08 change
+    pub fee_reserve: u64,
+    /// keys of amount 2^0..2^(max_order-1)
+    pub max_order: u32,
+    /// the mint quotes are paid on creation
+    pub auto_pay_mint_quotes: bool,
+    /// the melts keep pending until settle_melt_quote
+    pub melt_pending: bool,
+    /// NUT-05 amountless
+    pub amountless: bool,
+    /// NUT-12 DLEQ of the signatures
+    pub dleq: bool,
+    /// the DLEQ of the signatures is invalid
+    pub bad_dleq: bool,
+}
+
+impl Default for MockMintOptions {
+    fn default() -> Self {
+        Self {
+            input_fee_ppk: 0,
+            fee_reserve: 2,
+            max_order: 32,
+            auto_pay_mint_quotes: false,
+            melt_pending: false,
+            amountless: true,
+            dleq: true,
+            bad_dleq: false,
+        }
+    }
+}
+
+impl MockMintOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn input_fee_ppk(mut self, ppk: u64) -> Self {
+        self.input_fee_ppk = ppk;
+        self
+    }
+
+    pub fn fee_reserve(mut self, fee: u64) -> Self {
+        self.fee_reserve = fee;
+        self
+    }
+
+    pub fn max_order(mut self, max_order: u32) -> Self {
+        self.max_order = max_order;
+        self
+    }
+
+    pub fn auto_pay_mint_quotes(mut self, b: bool) -> Self {
+        self.auto_pay_mint_quotes = b;
+        self
+    }
+
+    pub fn melt_pending(mut self, b: bool) -> Self {
+        self.melt_pending = b;
+        self
+    }
+
+    pub fn amountless(mut self, b: bool) -> Self {
+        self.amountless = b;
+        self
+    }
+
+    pub fn dleq(mut self, b: bool) -> Self {
+        self.dleq = b;
+        self
+    }
+
+    pub fn bad_dleq(mut self, b: bool) -> Self {
+        self.bad_dleq = b;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MintKeySet {
+    id: String,
+    unit: String,
+    active: bool,
+    input_fee_ppk: u64,
+    keys: BTreeMap<u64, SecretKey>,
+}
+
+impl MintKeySet {
+    fn generate(unit: &str, input_fee_ppk: u64, max_order: u32) -> Self {
+        let keys = bdhke::generate_keys(max_order);
+        Self {
+            id: bdhke::keyset_id(&bdhke::public_keys(&keys)),
+            unit: unit.to_owned(),
+            active: true,
+            input_fee_ppk,
+            keys,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let keys = bdhke::public_keys(&self.keys)
+            .into_iter()
+            .map(|(a, k)| (a.to_string(), Value::String(k.to_string())))
+            .collect::<serde_json::Map<_, _>>();
+        json!({"id": self.id, "unit": self.unit, "keys": keys})
+    }
+
+    fn info(&self) -> KeySetInfo {
+        KeySetInfo {
+            id: self.id.clone(),
+            unit: self.unit.clone(),
+            active: self.active,
+            input_fee_ppk: self.input_fee_ppk,
+        }
+    }
+
+    fn key(&self, amount: u64) -> Result<&SecretKey, MintError> {
+        self.keys
+            .get(&amount)
+            .ok_or_else(|| MintError::new(10000, format!("invalid amount: {}", amount)))
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    options: MockMintOptions,
+    pubkey: String,
+    node: FakeNode,
+    keysets: Vec<MintKeySet>,
+    // Y -> state
+    proofs: BTreeMap<String, ProofState>,
+    // B_ -> signature, for NUT-09 restore
+    signatures: BTreeMap<String, BlindSignature>,
+    mint_quotes: BTreeMap<String, MintQuote>,
+    melt_quotes: BTreeMap<String, MeltQuote>,
+    // payment_hash -> (preimage, mint quote)
+    invoices: BTreeMap<String, (String, Option<String>)>,
+    // request path -> the faults of the next requests
+    faults: BTreeMap<String, VecDeque<Fault>>,
+}
+
+/// the in-memory mint: BDHKE blind signing with DLEQ, keysets, NUT-07 states, NUT-09 restore
+/// and fake Lightning quotes settled manually
+#[derive(Debug, Clone)]
+pub struct MockMint {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for MockMint {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+const QUOTE_EXPIRY: Duration = Duration::from_secs(3600);
+
+fn quote_not_found(quote: &str) -> MintError {
+    MintError::new(20000, format!("quote not found: {}", quote))
+}
+
+impl MockMint {
+    /// the mint with an active sat keyset
+    pub fn new(options: MockMintOptions) -> Self {
+        let keyset = MintKeySet::generate(UNIT_SAT, options.input_fee_ppk, options.max_order);
+        let pubkey = SecretKey::new(&mut bitcoin::secp256k1::rand::thread_rng())
+            .public_key(&bitcoin::secp256k1::Secp256k1::new())
+            .to_string();
+
+        let state = State {
+            options,
+            pubkey,
+            node: Default::default(),
+            keysets: vec![keyset],
+            proofs: Default::default(),
+            signatures: Default::default(),
+            mint_quotes: Default::default(),
+            melt_quotes: Default::default(),
+            invoices: Default::default(),
+            faults: Default::default(),
+        };
+
+        Self {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn options(&self) -> MockMintOptions {
+        self.lock().options.clone()
+    }
+
+    pub fn update_options(&self, f: impl FnOnce(&mut MockMintOptions)) {
+        f(&mut self.lock().options)
+    }
+
+    /// inject the fault into the next request of the path(such as /v1/swap),
+    /// the faults of a path are taken one per request in order
+    pub fn inject_fault(&self, path: &str, fault: Fault) {
+        self.lock()
+            .faults
+            .entry(path.to_owned())
+            .or_default()
+            .push_back(fault);
+    }
+
+    /// remove the faults not taken yet
+    pub fn clear_faults(&self) {
+        self.lock().faults.clear();
+    }
+
+    pub(crate) fn take_fault(&self, path: &str) -> Option<Fault> {
+        let mut state = self.lock();
+
+        let faults = state.faults.get_mut(path)?;
+        let fault = faults.pop_front();
+        if faults.is_empty() {
+            state.faults.remove(path);
+        }
+        fault
+    }
+
+    /// the active keyset id of unit
+    pub fn active_keyset_id(&self, unit: &str) -> Option<String> {
+        self.lock()
+            .keysets
+            .iter()
+            .find(|ks| ks.active && ks.unit == unit)
+            .map(|ks| ks.id.clone())
+    }
+
+    /// NUT-02 rotate the keys: the active keysets of unit become inactive, returns the new keyset id
+    pub fn rotate_keyset(&self, unit: &str, input_fee_ppk: u64) -> String {
+        let mut state = self.lock();
+
+        state
+            .keysets
+            .iter_mut()
+            .filter(|ks| ks.unit == unit)
+            .for_each(|ks| ks.active = false);
+
+        let keyset = MintKeySet::generate(unit, input_fee_ppk, state.options.max_order);
+        let id = keyset.id.clone();
+        state.keysets.push(keyset);
+        id
+    }
+
+    /// the invoice of the fake Lightning node, melting it always succeeds
+    pub fn invoice(&self, amount_msat: Option<u64>) -> String {
+        let mut state = self.lock();
+
+        let (invoice, preimage) = state.node.invoice(amount_msat, QUOTE_EXPIRY);
+        let (_, payment_hash) = decode_invoice(&invoice).expect("decode fake invoice");
+        state.invoices.insert(payment_hash, (preimage, None));
+        invoice
+    }
+
+    /// settle the mint quote as the invoice paid, returns false if not found or not unpaid
+    pub fn pay_mint_quote(&self, quote: &str) -> bool {
+        let mut state = self.lock();
+
+        match state.mint_quotes.get_mut(quote) {
+            Some(q) if q.state == MintQuoteState::Unpaid => {
+                q.set_state(MintQuoteState::Paid);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// settle the pending melt quote: paid or failed, returns false if not found or not pending
+    pub fn settle_melt_quote(&self, quote: &str, paid: bool) -> bool {
+        let mut state = self.lock();
+
+        match state.melt_quotes.get(quote) {
+            Some(q) if q.state == MeltQuoteState::Pending => {}
+            _ => return false,
+        }
+        state.settle_melt(quote, paid);
+        true
+    }
+
+    pub fn mint_quote(&self, quote: &str) -> Option<MintQuote> {
+        self.lock().mint_quotes.get(quote).cloned()
+    }
+
+    pub fn melt_quote(&self, quote: &str) -> Option<MeltQuote> {
+        self.lock().melt_quotes.get(quote).cloned()
+    }
+
+    /// NUT-07 state of Y(hex)
+    pub fn proof_state(&self, y: &str) -> ProofState {
+        self.lock()
+            .proofs
+            .get(y)
+            .copied()
+            .unwrap_or(ProofState::Unspent)
+    }
+
+    /// NUT-06
+    pub fn info(&self) -> Value {
+        let state = self.lock();
+
+        let units = state
+            .keysets
+            .iter()
+            .map(|ks| ks.unit.as_str())
+            .collect::<BTreeSet<_>>();
+        let methods = |amountless: bool| {
+            units
+                .iter()
+                .map(|unit| {
+                    let mut m = json!({"method": "bolt11", "unit": unit, "min_amount": 0, "max_amount": 1_000_000});
+                    if amountless {
+                        m["options"] = json!({"amountless": true});
+                    }
+                    m
+                })
+                .collect::<Vec<_>>()
+        };
+
+        json!({
+            "name": "Cashu mock mint",
+            "pubkey": state.pubkey,
+            "version": "Mock/0.1.0",
+            "description": "The in-memory mint for testing",
+            "contact": [],
+            "nuts": {
+                "4": {"methods": methods(false), "disabled": false},
+                "5": {"methods": methods(state.options.amountless), "disabled": false},
+                "7": {"supported": true},
+                "8": {"supported": true},
+                "9": {"supported": true},
+                "10": {"supported": true},
+                "11": {"supported": true},
+                "12": {"supported": state.options.dleq},
+            }
+        })
+    }
+
+    /// NUT-01 the active keysets or the keyset of id
+    pub fn keys(&self, id: Option<&str>) -> Result<Value, MintError> {
+        let state = self.lock();
+
+        let keysets = match id {
+            Some(id) => vec![state.keyset(id)?.to_json()],
+            None => state
+                .keysets
+                .iter()
+                .filter(|ks| ks.active)
+                .map(|ks| ks.to_json())
+                .collect(),
+        };
+
+        Ok(json!({ "keysets": keysets }))
+    }
+
+    /// NUT-02
+    pub fn keysets(&self) -> Vec<KeySetInfo> {
+        self.lock().keysets.iter().map(|ks| ks.info()).collect()
+    }
+
+    /// NUT-03
+    pub fn swap(&self, request: SwapRequest) -> Result<Value, MintError> {
+        let mut state = self.lock();
+
+        let ys = state.verify_inputs(&request.inputs)?;
+        let unit = state.verify_units(&request.inputs, &request.outputs)?;
+        let fee = state.input_fee(&request.inputs);
+
+        let amount_in = request.inputs.iter().map(|p| p.amount).sum::<u64>();
+        let amount_out = request.outputs.iter().map(|o| o.amount).sum::<u64>();
+        if amount_in != amount_out + fee {
+            return Err(MintError::new(
+                11002,
+                format!(
+                    "inputs ({}) - fees ({}) vs outputs ({}) are not balanced.",
+                    amount_in, fee, amount_out
+                ),
+            ));
+        }
+        debug!("swap {} {} -> {}, fee {}", unit, amount_in, amount_out, fee);
+
+        let signatures = state.sign_outputs(&request.outputs)?;
+        for y in ys {
+            state.proofs.insert(y, ProofState::Spent);
+        }
+
+        Ok(json!({ "signatures": signatures }))
+    }
+
+    /// NUT-04
+    pub fn create_mint_quote(&self, request: MintQuoteRequest) -> Result<MintQuote, MintError> {
+        let mut state = self.lock();
+
+        if !state.keysets.iter().any(|ks| ks.unit == request.unit) {
+            return Err(MintError::new(11005, "unit is not supported."));
+        }
+        if request.amount == 0 {
+            return Err(MintError::new(11006, "invalid amount: 0"));
+        }
+
+        let (invoice, preimage) = state
+            .node
+            .invoice(Some(request.amount * 1000), QUOTE_EXPIRY);
+        let (_, payment_hash) = decode_invoice(&invoice).expect("decode fake invoice");
+
+        let mut quote = MintQuote {
+            quote: bdhke::random_hex(),
+            request: invoice,
+            amount: request.amount,
+            unit: request.unit,
+            paid: false,
+            state: MintQuoteState::Unpaid,
+            expiry: now() + QUOTE_EXPIRY.as_secs(),
+        };
+        if state.options.auto_pay_mint_quotes {
+            quote.set_state(MintQuoteState::Paid);
+        }
+
+        state
+            .invoices
+            .insert(payment_hash, (preimage, Some(quote.quote.clone())));
+        state.mint_quotes.insert(quote.quote.clone(), quote.clone());
+
+        Ok(quote)
+    }
+
+    pub fn mint_quote_state(&self, quote: &str) -> Result<MintQuote, MintError> {
+        self.mint_quote(quote).ok_or_else(|| quote_not_found(quote))
+    }
+
+    /// NUT-04
+    pub fn mint(&self, request: MintRequest) -> Result<Value, MintError> {
+        let mut state = self.lock();
+
+        let quote = state
+            .mint_quotes
+            .get(&request.quote)
+            .cloned()
+            .ok_or_else(|| quote_not_found(&request.quote))?;
+
+        match quote.state {
+            MintQuoteState::Unpaid => {
+                return Err(MintError::new(20001, "Lightning invoice not paid yet."))
+            }
+            MintQuoteState::Issued => return Err(MintError::new(20002, "quote already issued.")),
+            MintQuoteState::Paid => {}
+        }
+
+        state.verify_units(&[], &request.outputs)?;
+        let amount = request.outputs.iter().map(|o| o.amount).sum::<u64>();
+        if amount != quote.amount {
+            return Err(MintError::new(
+                11002,
+                format!(
+                    "outputs ({}) vs quote ({}) are not balanced.",
+                    amount, quote.amount
+                ),
+            ));
+        }
+
+        let signatures = state.sign_outputs(&request.outputs)?;
+        if let Some(q) = state.mint_quotes.get_mut(&request.quote) {
+            q.set_state(MintQuoteState::Issued);
+        }
+
+        Ok(json!({ "signatures": signatures }))
+    }
+
+    /// NUT-05
+    pub fn create_melt_quote(&self, request: MeltQuoteRequest) -> Result<MeltQuote, MintError> {
+        let mut state = self.lock();
+
+        if !state.keysets.iter().any(|ks| ks.unit == request.unit) {
+            return Err(MintError::new(11005, "unit is not supported."));
+        }
+
+        let (amount_msat, payment_hash) =
+            decode_invoice(&request.request).map_err(|e| MintError::new(10000, e))?;
+        let amount_msat = match (amount_msat, request.options) {
+            (Some(a), None) => a,
+            (Some(a), Some(o)) if o.amountless.amount_msat == a => a,
+            (Some(_), Some(_)) => {
+                return Err(MintError::new(
+                    10000,
+                    "amount in options unmatch the invoice.",
+                ))
+            }
+            (None, Some(o)) if state.options.amountless => o.amountless.amount_msat,
+            (None, _) => return Err(MintError::new(0, "invoice has no amount.")),
+        };
+
+        let quote = MeltQuote {
+            quote: bdhke::random_hex(),
+            amount: (amount_msat + 999) / 1000,
+            fee_reserve: state.options.fee_reserve,
+            unit: request.unit,
+            paid: false,
+            state: MeltQuoteState::Unpaid,
+            expiry: now() + QUOTE_EXPIRY.as_secs(),
+            payment_preimage: None,
+            change: None,
+            request: request.request,
+            payment_hash,
+            inputs: vec![],
+            inputs_amount: 0,
+            outputs: vec![],
+        };
+        state.melt_quotes.insert(quote.quote.clone(), quote.clone());
+
+        Ok(quote)
+    }
+
+    pub fn melt_quote_state(&self, quote: &str) -> Result<MeltQuote, MintError> {
+        self.melt_quote(quote).ok_or_else(|| quote_not_found(quote))
+    }
+
+    /// NUT-05 melt with NUT-08 blank outputs, the payment is pending if melt_pending
+    pub fn melt(&self, request: MeltRequest) -> Result<MeltQuote, MintError> {
+        let mut state = self.lock();
+
+        let quote = state
+            .melt_quotes
+            .get(&request.quote)
+            .cloned()
+            .ok_or_else(|| quote_not_found(&request.quote))?;
+
+        match quote.state {
+            MeltQuoteState::Paid => return Err(MintError::new(20006, "Invoice already paid.")),
+            MeltQuoteState::Pending => return Err(MintError::new(20005, "Quote is pending.")),
+            MeltQuoteState::Unpaid => {}
+        }
+
+        let outputs = request.outputs.unwrap_or_default();
+        let ys = state.verify_inputs(&request.inputs)?;
+        state.verify_units(&request.inputs, &outputs)?;
+        if request.inputs.iter().any(|p| {
+            state
+                .keyset(&p.id)
+                .map(|ks| ks.unit != quote.unit)
+                .unwrap_or(true)
+        }) {
+            return Err(MintError::new(
+                11005,
+                "input unit does not match the quote.",
+            ));
+        }
+
+        let fee = state.input_fee(&request.inputs);
+        let amount_in = request.inputs.iter().map(|p| p.amount).sum::<u64>();
+        if amount_in < quote.amount + quote.fee_reserve + fee {
+            return Err(MintError::new(
+                11002,
+                format!(
+                    "not enough inputs provided for melt. Provided: {}, needed: {}",
+                    amount_in,
+                    quote.amount + quote.fee_reserve + fee
+                ),
+            ));
+        }
+
+        for y in &ys {
+            state.proofs.insert(y.clone(), ProofState::Pending);
+        }
+        if let Some(q) = state.melt_quotes.get_mut(&request.quote) {
+            q.inputs = ys;
+            q.inputs_amount = amount_in - fee;
+            q.outputs = outputs;
+            q.set_state(MeltQuoteState::Pending);
+        }
+
+        if !state.options.melt_pending {
+            state.settle_melt(&request.quote, true);
+        }
+
+        Ok(state.melt_quotes[&request.quote].clone())
+    }
+
+    /// NUT-07
+    pub fn check_state(&self, request: CheckStateRequest) -> Value {
+        let state = self.lock();
+
+        let states = request
+            .ys
+            .iter()
+            .map(|y| {
+                let s = state.proofs.get(y).copied().unwrap_or(ProofState::Unspent);
+                json!({"Y": y, "state": s, "witness": null})
+            })
+            .collect::<Vec<_>>();
+
+        json!({ "states": states })
+    }
+
+    /// NUT-09 the outputs signed before as requested and their signatures
+    pub fn restore(&self, request: RestoreRequest) -> Value {
+        let state = self.lock();
+
+        let (outputs, signatures): (Vec<_>, Vec<_>) = request
+            .outputs
+            .iter()
+            .filter_map(|o| state.signatures.get(&o.b).map(|s| (o.clone(), s.clone())))
+            .unzip();
+
+        // promises is deprecated, but still returned by nutshell
+        json!({ "outputs": outputs, "signatures": signatures, "promises": signatures })
+    }
+}
+
+impl State {
+    fn keyset(&self, id: &str) -> Result<&MintKeySet, MintError> {
+        self.keysets
+            .iter()
+            .find(|ks| ks.id == id)
+            .ok_or_else(|| MintError::new(12001, format!("keyset {} is not known.", id)))
+    }
+
+    /// NUT-02 fees = ceil(sum(input_fee_ppk) / 1000)
+    fn input_fee(&self, inputs: &[Proof]) -> u64 {
+        let ppk = inputs
+            .iter()
+            .filter_map(|p| self.keyset(&p.id).ok())
+            .map(|ks| ks.input_fee_ppk)
+            .sum::<u64>();
+        (ppk + 999) / 1000
+    }
+
+    /// all inputs and outputs are the same unit
+    fn verify_units(
+        &self,
+        inputs: &[Proof],
+        outputs: &[BlindedMessage],
+    ) -> Result<String, MintError> {
+        let mut units = BTreeSet::new();
+        for id in inputs
+            .iter()
+            .map(|p| &p.id)
+            .chain(outputs.iter().map(|o| &o.id))
+        {
+            units.insert(self.keyset(id)?.unit.clone());
+        }
+
+        match units.len() {
+            0 | 1 => Ok(units.pop_first().unwrap_or_else(|| UNIT_SAT.to_owned())),
+            _ => Err(MintError::new(
+                11005,
+                "inputs and outputs have different units.",
+            )),
+        }
+    }
+
+    /// returns the Ys of inputs if they are valid and unspent
+    fn verify_inputs(&self, inputs: &[Proof]) -> Result<Vec<String>, MintError> {
+        if inputs.is_empty() {
+            return Err(MintError::new(11002, "no inputs provided."));
+        }
+
+        let mut ys = Vec::with_capacity(inputs.len());
+        for p in inputs {
+            let keyset = self.keyset(&p.id)?;
+            let k = keyset.key(p.amount)?;
+
+            let c: PublicKey =
+                p.c.parse()
+                    .map_err(|_| MintError::new(10003, "could not verify proofs."))?;
+            if !bdhke::verify(k, &p.secret, &c) {
+                return Err(MintError::new(10003, "could not verify proofs."));
+            }
+
+            let y = bdhke::secret_y(&p.secret)
+                .ok_or_else(|| MintError::new(10003, "could not verify proofs."))?;
+            if ys.contains(&y) {
+                return Err(MintError::new(11007, "duplicate inputs."));
+            }
+            match self.proofs.get(&y) {
+                Some(ProofState::Spent) => {
+                    return Err(MintError::new(11001, "Token already spent."))
+                }
+                Some(ProofState::Pending) => {
+                    return Err(MintError::new(11003, "Token is pending."))
+                }
+                _ => {}
+            }
+            ys.push(y);
+        }
+
+        Ok(ys)
+    }
+
+    /// sign all outputs or none of them
+    fn sign_outputs(
+        &mut self,
+        outputs: &[BlindedMessage],
+    ) -> Result<Vec<BlindSignature>, MintError> {
+        let mut blinded = Vec::with_capacity(outputs.len());
+        for o in outputs {
+            let keyset = self.keyset(&o.id)?;
+            if !keyset.active {
+                return Err(MintError::new(
+                    12002,
+                    format!("keyset {} is inactive.", o.id),
+                ));
+            }
+            keyset.key(o.amount)?;
+
+            if self.signatures.contains_key(&o.b)
+                || outputs.iter().filter(|x| x.b == o.b).count() > 1
+            {
+                return Err(MintError::new(
+                    10002,
+                    "outputs have already been signed before.",
+                ));
+            }
+
+            let b: PublicKey =
+                o.b.parse()
+                    .map_err(|_| MintError::new(10000, "invalid blinded message."))?;
+            blinded.push(b);
+        }
+
+        let mut signatures = Vec::with_capacity(outputs.len());
+        for (o, b) in outputs.iter().zip(blinded) {
+            let k = self.keyset(&o.id)?.key(o.amount)?;
+            let (c, mut e, mut s) = bdhke::sign(k, &b)
+                .ok_or_else(|| MintError::new(10000, "invalid blinded message."))?;
+            if self.options.bad_dleq {
+                std::mem::swap(&mut e, &mut s);
+            }
+
+            let signature = BlindSignature {
+                amount: o.amount,
+                id: o.id.clone(),
+                c: c.to_string(),
+                dleq: self.options.dleq.then(|| Dleq {
+                    e: hex::encode(e),
+                    s: hex::encode(s),
+                }),
+            };
+            self.signatures.insert(o.b.clone(), signature.clone());
+            signatures.push(signature);
+        }
+
+        Ok(signatures)
+    }
+
+    /// the fake payment costs nothing, the overpaid is returned by NUT-08 change
+    fn settle_melt(&mut self, quote: &str, paid: bool) {
+        let mut q = match self.melt_quotes.get(quote) {
+            Some(q) => q.clone(),
+            None => return,
+        };
+
+        if !paid {
+            for y in &q.inputs {
+                self.proofs.remove(y);
+            }
+            q.set_state(MeltQuoteState::Unpaid);
+            self.melt_quotes.insert(q.quote.clone(), q);
+            return;
+        }
+
+        for y in &q.inputs {
+            self.proofs.insert(y.clone(), ProofState::Spent);
+        }
+
+        let (preimage, mint_quote) = self
+            .invoices
+            .get(&q.payment_hash)
+            .cloned()
+            .unwrap_or_else(|| (bdhke::random_hex(), None));
+        // pay the invoice of mint quote internally
+        if let Some(mq) = mint_quote.and_then(|mq| self.mint_quotes.get_mut(&mq)) {
+            if mq.state == MintQuoteState::Unpaid {
+                mq.set_state(MintQuoteState::Paid);
+            }
+        }
+
+        let overpaid = q.inputs_amount.saturating_sub(q.amount);
+        if overpaid > 0 && !q.outputs.is_empty() {
+            // the largest amounts first if the blank outputs are not enough
+            let amounts = (0..64u32)
+                .rev()
+                .map(|i| 1u64 << i)
+                .filter(|a| (overpaid & a) > 0);
+            let outputs = q
+                .outputs
+                .iter()
+                .zip(amounts)
+                .map(|(o, amount)| BlindedMessage {
+                    amount,
+                    id: o.id.clone(),
+                    b: o.b.clone(),
+                })
+                .collect::<Vec<_>>();
+
+            match self.sign_outputs(&outputs) {
+                Ok(change) => q.change = Some(change),
+                Err(e) => warn!("melt {} sign change failed: {}", q.quote, e),
+            }
+        }
+
+        q.payment_preimage = Some(preimage);
+        q.set_state(MeltQuoteState::Paid);
+        self.melt_quotes.insert(q.quote.clone(), q);
+    }
+}
@@ -0,0 +1,214 @@
+use axum::extract::{Path, Request, State};
+use axum::http::header::RETRY_AFTER;
+use axum::http::StatusCode;
+use axum::middleware::{from_fn_with_state, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::json;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::fault::Fault;
+use crate::mint::*;
+
+/// the mock mint served over a local http port, the server stops on drop
+#[derive(Debug)]
+pub struct MockMintServer {
+    mint: MockMint,
+    url: String,
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockMintServer {
+    /// serve the mint on 127.0.0.1 with a random port
+    pub async fn start(mint: MockMint) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (task, shutdown) = serve(listener, mint.clone());
+
+        Ok(Self {
+            mint,
+            url: format!("http://{}/", addr),
+            addr,
+            task,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// http://127.0.0.1:port/
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn mint(&self) -> &MockMint {
+        &self.mint
+    }
+
+    /// close the port and the idle connections, the clients get the connect errors as the mint is offline
+    pub async fn stop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+            let _ = (&mut self.task).await;
+        }
+    }
+
+    /// serve the mint again on the same port after stop
+    pub async fn resume(&mut self) -> std::io::Result<()> {
+        if self.shutdown.is_none() {
+            let listener = TcpListener::bind(self.addr).await?;
+            let (task, shutdown) = serve(listener, self.mint.clone());
+            self.task = task;
+            self.shutdown = Some(shutdown);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for MockMintServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn serve(listener: TcpListener, mint: MockMint) -> (JoinHandle<()>, oneshot::Sender<()>) {
+    let (tx, rx) = oneshot::channel::<()>();
+
+    let app = router(mint);
+    let task = tokio::spawn(async move {
+        let shutdown = async move {
+            let _ = rx.await;
+        };
+        if let Err(e) = axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown)
+            .await
+        {
+            error!("mock mint server stopped: {}", e);
+        }
+    });
+
+    (task, tx)
+}
+
+pub fn router(mint: MockMint) -> Router {
+    Router::new()
+        .route("/v1/info", get(info))
+        .route("/v1/keys", get(keys))
+        .route("/v1/keys/:id", get(keys_by_id))
+        .route("/v1/keysets", get(keysets))
+        .route("/v1/swap", post(swap))
+        .route("/v1/mint/quote/bolt11", post(mint_quote))
+        .route("/v1/mint/quote/bolt11/:quote", get(mint_quote_state))
+        .route("/v1/mint/bolt11", post(mint))
+        .route("/v1/melt/quote/bolt11", post(melt_quote))
+        .route("/v1/melt/quote/bolt11/:quote", get(melt_quote_state))
+        .route("/v1/melt/bolt11", post(melt))
+        .route("/v1/checkstate", post(check_state))
+        .route("/v1/restore", post(restore))
+        .layer(from_fn_with_state(mint.clone(), inject_fault))
+        .with_state(mint)
+}
+
+async fn inject_fault(State(mint): State<MockMint>, request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_owned();
+    let fault = match mint.take_fault(&path) {
+        Some(f) => f,
+        None => return next.run(request).await,
+    };
+    debug!("mock mint injects fault {:?} into {}", fault, path);
+
+    match fault {
+        Fault::RateLimit => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(RETRY_AFTER, "1")],
+            Json(json!({ "detail": "Rate limit exceeded." })),
+        )
+            .into_response(),
+        Fault::Error(e) => (StatusCode::BAD_REQUEST, Json(e)).into_response(),
+        Fault::Timeout => std::future::pending().await,
+        Fault::TimeoutAfterCommit => {
+            let _ = next.run(request).await;
+            std::future::pending().await
+        }
+        Fault::ErrorAfterCommit(e) => {
+            let _ = next.run(request).await;
+            (StatusCode::BAD_REQUEST, Json(e)).into_response()
+        }
+    }
+}
+
+fn reply<T: serde::Serialize>(res: Result<T, MintError>) -> Response {
+    match res {
+        Ok(t) => Json(t).into_response(),
+        Err(e) => {
+            debug!("mock mint returns error: {}", e);
+            (StatusCode::BAD_REQUEST, Json(e)).into_response()
+        }
+    }
+}
+
+async fn info(State(mint): State<MockMint>) -> Response {
+    Json(mint.info()).into_response()
+}
+
+async fn keys(State(mint): State<MockMint>) -> Response {
+    reply(mint.keys(None))
+}
+
+async fn keys_by_id(State(mint): State<MockMint>, Path(id): Path<String>) -> Response {
+    reply(mint.keys(Some(&id)))
+}
+
+async fn keysets(State(mint): State<MockMint>) -> Response {
+    Json(json!({ "keysets": mint.keysets() })).into_response()
+}
+
+async fn swap(State(mint): State<MockMint>, Json(request): Json<SwapRequest>) -> Response {
+    reply(mint.swap(request))
+}
+
+async fn mint_quote(
+    State(mint): State<MockMint>,
+    Json(request): Json<MintQuoteRequest>,
+) -> Response {
+    reply(mint.create_mint_quote(request))
+}
+
+async fn mint_quote_state(State(mint): State<MockMint>, Path(quote): Path<String>) -> Response {
+    reply(mint.mint_quote_state(&quote))
+}
+
+async fn mint(State(mint): State<MockMint>, Json(request): Json<MintRequest>) -> Response {
+    reply(mint.mint(request))
+}
+
+async fn melt_quote(
+    State(mint): State<MockMint>,
+    Json(request): Json<MeltQuoteRequest>,
+) -> Response {
+    reply(mint.create_melt_quote(request))
+}
+
+async fn melt_quote_state(State(mint): State<MockMint>, Path(quote): Path<String>) -> Response {
+    reply(mint.melt_quote_state(&quote))
+}
+
+async fn melt(State(mint): State<MockMint>, Json(request): Json<MeltRequest>) -> Response {
+    reply(mint.melt(request))
+}
+
+async fn check_state(
+    State(mint): State<MockMint>,
+    Json(request): Json<CheckStateRequest>,
+) -> Response {
+    Json(mint.check_state(request)).into_response()
+}
+
+async fn restore(State(mint): State<MockMint>, Json(request): Json<RestoreRequest>) -> Response {
+    Json(mint.restore(request)).into_response()
+}
@@ -0,0 +1,522 @@
+use cashu_wallet::store::UnitedStore;
+use cashu_wallet::types::TransactionStatus;
+use cashu_wallet::wallet::{DleqPolicy, HttpOptions, MnemonicInfo};
+use cashu_wallet::{UnitedWallet, Url};
+use cashu_wallet_mock::{Fault, FaultyStore, MintError, MockMint, MockMintOptions, MockMintServer};
+use cashu_wallet_sqlite::LitePool;
+
+use std::sync::Arc;
+
+type Store = FaultyStore<LitePool>;
+
+async fn start() -> MockMintServer {
+    let options = MockMintOptions::new().auto_pay_mint_quotes(true);
+    MockMintServer::start(MockMint::new(options)).await.unwrap()
+}
+
+// the faults of Timeout never reply
+fn http() -> HttpOptions {
+    HttpOptions::new()
+        .timeout_get_ms(2000)
+        .timeout_swap_ms(500)
+        .timeout_melt_ms(500)
+}
+
+async fn open(
+    server: &MockMintServer,
+    store: &Store,
+    mi: &Arc<MnemonicInfo>,
+) -> UnitedWallet<Store> {
+    let w = UnitedWallet::with_mnemonic(store.clone(), http(), Some(mi.clone()));
+    w.add_mint(server.url().parse().unwrap(), false)
+        .await
+        .unwrap();
+    w
+}
+
+/// the wallet with 100 sats minted
+async fn funded(server: &MockMintServer) -> (Store, Arc<MnemonicInfo>, UnitedWallet<Store>) {
+    let store = FaultyStore::new(
+        LitePool::open("sqlite::memory:", Default::default())
+            .await
+            .unwrap(),
+    );
+    let words = MnemonicInfo::generate_words(12).unwrap();
+    let mi = Arc::new(MnemonicInfo::with_words(&words).unwrap());
+
+    let w = open(server, &store, &mi).await;
+    let url = server.url().parse().unwrap();
+    let tx = w.request_mint(&url, 100, None).await.unwrap();
+    w.mint_tokens(&url, 100, tx.id().to_owned(), None)
+        .await
+        .unwrap();
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+
+    (store, mi, w)
+}
+
+/// drop the proofs spent on mint and restore the proofs signed but not stored, returns the balance
+async fn recover(w: &UnitedWallet<Store>, url: &Url) -> u64 {
+    w.check_proofs_in_database().await.unwrap();
+    w.restore(
+        url,
+        10,
+        0,
+        &[],
+        None,
+        |_, _, _, _, _, _, _, _, _, _, _, _| false,
+    )
+    .await
+    .unwrap();
+    w.get_balance_limit_unit(url, None).await.unwrap()
+}
+
+/// the sum of the counters stored
+async fn counters(store: &Store, url: &Url, mi: &MnemonicInfo) -> u64 {
+    let rs = store.inner().get_counters(url, mi.pubkey()).await.unwrap();
+    rs.iter().map(|r| r.counter).sum()
+}
+
+#[tokio::test]
+async fn test_swap_timeout_after_commit() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (_store, _mi, w) = funded(&server).await;
+
+    server
+        .mint()
+        .inject_fault("/v1/swap", Fault::TimeoutAfterCommit);
+    let err = w.send_tokens(&url, 10, None, None, None).await.unwrap_err();
+    assert!(err.is_network_error(), "{}", err);
+
+    // the inputs are spent on mint but still in store
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+    assert_eq!(recover(&w, &url).await, 100);
+
+    w.send_tokens(&url, 10, None, None, None).await.unwrap();
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 90);
+}
+
+#[tokio::test]
+async fn test_swap_timeout_and_rate_limit() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (_store, _mi, w) = funded(&server).await;
+
+    server.mint().inject_fault("/v1/swap", Fault::Timeout);
+    server.mint().inject_fault("/v1/swap", Fault::RateLimit);
+
+    let err = w.send_tokens(&url, 10, None, None, None).await.unwrap_err();
+    assert!(err.is_network_error(), "{}", err);
+    let err = w.send_tokens(&url, 10, None, None, None).await.unwrap_err();
+    assert!(!err.is_network_error(), "{}", err);
+
+    // nothing committed by mint
+    assert_eq!(w.check_proofs_in_database().await.unwrap().0, 0);
+    assert_eq!(recover(&w, &url).await, 100);
+
+    w.send_tokens(&url, 10, None, None, None).await.unwrap();
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 90);
+}
+
+#[tokio::test]
+async fn test_crash_between_swap_and_add_proofs() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (store, mi, w) = funded(&server).await;
+
+    store.crash_at("add_proofs", 1);
+    assert!(w.send_tokens(&url, 10, None, None, None).await.is_err());
+    assert_eq!(store.crashed(), Some("add_proofs"));
+    drop(w);
+
+    store.restart();
+    let w = open(&server, &store, &mi).await;
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+    assert_eq!(recover(&w, &url).await, 100);
+}
+
+#[tokio::test]
+async fn test_store_error_mid_send() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (store, _mi, w) = funded(&server).await;
+
+    // the batch fails as a whole: the keep proofs not stored with the spent inputs
+    store.fail_at("delete_proofs", 1);
+    assert!(w.send_tokens(&url, 10, None, None, None).await.is_err());
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+    assert!(store
+        .inner()
+        .get_pending_transactions()
+        .await
+        .unwrap()
+        .is_empty());
+
+    // the send proofs are not in any transaction
+    assert_eq!(recover(&w, &url).await, 100);
+}
+
+#[tokio::test]
+async fn test_mint_timeout_after_commit() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (_store, _mi, w) = funded(&server).await;
+
+    let tx = w.request_mint(&url, 50, None).await.unwrap();
+    server
+        .mint()
+        .inject_fault("/v1/mint/bolt11", Fault::TimeoutAfterCommit);
+    assert!(w
+        .mint_tokens(&url, 50, tx.id().to_owned(), None)
+        .await
+        .is_err());
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+
+    assert_eq!(recover(&w, &url).await, 150);
+}
+
+#[tokio::test]
+async fn test_receive_timeout_after_commit() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (_store, _mi, alice) = funded(&server).await;
+    let (_store, _mi, bob) = funded(&server).await;
+
+    let tx = alice.send_tokens(&url, 10, None, None, None).await.unwrap();
+
+    // the mint maybe committed on the timeout, it's not received offline
+    server
+        .mint()
+        .inject_fault("/v1/swap", Fault::TimeoutAfterCommit);
+    let err = bob.receive_tokens(tx.content()).await.unwrap_err();
+    assert!(err.is_network_error(), "{}", err);
+    assert_eq!(bob.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+    assert!(bob.get_unswapped_balances().await.unwrap().is_empty());
+
+    bob.check_pendings().await.unwrap();
+    assert_eq!(recover(&bob, &url).await, 110);
+}
+
+#[tokio::test]
+async fn test_receive_offline() {
+    let mut server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (_store, _mi, alice) = funded(&server).await;
+    let (_store, _mi, bob) = funded(&server).await;
+
+    let tx = alice.send_tokens(&url, 10, None, None, None).await.unwrap();
+
+    // the request never reached the mint, the DLEQ verified token is accepted once
+    server.stop().await;
+    assert_eq!(bob.receive_tokens(tx.content()).await.unwrap(), 10);
+    assert!(bob.receive_tokens(tx.content()).await.is_err());
+    assert_eq!(bob.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+
+    let unswapped = bob.get_unswapped_balances().await.unwrap();
+    assert_eq!(unswapped.values().sum::<u64>(), 10);
+    assert_eq!(bob.check_pendings().await.unwrap(), (0, 1));
+
+    server.resume().await.unwrap();
+    assert_eq!(bob.check_pendings().await.unwrap(), (1, 1));
+    assert_eq!(bob.get_balance_limit_unit(&url, None).await.unwrap(), 110);
+    assert!(bob.get_unswapped_balances().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_melt_timeout_after_commit() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (store, _mi, w) = funded(&server).await;
+
+    server
+        .mint()
+        .inject_fault("/v1/melt/bolt11", Fault::TimeoutAfterCommit);
+    let invoice = server.mint().invoice(Some(10_000));
+    let tx = w.melt(&url, invoice, None, None, None).await.unwrap();
+    assert_eq!(tx.status(), TransactionStatus::Pending);
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 88);
+
+    assert_eq!(w.check_pendings().await.unwrap(), (1, 1));
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 90);
+
+    let tx = store.inner().get_transaction(tx.id()).await.unwrap();
+    assert_eq!(tx.unwrap().status(), TransactionStatus::Success);
+}
+
+#[tokio::test]
+async fn test_melt_rate_limit() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (_store, _mi, w) = funded(&server).await;
+
+    // the reserved inputs are released
+    server
+        .mint()
+        .inject_fault("/v1/melt/bolt11", Fault::RateLimit);
+    let invoice = server.mint().invoice(Some(10_000));
+    assert!(w.melt(&url, invoice, None, None, None).await.is_err());
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+    assert_eq!(w.resume_pending_melts().await.unwrap(), (0, 0));
+
+    let invoice = server.mint().invoice(Some(10_000));
+    w.melt(&url, invoice, None, None, None).await.unwrap();
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 90);
+}
+
+#[tokio::test]
+async fn test_melt_error_after_commit() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (store, _mi, w) = funded(&server).await;
+
+    // the quote is paid, the inputs are kept pending
+    let e = MintError::new(10000, "oops.");
+    server
+        .mint()
+        .inject_fault("/v1/melt/bolt11", Fault::ErrorAfterCommit(e));
+    let invoice = server.mint().invoice(Some(10_000));
+    let tx = w.melt(&url, invoice, None, None, None).await.unwrap();
+    assert_eq!(tx.status(), TransactionStatus::Pending);
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 88);
+
+    assert_eq!(w.check_pendings().await.unwrap(), (1, 1));
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 90);
+
+    let tx = store.inner().get_transaction(tx.id()).await.unwrap();
+    assert_eq!(tx.unwrap().status(), TransactionStatus::Success);
+}
+
+#[tokio::test]
+async fn test_recover_bad_dleq() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (store, _mi, w) = funded(&server).await;
+    w.set_dleq_policy(&url, DleqPolicy::Required).unwrap();
+
+    // the swap committed with the signatures failed the DLEQ, the journal is kept
+    server.mint().update_options(|o| o.bad_dleq = true);
+    let err = w.send_tokens(&url, 10, None, None, None).await.unwrap_err();
+    assert!(err.is_dleq(), "{}", err);
+    assert_eq!(store.inner().get_operations().await.unwrap().len(), 1);
+
+    // restored without the DLEQ
+    assert_eq!(w.recover_operations().await.unwrap(), (1, 1));
+    assert!(store.inner().get_operations().await.unwrap().is_empty());
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+
+    server.mint().update_options(|o| {
+        o.bad_dleq = false;
+        o.melt_pending = true;
+    });
+    let invoice = server.mint().invoice(Some(10_000));
+    let tx = w.melt(&url, invoice, None, None, None).await.unwrap();
+    assert_eq!(tx.status(), TransactionStatus::Pending);
+
+    // the paid melt settles, the change failed the DLEQ is dropped
+    server.mint().update_options(|o| o.bad_dleq = true);
+    assert!(server.mint().settle_melt_quote(tx.id(), true));
+    assert_eq!(w.resume_pending_melts().await.unwrap(), (1, 1));
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 88);
+    assert!(store.inner().get_pending_melts().await.unwrap().is_empty());
+
+    let tx = store.inner().get_transaction(tx.id()).await.unwrap();
+    assert_eq!(tx.unwrap().status(), TransactionStatus::Success);
+}
+
+#[tokio::test]
+async fn test_crash_before_melt_change_stored() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (store, mi, w) = funded(&server).await;
+
+    // the 1st add_proofs is the split before melt, the 2nd is the change
+    store.crash_at("add_proofs", 2);
+    let invoice = server.mint().invoice(Some(10_000));
+    assert!(w
+        .melt(&url, invoice.clone(), None, None, None)
+        .await
+        .is_err());
+    drop(w);
+
+    store.restart();
+    let w = open(&server, &store, &mi).await;
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 88);
+
+    assert_eq!(w.resume_pending_melts().await.unwrap(), (1, 1));
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 90);
+
+    // the transaction stored with the pending melt keeps the invoice
+    let txs = store.inner().get_all_transactions().await.unwrap();
+    let tx = txs.iter().find(|tx| tx.content() == invoice).unwrap();
+    assert_eq!(tx.status(), TransactionStatus::Success);
+}
+
+#[tokio::test]
+async fn test_journal_swap_timeout() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (store, _mi, w) = funded(&server).await;
+
+    // the mint committed
+    server
+        .mint()
+        .inject_fault("/v1/swap", Fault::TimeoutAfterCommit);
+    assert!(w.send_tokens(&url, 10, None, None, None).await.is_err());
+    assert_eq!(store.inner().get_operations().await.unwrap().len(), 1);
+
+    assert_eq!(w.recover_operations().await.unwrap(), (1, 1));
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+    assert_eq!(w.check_proofs_in_database().await.unwrap().0, 0);
+
+    // the mint not committed
+    server.mint().inject_fault("/v1/swap", Fault::Timeout);
+    assert!(w.send_tokens(&url, 10, None, None, None).await.is_err());
+
+    assert_eq!(w.recover_operations().await.unwrap(), (1, 1));
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+    assert_eq!(w.check_proofs_in_database().await.unwrap().0, 0);
+    assert!(store.inner().get_operations().await.unwrap().is_empty());
+
+    // the rejected is not journaled
+    server.mint().inject_fault("/v1/swap", Fault::RateLimit);
+    assert!(w.send_tokens(&url, 10, None, None, None).await.is_err());
+    assert!(store.inner().get_operations().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_journal_crash_after_swap() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (store, mi, w) = funded(&server).await;
+    let minted = counters(&store, &url, &mi).await;
+
+    // the counters are stored with the proofs
+    store.crash_at("add_proofs", 1);
+    assert!(w.send_tokens(&url, 10, None, None, None).await.is_err());
+    drop(w);
+    assert_eq!(counters(&store, &url, &mi).await, minted);
+
+    store.restart();
+    let w = open(&server, &store, &mi).await;
+    assert_eq!(w.recover_operations().await.unwrap(), (1, 1));
+    assert!(counters(&store, &url, &mi).await > minted);
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+    assert_eq!(w.check_proofs_in_database().await.unwrap().0, 0);
+}
+
+#[tokio::test]
+async fn test_journal_crash_after_send() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (store, mi, w) = funded(&server).await;
+
+    // the token is not stored with the journal done, the send proofs are taken back
+    store.crash_at("delete_operation", 1);
+    assert!(w.send_tokens(&url, 10, None, None, None).await.is_err());
+    drop(w);
+
+    store.restart();
+    let w = open(&server, &store, &mi).await;
+    assert_eq!(w.recover_operations().await.unwrap(), (1, 1));
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+    assert!(store
+        .inner()
+        .get_pending_transactions()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_journal_mint_timeout() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (_store, _mi, w) = funded(&server).await;
+
+    let tx = w.request_mint(&url, 50, None).await.unwrap();
+    server
+        .mint()
+        .inject_fault("/v1/mint/bolt11", Fault::TimeoutAfterCommit);
+    assert!(w
+        .mint_tokens(&url, 50, tx.id().to_owned(), None)
+        .await
+        .is_err());
+
+    assert_eq!(w.recover_operations().await.unwrap(), (1, 1));
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 150);
+}
+
+#[tokio::test]
+async fn test_journal_receive_timeout() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (_store, _mi, alice) = funded(&server).await;
+    let (_store, _mi, bob) = funded(&server).await;
+
+    let tx = alice.send_tokens(&url, 10, None, None, None).await.unwrap();
+    server
+        .mint()
+        .inject_fault("/v1/swap", Fault::TimeoutAfterCommit);
+    assert!(bob.receive_tokens(tx.content()).await.is_err());
+
+    assert_eq!(bob.recover_operations().await.unwrap(), (1, 1));
+    assert_eq!(bob.get_balance_limit_unit(&url, None).await.unwrap(), 110);
+}
+
+#[tokio::test]
+async fn test_journal_receive_offline_timeout() {
+    let mut server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (_store, _mi, alice) = funded(&server).await;
+    let (store, _mi, bob) = funded(&server).await;
+
+    let tx = alice.send_tokens(&url, 10, None, None, None).await.unwrap();
+    server.stop().await;
+    let mut txs = vec![];
+    bob.receive_tokens_full(tx.content(), &mut txs)
+        .await
+        .unwrap();
+    let txid = txs[0].id().to_owned();
+
+    // the swap of the offline received committed but timeout
+    server.resume().await.unwrap();
+    server
+        .mint()
+        .inject_fault("/v1/swap", Fault::TimeoutAfterCommit);
+    assert_eq!(bob.check_pendings().await.unwrap(), (0, 1));
+
+    // the offline received is swapped by the journal
+    assert_eq!(bob.recover_operations().await.unwrap(), (1, 1));
+    assert_eq!(bob.get_balance_limit_unit(&url, None).await.unwrap(), 110);
+    let tx = store.inner().get_transaction(&txid).await.unwrap().unwrap();
+    assert_eq!(tx.status(), TransactionStatus::Success);
+    assert!(bob.get_unswapped_balances().await.unwrap().is_empty());
+
+    assert_eq!(bob.check_pendings().await.unwrap(), (0, 0));
+    assert_eq!(bob.get_balance_limit_unit(&url, None).await.unwrap(), 110);
+}
+
+#[tokio::test]
+async fn test_journal_crash_before_melt() {
+    let server = start().await;
+    let url: Url = server.url().parse().unwrap();
+    let (store, mi, w) = funded(&server).await;
+
+    // the inputs are reserved in the batch with the pending melt
+    store.crash_at("update_proofs_state", 1);
+    let invoice = server.mint().invoice(Some(10_000));
+    assert!(w.melt(&url, invoice, None, None, None).await.is_err());
+    drop(w);
+
+    store.restart();
+    let w = open(&server, &store, &mi).await;
+    assert_eq!(w.recover_operations().await.unwrap(), (0, 0));
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+
+    // the melt never sent and not reserved
+    assert_eq!(w.resume_pending_melts().await.unwrap(), (0, 0));
+    let invoice = server.mint().invoice(Some(10_000));
+    w.melt(&url, invoice, None, None, None).await.unwrap();
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 90);
+}
@@ -0,0 +1,130 @@
+use cashu_wallet::wallet::{spki_pin, HttpOptions, MintClient};
+use cashu_wallet::{UniError, UnitedWallet, Url};
+use cashu_wallet_mock::{MockMint, MockMintOptions, MockMintServer};
+use cashu_wallet_sqlite::LitePool;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{rustls, TlsAcceptor};
+
+use std::sync::Arc;
+
+/// the private CA and the certificate of localhost signed by it
+struct Pki {
+    ca_pem: String,
+    cert_der: Vec<u8>,
+    key_der: Vec<u8>,
+}
+
+fn pki() -> Pki {
+    let mut params = CertificateParams::new(vec![]);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = Certificate::from_params(params).unwrap();
+
+    let cert =
+        Certificate::from_params(CertificateParams::new(vec!["localhost".to_owned()])).unwrap();
+    Pki {
+        ca_pem: ca.serialize_pem().unwrap(),
+        cert_der: cert.serialize_der_with_signer(&ca).unwrap(),
+        key_der: cert.serialize_private_key_der(),
+    }
+}
+
+/// the mock mint behind a TLS terminator of localhost
+async fn start(pki: &Pki) -> (MockMintServer, Url) {
+    let server = MockMintServer::start(MockMint::new(MockMintOptions::new()))
+        .await
+        .unwrap();
+    let upstream = server
+        .url()
+        .trim_start_matches("http://")
+        .trim_end_matches('/')
+        .to_owned();
+
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![rustls::Certificate(pki.cert_der.clone())],
+            rustls::PrivateKey(pki.key_der.clone()),
+        )
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            let upstream = upstream.clone();
+            tokio::spawn(async move {
+                let mut tls = match acceptor.accept(stream).await {
+                    Ok(tls) => tls,
+                    Err(_) => return,
+                };
+                let mut plain = TcpStream::connect(upstream).await.unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut tls, &mut plain).await;
+            });
+        }
+    });
+
+    let url = format!("https://localhost:{}/", port).parse().unwrap();
+    (server, url)
+}
+
+#[tokio::test]
+async fn test_client_root_certificate_and_pins() {
+    let pki = pki();
+    let (_server, url) = start(&pki).await;
+    let pin = spki_pin(&pki.cert_der).unwrap();
+
+    // the private CA is not trusted
+    let client = MintClient::new(url.clone(), HttpOptions::new()).unwrap();
+    assert!(client.get_info().await.is_err());
+
+    let options = HttpOptions::new().root_certificate(pki.ca_pem.as_str());
+    let client = MintClient::new(url.clone(), options.clone()).unwrap();
+    client.get_info().await.unwrap();
+
+    let pinned = options.clone().mint_pins(&url, vec![pin]);
+    let client = MintClient::new(url.clone(), pinned).unwrap();
+    client.get_info().await.unwrap();
+
+    let other = spki_pin(&pki().cert_der).unwrap();
+    let pinned = options.mint_pins(&url, vec![other]);
+    let client = MintClient::new(url, pinned).unwrap();
+    let err = client.get_info().await.unwrap_err();
+    assert!(err.is_pin_mismatch());
+    assert!(!err.is_network_error());
+
+    // the concurrent handshakes all fail as the mismatch
+    let (a, b, c) = tokio::join!(client.get_info(), client.get_info(), client.get_info());
+    for err in [a.unwrap_err(), b.unwrap_err(), c.unwrap_err()] {
+        assert!(err.is_pin_mismatch(), "{}", err);
+    }
+}
+
+#[tokio::test]
+async fn test_united_mint_pins() {
+    let pki = pki();
+    let (_server, url) = start(&pki).await;
+    let pin = spki_pin(&pki.cert_der).unwrap();
+
+    let store = LitePool::open("sqlite::memory:", Default::default())
+        .await
+        .unwrap();
+    let options = HttpOptions::new().root_certificate(pki.ca_pem.as_str());
+    let w = UnitedWallet::new(store.clone(), options.clone());
+    w.add_mint(url.clone(), false).await.unwrap();
+
+    w.set_mint_pins(&url, vec![pin.clone()]).await.unwrap();
+    let other = spki_pin(&pki().cert_der).unwrap();
+    let err = w.set_mint_pins(&url, vec![other]).await.unwrap_err();
+    assert!(matches!(err, UniError::Client(ref e) if e.is_pin_mismatch()));
+
+    // the pins are stored with the mint and used after loaded
+    w.set_mint_pins(&url, vec![pin.clone()]).await.unwrap();
+    let w = UnitedWallet::new(store, options);
+    let mints = w.load_mints_from_database().await.unwrap();
+    assert_eq!(mints[0].pins, [pin]);
+    w.refresh_mint(&url).await.unwrap();
+}
@@ -0,0 +1,377 @@
+use cashu_wallet::cashu::nuts::nut07::State;
+use cashu_wallet::types::TransactionStatus;
+use cashu_wallet::wallet::{
+    AmountHelper, DleqPolicy, HttpOptions, MintClient, MintErrorCode, MnemonicInfo, PaymentRequest,
+    Proof, ProofsHelper, RetryPolicy, Wallet, PROXY_DIRECT,
+};
+use cashu_wallet::{UnitedWallet, Url};
+use cashu_wallet_mock::{Fault, MockMint, MockMintOptions, MockMintServer, ProofState};
+use cashu_wallet_sqlite::LitePool;
+
+use std::sync::Arc;
+
+async fn start(options: MockMintOptions) -> MockMintServer {
+    MockMintServer::start(MockMint::new(options)).await.unwrap()
+}
+
+async fn united(
+    server: &MockMintServer,
+    mnemonic: Option<Arc<MnemonicInfo>>,
+) -> UnitedWallet<LitePool> {
+    let store = LitePool::open("sqlite::memory:", Default::default())
+        .await
+        .unwrap();
+    let w = UnitedWallet::with_mnemonic(store, HttpOptions::new(), mnemonic);
+    w.add_mint(server.url().parse().unwrap(), false)
+        .await
+        .unwrap();
+    w
+}
+
+#[tokio::test]
+async fn test_client() {
+    let server = start(Default::default()).await;
+    let client = MintClient::new(server.url().parse().unwrap(), HttpOptions::new()).unwrap();
+
+    let info = client.get_info().await.unwrap();
+    assert!(info.nuts.nut07.supported);
+    assert!(info.nuts.nut05.supports_amountless("bolt11", "sat"));
+
+    let keysets = client.get_keysetids().await.unwrap().keysets;
+    assert_eq!(keysets.len(), 1);
+    let id = keysets[0].id.to_string();
+    assert_eq!(server.mint().active_keyset_id("sat"), Some(id.clone()));
+
+    let keys = client.get_keys(Some(&id)).await.unwrap();
+    assert_eq!(keys.keysets[0].id.to_string(), id);
+
+    let err = client.mint_quote_state("none", "bolt11").await.unwrap_err();
+    assert!(err.is_mint());
+}
+
+#[tokio::test]
+async fn test_client_retry() {
+    let server = start(Default::default()).await;
+    let policy = RetryPolicy::new().max_attempts(3).backoff_ms(10);
+    let client = MintClient::new(
+        server.url().parse().unwrap(),
+        HttpOptions::new().retry(policy),
+    )
+    .unwrap();
+
+    // waits the Retry-After of 429
+    server.mint().inject_fault("/v1/keysets", Fault::RateLimit);
+    server.mint().inject_fault("/v1/keysets", Fault::RateLimit);
+    let now = std::time::Instant::now();
+    let keysets = client.get_keysetids().await.unwrap().keysets;
+    assert_eq!(keysets.len(), 1);
+    assert!(now.elapsed() >= std::time::Duration::from_secs(2));
+
+    // the swap is never retried
+    server.mint().inject_fault("/v1/swap", Fault::RateLimit);
+    let inputs: Vec<Proof> = vec![];
+    let err = client.swap(inputs, &Default::default()).await.unwrap_err();
+    assert!(err.is_retryable());
+}
+
+#[tokio::test]
+async fn test_client_proxy() {
+    let server = start(Default::default()).await;
+    let url: Url = server.url().parse().unwrap();
+
+    // nothing listens on the proxy
+    let options = HttpOptions::new()
+        .proxy("socks5h://127.0.0.1:9")
+        .timeout_get_ms(2000);
+    let client = MintClient::new(url.clone(), options.clone()).unwrap();
+    assert!(client.get_info().await.is_err());
+
+    let options = options.mint_proxy(&url, PROXY_DIRECT);
+    let client = MintClient::new(url, options).unwrap();
+    assert!(client.get_info().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_wallet_mint_send_check_state() {
+    let server = start(MockMintOptions::new().auto_pay_mint_quotes(true)).await;
+    let client = MintClient::new(server.url().parse().unwrap(), HttpOptions::new()).unwrap();
+    let mut w = Wallet::new(client, None, None, None, (), None)
+        .await
+        .unwrap();
+    w.set_dleq_policy(DleqPolicy::Required);
+
+    let quote = w.request_mint(100.into(), None, None).await.unwrap();
+    let token = w
+        .mint_token(100.into(), None, &quote.quote, None, ())
+        .await
+        .unwrap();
+    let proofs = &token.token[0].proofs;
+    assert_eq!(proofs.sum().to_u64(), 100);
+    w.verify_proofs_dleq_offline(proofs).unwrap();
+
+    // the quote is issued
+    let err = w
+        .mint_token(100.into(), None, &quote.quote, None, ())
+        .await
+        .unwrap_err();
+    assert!(err.is_client());
+
+    let split = w.send(30.into(), proofs, None, ()).await.unwrap();
+    assert_eq!(split.send().sum().to_u64(), 30);
+    assert_eq!(split.keep().sum().to_u64(), 70);
+
+    let states = w.check_proofs(proofs).await.unwrap().states;
+    assert!(states.iter().all(|s| s.state == State::Spent));
+    let states = w.check_proofs(split.all()).await.unwrap().states;
+    assert!(states.iter().all(|s| s.state == State::Unspent));
+
+    // double spend
+    let err = w.send(30.into(), proofs, None, ()).await.unwrap_err();
+    assert!(err.is_client());
+}
+
+#[tokio::test]
+async fn test_wallet_dleq_required_not_supported() {
+    let server = start(
+        MockMintOptions::new()
+            .auto_pay_mint_quotes(true)
+            .dleq(false),
+    )
+    .await;
+    let client = MintClient::new(server.url().parse().unwrap(), HttpOptions::new()).unwrap();
+    let mut w = Wallet::new(client, None, None, None, (), None)
+        .await
+        .unwrap();
+    w.set_dleq_policy(DleqPolicy::Required);
+
+    // refused before the mint signs
+    let quote = w.request_mint(100.into(), None, None).await.unwrap();
+    let err = w
+        .mint_token(100.into(), None, &quote.quote, None, ())
+        .await
+        .unwrap_err();
+    assert!(err.is_dleq(), "{}", err);
+
+    w.set_dleq_policy(DleqPolicy::VerifyIfPresent);
+    let token = w
+        .mint_token(100.into(), None, &quote.quote, None, ())
+        .await
+        .unwrap();
+    assert_eq!(token.token[0].proofs.sum().to_u64(), 100);
+}
+
+#[tokio::test]
+async fn test_united_mint_send_receive() {
+    let server = start(Default::default()).await;
+    let url: Url = server.url().parse().unwrap();
+    let alice = united(&server, None).await;
+    let bob = united(&server, None).await;
+
+    let mut tx = alice.request_mint(&url, 100, None).await.unwrap();
+    assert!(!alice.check_mint_quote(&url, &mut tx).await.unwrap());
+
+    assert!(server.mint().pay_mint_quote(tx.id()));
+    assert!(alice.check_mint_quote(&url, &mut tx).await.unwrap());
+    assert_eq!(alice.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+
+    let tx = alice.send_tokens(&url, 10, None, None, None).await.unwrap();
+    assert_eq!(alice.get_balance_limit_unit(&url, None).await.unwrap(), 90);
+
+    // the token not claimed is reserved
+    let balance = alice.get_balance_with_state(&url, None).await.unwrap();
+    assert_eq!((balance.available, balance.reserved), (90, 10));
+
+    let received = bob.receive_tokens(tx.content()).await.unwrap();
+    assert_eq!(received, 10);
+    assert_eq!(bob.get_balance_limit_unit(&url, None).await.unwrap(), 10);
+
+    alice.check_pendings().await.unwrap();
+    let balance = alice.get_balance_with_state(&url, None).await.unwrap();
+    assert_eq!((balance.available, balance.reserved), (90, 0));
+
+    // the token is spent
+    let err = bob.receive_tokens(tx.content()).await.unwrap_err();
+    assert_eq!(
+        err.mint_error_code(),
+        Some(MintErrorCode::TokenAlreadySpent)
+    );
+    assert!(err.is_permanent());
+    assert_eq!(bob.get_balance_limit_unit(&url, None).await.unwrap(), 10);
+}
+
+#[tokio::test]
+async fn test_united_receive_fee() {
+    let options = MockMintOptions::new()
+        .auto_pay_mint_quotes(true)
+        .input_fee_ppk(1000);
+    let server = start(options).await;
+    let url: Url = server.url().parse().unwrap();
+    let alice = united(&server, None).await;
+    let bob = united(&server, None).await;
+
+    let tx = alice.request_mint(&url, 100, None).await.unwrap();
+    alice
+        .mint_tokens(&url, 100, tx.id().to_owned(), None)
+        .await
+        .unwrap();
+
+    // the 2 and 8 proofs pay 1 sat each to swap
+    let tx = alice.send_tokens(&url, 10, None, None, None).await.unwrap();
+    let mut txs = vec![];
+    bob.receive_tokens_full(tx.content(), &mut txs)
+        .await
+        .unwrap();
+    assert_eq!((txs[0].amount(), txs[0].fee()), (8, Some(2)));
+    assert_eq!(bob.get_balance_limit_unit(&url, None).await.unwrap(), 8);
+}
+
+#[tokio::test]
+async fn test_united_pay_request_single_use() {
+    let options = MockMintOptions::new()
+        .auto_pay_mint_quotes(true)
+        .input_fee_ppk(1000);
+    let server = start(options).await;
+    let url: Url = server.url().parse().unwrap();
+    let w = united(&server, None).await;
+
+    let tx = w.request_mint(&url, 100, None).await.unwrap();
+    w.mint_tokens(&url, 100, tx.id().to_owned(), None)
+        .await
+        .unwrap();
+
+    let request = PaymentRequest {
+        payment_id: Some("id0".to_owned()),
+        amount: Some(10),
+        single_use: Some(true),
+        ..Default::default()
+    };
+    let (payload, tx) = w.pay_request(&request, None).await.unwrap();
+    assert_eq!(payload.proofs.sum().to_u64(), 10);
+    assert_eq!(tx.info(), Some(request.to_string().as_str()));
+
+    // paid once
+    assert!(w.pay_request(&request, None).await.is_err());
+
+    let request = PaymentRequest {
+        single_use: None,
+        ..request
+    };
+    w.pay_request(&request, None).await.unwrap();
+    w.pay_request(&request, None).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_united_melt() {
+    let server = start(MockMintOptions::new().auto_pay_mint_quotes(true)).await;
+    let url: Url = server.url().parse().unwrap();
+    let w = united(&server, None).await;
+
+    let tx = w.request_mint(&url, 100, None).await.unwrap();
+    w.mint_tokens(&url, 100, tx.id().to_owned(), None)
+        .await
+        .unwrap();
+
+    // the fee_reserve is returned as change
+    let invoice = server.mint().invoice(Some(10_000));
+    let tx = w.melt(&url, invoice, None, None, None).await.unwrap();
+    assert_eq!(tx.status(), TransactionStatus::Success);
+    assert!(tx.preimage().is_some());
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 90);
+
+    // NUT-05 amountless
+    let invoice = server.mint().invoice(None);
+    let tx = w.melt(&url, invoice, Some(20), None, None).await.unwrap();
+    assert_eq!(tx.status(), TransactionStatus::Success);
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 70);
+
+    // the amount of amountless is sat only
+    let invoice = server.mint().invoice(None);
+    let err = w
+        .melt(&url, invoice, Some(20), Some("usd"), None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("usd"), "{}", err);
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 70);
+}
+
+#[tokio::test]
+async fn test_united_melt_pending() {
+    let options = MockMintOptions::new()
+        .auto_pay_mint_quotes(true)
+        .melt_pending(true);
+    let server = start(options).await;
+    let url: Url = server.url().parse().unwrap();
+    let w = united(&server, None).await;
+
+    let tx = w.request_mint(&url, 100, None).await.unwrap();
+    w.mint_tokens(&url, 100, tx.id().to_owned(), None)
+        .await
+        .unwrap();
+
+    let invoice = server.mint().invoice(Some(10_000));
+    let tx = w.melt(&url, invoice, None, None, None).await.unwrap();
+    assert_eq!(tx.status(), TransactionStatus::Pending);
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 88);
+    assert_eq!(w.resume_pending_melts().await.unwrap(), (0, 1));
+
+    let balance = w.get_balance_with_state(&url, None).await.unwrap();
+    assert_eq!((balance.available, balance.pending), (88, 12));
+
+    assert!(server.mint().settle_melt_quote(tx.id(), true));
+    assert_eq!(w.resume_pending_melts().await.unwrap(), (1, 1));
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 90);
+    assert_eq!(
+        w.get_balance_with_state(&url, None).await.unwrap().pending,
+        0
+    );
+
+    // the failed payment releases the inputs
+    let invoice = server.mint().invoice(Some(10_000));
+    let tx = w.melt(&url, invoice, None, None, None).await.unwrap();
+    assert_eq!(tx.status(), TransactionStatus::Pending);
+    assert!(server.mint().settle_melt_quote(tx.id(), false));
+    assert_eq!(w.resume_pending_melts().await.unwrap(), (1, 1));
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 90);
+}
+
+#[tokio::test]
+async fn test_united_restore() {
+    let server = start(MockMintOptions::new().auto_pay_mint_quotes(true)).await;
+    let url: Url = server.url().parse().unwrap();
+
+    let words = MnemonicInfo::generate_words(12).unwrap();
+    let mi = Arc::new(MnemonicInfo::with_words(&words).unwrap());
+
+    let w = united(&server, Some(mi.clone())).await;
+    let tx = w.request_mint(&url, 100, None).await.unwrap();
+    w.mint_tokens(&url, 100, tx.id().to_owned(), None)
+        .await
+        .unwrap();
+    let tx = w.send_tokens(&url, 10, None, None, None).await.unwrap();
+    w.receive_tokens(tx.content()).await.unwrap();
+    assert_eq!(w.get_balance_limit_unit(&url, None).await.unwrap(), 100);
+
+    // the spent proofs are not restored
+    let restored = united(&server, Some(mi.clone())).await;
+    let proofs = restored
+        .restore(
+            &url,
+            10,
+            0,
+            &[],
+            None,
+            |_, _, _, _, _, _, _, _, _, _, _, _| false,
+        )
+        .await
+        .unwrap();
+    assert_eq!(proofs.sum().to_u64(), 100);
+    assert_eq!(
+        restored.get_balance_limit_unit(&url, None).await.unwrap(),
+        100
+    );
+
+    let ys = cashu_wallet::wallet::proofs_ys(&proofs).unwrap();
+    assert!(ys
+        .iter()
+        .all(|y| server.mint().proof_state(&y.to_string()) == ProofState::Unspent));
+}
@@ -0,0 +1,1213 @@
+#[macro_use]
+extern crate anyhow;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate async_trait;
+
+use cashu_wallet::store::{cmp_by_asc, Batch, MintUrlWithUnit, MintUrlWithUnitOwned, Write};
+use cashu_wallet::store::{UnitedStore, Url};
+use cashu_wallet::types::{
+    unixtime_ms, Mint, MintKeySet, PendingMelt, PendingOperation, Transaction, TransactionKind,
+    TransactionStatus,
+};
+use cashu_wallet::wallet::{ProofExtended, ProofState, ProofsExtended, Record, CURRENCY_UNIT_SAT};
+use cashu_wallet::UniError;
+pub use redb;
+
+use redb::{Database, ReadableTable, TableDefinition, WriteTransaction};
+use serde::Serialize;
+use std::collections::BTreeMap as Map;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use strum::EnumIs;
+
+// url -> Mint json
+type MintTable = TableDefinition<'static, &'static str, &'static str>;
+// (mint, pubkey, keysetid) -> Record json
+type CounterTable =
+    TableDefinition<'static, (&'static str, &'static str, &'static str), &'static str>;
+// (mint, secret) -> ProofExtended json, (id, io) -> Transaction json
+type PairTable = TableDefinition<'static, (&'static str, &'static str), &'static str>;
+// (mint, quote/id) -> (ctime, json), the upsert keeps the ctime of the first insert
+type TimedTable = TableDefinition<'static, (&'static str, &'static str), (u64, &'static str)>;
+
+#[derive(Clone)]
+pub struct Redb {
+    db: Arc<Database>,
+    tables: Tables,
+}
+
+impl fmt::Debug for Redb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Redb")
+            .field("tables", &self.tables)
+            .finish()
+    }
+}
+
+impl Redb {
+    pub fn new(db: Database, tables: Tables) -> Result<Redb, StoreError> {
+        tables.check()?;
+
+        let this = Self {
+            db: Arc::new(db),
+            tables,
+        };
+        this.init()?;
+
+        Ok(this)
+    }
+
+    /// create the database file if missing
+    pub fn open(dbpath: impl AsRef<Path>, tables: Tables) -> Result<Redb, StoreError> {
+        let dbpath = dbpath.as_ref();
+
+        info!("Redb open: {}", dbpath.display());
+        let db = Database::create(dbpath)?;
+
+        Self::new(db, tables)
+    }
+
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    pub fn tables(&self) -> &Tables {
+        &self.tables
+    }
+
+    /// create the tables, the read transaction fails on the table not exists
+    pub fn init(&self) -> Result<(), StoreError> {
+        let tx = self.db.begin_write()?;
+        tx.open_table(self.definition_mints())?;
+        tx.open_table(self.definition_counters())?;
+        tx.open_table(self.definition_proofs())?;
+        tx.open_table(self.definition_transactions())?;
+        tx.open_table(self.definition_melts())?;
+        tx.open_table(self.definition_keysets())?;
+        tx.open_table(self.definition_operations())?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn definition_mints(&self) -> MintTable {
+        TableDefinition::new(self.tables.mints)
+    }
+
+    #[inline]
+    pub fn definition_counters(&self) -> CounterTable {
+        TableDefinition::new(self.tables.counters)
+    }
+
+    #[inline]
+    pub fn definition_proofs(&self) -> PairTable {
+        TableDefinition::new(self.tables.proofs)
+    }
+
+    #[inline]
+    pub fn definition_transactions(&self) -> PairTable {
+        TableDefinition::new(self.tables.transactions)
+    }
+
+    #[inline]
+    pub fn definition_melts(&self) -> TimedTable {
+        TableDefinition::new(self.tables.melts)
+    }
+
+    #[inline]
+    pub fn definition_keysets(&self) -> TimedTable {
+        TableDefinition::new(self.tables.keysets)
+    }
+
+    #[inline]
+    pub fn definition_operations(&self) -> TimedTable {
+        TableDefinition::new(self.tables.operations)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Tables {
+    mints: &'static str,
+    proofs: &'static str,
+    counters: &'static str,
+    /// add records for invoices
+    transactions: &'static str,
+    /// in-flight melts
+    melts: &'static str,
+    /// keysets of mints
+    keysets: &'static str,
+    /// write-ahead journal of swaps and mints
+    operations: &'static str,
+}
+
+impl Default for Tables {
+    fn default() -> Self {
+        Self {
+            mints: "mints",
+            proofs: "proofs",
+            counters: "counters",
+            transactions: "transactions",
+            melts: "melts",
+            keysets: "keysets",
+            operations: "operations",
+        }
+    }
+}
+
+impl Tables {
+    pub fn check(&self) -> anyhow::Result<()> {
+        let strs = [
+            self.mints,
+            self.proofs,
+            self.counters,
+            self.transactions,
+            self.melts,
+            self.keysets,
+            self.operations,
+        ];
+        let mut names = strs.iter().filter(|s| !s.is_empty()).collect::<Vec<_>>();
+        if names.len() != strs.len() {
+            bail!("empty table name");
+        }
+
+        names.sort();
+        names.dedup();
+        if names.len() != strs.len() {
+            bail!("duplicate table name");
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+//
+#[derive(EnumIs, thiserror::Error)]
+pub enum StoreError {
+    /// Json error
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Custom(#[from] anyhow::Error),
+    #[error("{0}")]
+    Database(#[from] redb::Error),
+}
+
+impl From<StoreError> for UniError<StoreError> {
+    fn from(err: StoreError) -> Self {
+        Self::Store(err)
+    }
+}
+
+macro_rules! impl_from_redb {
+    ($($e: ty),*) => {
+        $(
+            impl From<$e> for StoreError {
+                fn from(err: $e) -> Self {
+                    Self::Database(err.into())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_redb!(
+    redb::DatabaseError,
+    redb::TransactionError,
+    redb::TableError,
+    redb::StorageError,
+    redb::CommitError
+);
+
+// the writes on a transaction, so the batch shares it
+impl Redb {
+    fn add_counter_with(&self, tx: &WriteTransaction, record: &Record) -> Result<(), StoreError> {
+        debug!("add_counter: {:?}", record);
+
+        let mut table = tx.open_table(self.definition_counters())?;
+        let key = (
+            record.mint.as_str(),
+            record.pubkey.as_str(),
+            record.keysetid.as_str(),
+        );
+
+        // update the counter only
+        let old = table
+            .get(key)?
+            .map(|v| serde_json::from_str::<Record>(v.value()))
+            .transpose()?;
+        let record = match old {
+            Some(mut r) => {
+                r.counter = record.counter;
+                r
+            }
+            None => record.clone(),
+        };
+
+        let js = serde_json::to_string(&record)?;
+        table.insert(key, js.as_str())?;
+
+        Ok(())
+    }
+
+    fn delete_proofs_with(
+        &self,
+        tx: &WriteTransaction,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+    ) -> Result<(), StoreError> {
+        let mint = mint_url.as_str();
+
+        debug!("del_proofs: {:?}", proofs);
+
+        let mut table = tx.open_table(self.definition_proofs())?;
+        for p in proofs {
+            table.remove((mint, p.raw.secret.as_str()))?;
+        }
+
+        Ok(())
+    }
+
+    fn add_proofs_with(
+        &self,
+        tx: &WriteTransaction,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+    ) -> Result<(), StoreError> {
+        let mint = mint_url.as_str();
+
+        debug!("add_proofs: {:?}", proofs);
+
+        let mut table = tx.open_table(self.definition_proofs())?;
+        for p in proofs {
+            let key = (mint, p.raw.secret.as_str());
+            // the same as the unique (secret, mint) of sqlite
+            if table.get(key)?.is_some() {
+                return Err(format_err!("proof exists: {} {}", mint, p.raw.secret.as_str()).into());
+            }
+
+            let mut p = p.clone();
+            p.ts = Some(p.ts.unwrap_or_else(unixtime_ms));
+            let js = serde_json::to_string(&p)?;
+            table.insert(key, js.as_str())?;
+        }
+
+        Ok(())
+    }
+
+    fn update_proofs_state_with(
+        &self,
+        tx: &WriteTransaction,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+        state: ProofState,
+        operation: Option<&str>,
+    ) -> Result<(), StoreError> {
+        let mint = mint_url.as_str();
+
+        debug!(
+            "update_proofs_state: {} {} {:?}",
+            proofs.len(),
+            state,
+            operation
+        );
+
+        let ts = unixtime_ms();
+        let mut table = tx.open_table(self.definition_proofs())?;
+        for p in proofs {
+            let key = (mint, p.raw.secret.as_str());
+            let old = table
+                .get(key)?
+                .map(|v| serde_json::from_str::<ProofExtended>(v.value()))
+                .transpose()?;
+            let mut p = match old {
+                Some(p) => p,
+                None => continue,
+            };
+
+            p.state = state;
+            if state == ProofState::Unspent {
+                p.operation = None;
+                p.reserved_at = None;
+            } else {
+                if let Some(op) = operation {
+                    p.operation = Some(op.to_owned());
+                }
+                p.reserved_at = Some(p.reserved_at.unwrap_or(ts));
+            }
+
+            let js = serde_json::to_string(&p)?;
+            table.insert(key, js.as_str())?;
+        }
+
+        Ok(())
+    }
+
+    fn add_transaction_with(
+        &self,
+        tx: &WriteTransaction,
+        t: &Transaction,
+    ) -> Result<(), StoreError> {
+        debug!(
+            "add_transaction: {} {} {}",
+            t.id(),
+            t.status(),
+            t.direction()
+        );
+
+        let mut table = tx.open_table(self.definition_transactions())?;
+        let io = t.direction();
+        let key = (t.id(), io.as_ref());
+
+        // update the status, info, fee and preimage only
+        let old = table
+            .get(key)?
+            .map(|v| serde_json::from_str::<Transaction>(v.value()))
+            .transpose()?;
+        let t = match old {
+            Some(mut old) => {
+                *old.status_mut() = t.status();
+                *old.info_mut() = t.info().map(|s| s.to_owned());
+                match &mut old {
+                    Transaction::Cashu(old) => old.fee = t.fee(),
+                    Transaction::LN(old) => {
+                        old.fee = t.fee();
+                        old.preimage = t.preimage().map(|s| s.to_owned());
+                    }
+                }
+                old
+            }
+            None => t.clone(),
+        };
+
+        let js = serde_json::to_string(&t)?;
+        table.insert(key, js.as_str())?;
+
+        Ok(())
+    }
+
+    fn add_pending_melt_with(
+        &self,
+        tx: &WriteTransaction,
+        melt: &PendingMelt,
+    ) -> Result<(), StoreError> {
+        debug!("add_pending_melt: {} {}", melt.mint, melt.quote);
+
+        let mut table = tx.open_table(self.definition_melts())?;
+        let key = (melt.mint.as_str(), melt.quote.as_str());
+        let ctime = table.get(key)?.map(|v| v.value().0);
+
+        let js = serde_json::to_string(melt)?;
+        table.insert(key, (ctime.unwrap_or(melt.time), js.as_str()))?;
+
+        Ok(())
+    }
+
+    fn delete_pending_melt_with(
+        &self,
+        tx: &WriteTransaction,
+        mint_url: &str,
+        quote: &str,
+    ) -> Result<(), StoreError> {
+        debug!("delete_pending_melt: {} {}", mint_url, quote);
+
+        let mut table = tx.open_table(self.definition_melts())?;
+        table.remove((mint_url, quote))?;
+
+        Ok(())
+    }
+
+    fn delete_operation_with(
+        &self,
+        tx: &WriteTransaction,
+        mint_url: &str,
+        id: &str,
+    ) -> Result<(), StoreError> {
+        debug!("delete_operation: {} {}", mint_url, id);
+
+        let mut table = tx.open_table(self.definition_operations())?;
+        table.remove((mint_url, id))?;
+
+        Ok(())
+    }
+
+    // redb blocks on the disk io, run it out of the async runtime
+    async fn blocking<T, F>(&self, f: F) -> Result<T, StoreError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Redb) -> Result<T, StoreError> + Send + 'static,
+    {
+        let this = self.clone();
+        tokio::task::spawn_blocking(move || f(&this))
+            .await
+            .map_err(|e| anyhow!("spawn_blocking: {}", e))?
+    }
+
+    // the proofs of the mint or all in the states, order by ctime
+    fn get_proofs_with(
+        &self,
+        mint_url: Option<&str>,
+        states: &[ProofState],
+    ) -> Result<Vec<(String, ProofExtended)>, StoreError> {
+        let rtx = self.db.begin_read()?;
+        let table = rtx.open_table(self.definition_proofs())?;
+        let iter = match mint_url {
+            Some(mint) => table.range((mint, "")..)?,
+            None => table.iter()?,
+        };
+
+        let mut proofs = vec![];
+        for it in iter {
+            let (k, v) = it?;
+            let (mint, _secret) = k.value();
+            if mint_url.is_some_and(|m| m != mint) {
+                break;
+            }
+
+            let p = serde_json::from_str::<ProofExtended>(v.value())?;
+            if states.contains(&p.state) {
+                proofs.push((mint.to_owned(), p));
+            }
+        }
+        proofs.sort_by(|a, b| cmp_by_asc(a.1.ts, b.1.ts));
+
+        Ok(proofs)
+    }
+
+    // the contents of the timed table order by ctime
+    fn get_timed_with(
+        &self,
+        definition: TimedTable,
+        mint_url: Option<&str>,
+    ) -> Result<Vec<String>, StoreError> {
+        let rtx = self.db.begin_read()?;
+        let table = rtx.open_table(definition)?;
+        let iter = match mint_url {
+            Some(mint) => table.range((mint, "")..)?,
+            None => table.iter()?,
+        };
+
+        let mut rows = vec![];
+        for it in iter {
+            let (k, v) = it?;
+            let (mint, _id) = k.value();
+            if mint_url.is_some_and(|m| m != mint) {
+                break;
+            }
+
+            let (ctime, js) = v.value();
+            rows.push((ctime, js.to_owned()));
+        }
+        rows.sort_by(|a, b| cmp_by_asc(a.0, b.0));
+
+        Ok(rows.into_iter().map(|(_, js)| js).collect())
+    }
+}
+
+#[async_trait]
+impl UnitedStore for Redb {
+    type Error = StoreError;
+
+    // counter records
+    async fn add_counter(&self, record: &Record) -> Result<(), Self::Error> {
+        let record = record.clone();
+        self.blocking(move |this| {
+            let tx = this.db.begin_write()?;
+            this.add_counter_with(&tx, &record)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_counters(&self, mint_url: &Url) -> Result<(), Self::Error> {
+        let mint = mint_url.as_str().to_owned();
+        debug!("delete_counters: {}", mint);
+
+        self.blocking(move |this| {
+            let mint = mint.as_str();
+            let tx = this.db.begin_write()?;
+            {
+                let mut table = tx.open_table(this.definition_counters())?;
+                let mut keys = vec![];
+                for it in table.range((mint, "", "")..)? {
+                    let (k, _) = it?;
+                    let (m, pubkey, keysetid) = k.value();
+                    if m != mint {
+                        break;
+                    }
+                    keys.push((pubkey.to_owned(), keysetid.to_owned()));
+                }
+
+                for (pubkey, keysetid) in &keys {
+                    table.remove((mint, pubkey.as_str(), keysetid.as_str()))?;
+                }
+            }
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_counters(&self, mint_url: &Url, pubkey: &str) -> Result<Vec<Record>, Self::Error> {
+        let mint = mint_url.as_str().to_owned();
+        let pubkey = pubkey.to_owned();
+
+        self.blocking(move |this| {
+            let (mint, pubkey) = (mint.as_str(), pubkey.as_str());
+            let rtx = this.db.begin_read()?;
+            let table = rtx.open_table(this.definition_counters())?;
+
+            let mut records = vec![];
+            for it in table.range((mint, pubkey, "")..)? {
+                let (k, v) = it?;
+                let (m, p, _keysetid) = k.value();
+                if m != mint || p != pubkey {
+                    break;
+                }
+
+                let r = serde_json::from_str::<Record>(v.value())?;
+                records.push(r);
+            }
+            records.sort_by(|a, b| cmp_by_asc(a.ts, b.ts));
+
+            Ok(records)
+        })
+        .await
+    }
+    async fn delete_proofs(
+        &self,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+    ) -> Result<(), Self::Error> {
+        if proofs.is_empty() {
+            return Ok(());
+        }
+
+        let (mint_url, proofs) = (mint_url.clone(), proofs.to_vec());
+        self.blocking(move |this| {
+            let tx = this.db.begin_write()?;
+            this.delete_proofs_with(&tx, &mint_url, &proofs)?;
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+    async fn add_proofs(
+        &self,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+    ) -> Result<(), Self::Error> {
+        if proofs.is_empty() {
+            return Ok(());
+        }
+
+        let (mint_url, proofs) = (mint_url.clone(), proofs.to_vec());
+        self.blocking(move |this| {
+            let tx = this.db.begin_write()?;
+            this.add_proofs_with(&tx, &mint_url, &proofs)?;
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+    async fn get_proofs_limit_unit(
+        &self,
+        mint_url: &Url,
+        unit: &str,
+    ) -> Result<ProofsExtended, Self::Error> {
+        let (mint, unit) = (mint_url.as_str().to_owned(), unit.to_owned());
+        let proofs = self
+            .blocking(move |this| this.get_proofs_with(Some(mint.as_str()), &[ProofState::Unspent]))
+            .await?
+            .into_iter()
+            .map(|(_, p)| p)
+            .filter(|p| p.unit().unwrap_or(CURRENCY_UNIT_SAT) == unit)
+            .collect();
+
+        Ok(proofs)
+    }
+    async fn get_proofs(&self, mint_url: &Url) -> Result<Map<String, ProofsExtended>, Self::Error> {
+        let mint = mint_url.as_str().to_owned();
+        let rows = self
+            .blocking(move |this| this.get_proofs_with(Some(mint.as_str()), &[ProofState::Unspent]))
+            .await?;
+
+        let mut proofs = Map::new();
+        for (_mint, p) in rows {
+            let k = p.unit().unwrap_or(CURRENCY_UNIT_SAT).to_owned();
+            let ps: &mut Vec<_> = proofs.entry(k).or_default();
+            ps.push(p);
+        }
+
+        Ok(proofs)
+    }
+    async fn get_all_proofs(
+        &self,
+    ) -> Result<Map<MintUrlWithUnitOwned, ProofsExtended>, Self::Error> {
+        self.get_all_proofs_with_state(&[ProofState::Unspent]).await
+    }
+    async fn update_proofs_state(
+        &self,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+        state: ProofState,
+        operation: Option<&str>,
+    ) -> Result<(), Self::Error> {
+        if proofs.is_empty() {
+            return Ok(());
+        }
+
+        let (mint_url, proofs) = (mint_url.clone(), proofs.to_vec());
+        let operation = operation.map(|o| o.to_owned());
+        self.blocking(move |this| {
+            let tx = this.db.begin_write()?;
+            this.update_proofs_state_with(&tx, &mint_url, &proofs, state, operation.as_deref())?;
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+    async fn get_all_proofs_with_state(
+        &self,
+        states: &[ProofState],
+    ) -> Result<Map<MintUrlWithUnitOwned, ProofsExtended>, Self::Error> {
+        let mut proofs = Map::new();
+        if states.is_empty() {
+            return Ok(proofs);
+        }
+
+        let states = states.to_vec();
+        let rows = self
+            .blocking(move |this| this.get_proofs_with(None, &states))
+            .await?;
+        for (mint, p) in rows {
+            let key = p.unit().unwrap_or(CURRENCY_UNIT_SAT);
+            let key = MintUrlWithUnit::new(mint, key).into_owned();
+
+            let ps: &mut Vec<_> = proofs.entry(key).or_default();
+            ps.push(p);
+        }
+
+        Ok(proofs)
+    }
+    async fn get_proofs_with_state(
+        &self,
+        mint_url: &Url,
+        states: &[ProofState],
+    ) -> Result<ProofsExtended, Self::Error> {
+        if states.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let (mint, states) = (mint_url.as_str().to_owned(), states.to_vec());
+        let proofs = self
+            .blocking(move |this| this.get_proofs_with(Some(mint.as_str()), &states))
+            .await?
+            .into_iter()
+            .map(|(_, p)| p)
+            .collect();
+
+        Ok(proofs)
+    }
+    async fn delete_proofs_with_state(
+        &self,
+        states: &[ProofState],
+        unix_timestamp_ms_le: u64,
+    ) -> Result<u64, Self::Error> {
+        let states = states.to_vec();
+        self.blocking(move |this| {
+            let tx = this.db.begin_write()?;
+            let mut keys = vec![];
+            {
+                let mut table = tx.open_table(this.definition_proofs())?;
+                for it in table.iter()? {
+                    let (k, v) = it?;
+                    let p = serde_json::from_str::<ProofExtended>(v.value())?;
+                    let at = p.reserved_at.or(p.ts).unwrap_or_default();
+                    if at <= unix_timestamp_ms_le && states.contains(&p.state) {
+                        let (mint, secret) = k.value();
+                        keys.push((mint.to_owned(), secret.to_owned()));
+                    }
+                }
+
+                for (mint, secret) in &keys {
+                    table.remove((mint.as_str(), secret.as_str()))?;
+                }
+            }
+            tx.commit()?;
+
+            Ok(keys.len() as u64)
+        })
+        .await
+    }
+    /// create the tables
+    async fn migrate(&self) -> Result<(), Self::Error> {
+        self.blocking(|this| this.init()).await
+    }
+    //
+    // mints
+    /// overwrite it
+    async fn add_mint(&self, mint: &Mint) -> Result<(), Self::Error> {
+        let mut mint = mint.clone();
+        self.blocking(move |this| {
+            let tx = this.db.begin_write()?;
+            {
+                let mut table = tx.open_table(this.definition_mints())?;
+
+                // keep the ctime
+                let old = table
+                    .get(mint.url.as_str())?
+                    .map(|v| serde_json::from_str::<Mint>(v.value()))
+                    .transpose()?;
+                if let Some(old) = old {
+                    mint.time = old.time;
+                }
+
+                let js = serde_json::to_string(&mint)?;
+                table.insert(mint.url.as_str(), js.as_str())?;
+            }
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+    async fn get_mint(&self, mint_url: &str) -> Result<Option<Mint>, Self::Error> {
+        let mint_url = mint_url.to_owned();
+        self.blocking(move |this| {
+            let rtx = this.db.begin_read()?;
+            let table = rtx.open_table(this.definition_mints())?;
+
+            let mint = table
+                .get(mint_url.as_str())?
+                .map(|v| serde_json::from_str::<Mint>(v.value()))
+                .transpose()?;
+
+            Ok(mint)
+        })
+        .await
+    }
+    async fn get_mints(&self) -> Result<Vec<Mint>, Self::Error> {
+        self.blocking(|this| {
+            let rtx = this.db.begin_read()?;
+            let table = rtx.open_table(this.definition_mints())?;
+
+            // order by url
+            let mut mints = vec![];
+            for it in table.iter()? {
+                let (_, v) = it?;
+                let mint = serde_json::from_str::<Mint>(v.value())?;
+                mints.push(mint);
+            }
+
+            Ok(mints)
+        })
+        .await
+    }
+    //
+    // tx
+    async fn add_transaction(&self, t: &Transaction) -> Result<(), Self::Error> {
+        let t = t.clone();
+        self.blocking(move |this| {
+            let tx = this.db.begin_write()?;
+            this.add_transaction_with(&tx, &t)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_transaction(&self, txid: &str) -> Result<Option<Transaction>, Self::Error> {
+        let txid = txid.to_owned();
+        self.blocking(move |this| {
+            let txid = txid.as_str();
+            let rtx = this.db.begin_read()?;
+            let table = rtx.open_table(this.definition_transactions())?;
+
+            if let Some(it) = table.range((txid, "")..)?.next() {
+                let (k, v) = it?;
+                if k.value().0 == txid {
+                    let t = serde_json::from_str::<Transaction>(v.value())?;
+                    return Ok(Some(t));
+                }
+            }
+
+            Ok(None)
+        })
+        .await
+    }
+
+    async fn get_transactions(
+        &self,
+        status: &[TransactionStatus],
+    ) -> Result<Vec<Transaction>, Self::Error> {
+        let status = status.to_vec();
+        self.blocking(move |this| {
+            let rtx = this.db.begin_read()?;
+            let table = rtx.open_table(this.definition_transactions())?;
+
+            let mut txs = vec![];
+            for it in table.iter()? {
+                let (_, v) = it?;
+                let t = serde_json::from_str::<Transaction>(v.value())?;
+                if status.contains(&t.status()) {
+                    txs.push(t);
+                }
+            }
+            txs.sort_by(|a, b| cmp_by_asc(a.time(), b.time()));
+
+            Ok(txs)
+        })
+        .await
+    }
+
+    async fn get_transactions_with_offset(
+        &self,
+        offset: usize,
+        limit: usize,
+        kinds: &[TransactionKind],
+    ) -> Result<Vec<Transaction>, Self::Error> {
+        let kinds = kinds.to_vec();
+        self.blocking(move |this| {
+            let rtx = this.db.begin_read()?;
+            let table = rtx.open_table(this.definition_transactions())?;
+
+            let mut txs = vec![];
+            for it in table.iter()? {
+                let (_, v) = it?;
+                let t = serde_json::from_str::<Transaction>(v.value())?;
+                if kinds.contains(&t.kind()) {
+                    txs.push(t);
+                }
+            }
+            txs.sort_by(|a, b| cmp_by_asc(b.time(), a.time()));
+
+            Ok(txs.into_iter().skip(offset).take(limit).collect())
+        })
+        .await
+    }
+
+    async fn delete_transactions(
+        &self,
+        status: &[TransactionStatus],
+        unix_timestamp_ms_le: u64,
+    ) -> Result<u64, Self::Error> {
+        let status = status.to_vec();
+        self.blocking(move |this| {
+            let tx = this.db.begin_write()?;
+            let mut keys = vec![];
+            {
+                let mut table = tx.open_table(this.definition_transactions())?;
+                for it in table.iter()? {
+                    let (k, v) = it?;
+                    let t = serde_json::from_str::<Transaction>(v.value())?;
+                    if t.time() <= unix_timestamp_ms_le && status.contains(&t.status()) {
+                        let (id, io) = k.value();
+                        keys.push((id.to_owned(), io.to_owned()));
+                    }
+                }
+
+                for (id, io) in &keys {
+                    table.remove((id.as_str(), io.as_str()))?;
+                }
+            }
+            tx.commit()?;
+
+            Ok(keys.len() as u64)
+        })
+        .await
+    }
+    //
+    // in-flight melts
+    async fn add_pending_melt(&self, melt: &PendingMelt) -> Result<(), Self::Error> {
+        let melt = melt.clone();
+        self.blocking(move |this| {
+            let tx = this.db.begin_write()?;
+            this.add_pending_melt_with(&tx, &melt)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+    async fn delete_pending_melt(&self, mint_url: &str, quote: &str) -> Result<(), Self::Error> {
+        let (mint_url, quote) = (mint_url.to_owned(), quote.to_owned());
+        self.blocking(move |this| {
+            let tx = this.db.begin_write()?;
+            this.delete_pending_melt_with(&tx, &mint_url, &quote)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+    async fn get_pending_melts(&self) -> Result<Vec<PendingMelt>, Self::Error> {
+        let rows = self
+            .blocking(|this| this.get_timed_with(this.definition_melts(), None))
+            .await?;
+
+        let mut melts = vec![];
+        for js in rows {
+            let melt = serde_json::from_str::<PendingMelt>(&js)?;
+            melts.push(melt);
+        }
+
+        Ok(melts)
+    }
+    //
+    // write-ahead journal of swaps and mints
+    async fn add_operation(&self, op: &PendingOperation) -> Result<(), Self::Error> {
+        debug!("add_operation: {} {} {:?}", op.mint, op.id, op.kind);
+
+        let op = op.clone();
+        self.blocking(move |this| {
+            let tx = this.db.begin_write()?;
+            {
+                let mut table = tx.open_table(this.definition_operations())?;
+                let key = (op.mint.as_str(), op.id.as_str());
+                let ctime = table.get(key)?.map(|v| v.value().0);
+
+                let js = serde_json::to_string(&op)?;
+                table.insert(key, (ctime.unwrap_or(op.time), js.as_str()))?;
+            }
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+    async fn delete_operation(&self, mint_url: &str, id: &str) -> Result<(), Self::Error> {
+        let (mint_url, id) = (mint_url.to_owned(), id.to_owned());
+        self.blocking(move |this| {
+            let tx = this.db.begin_write()?;
+            this.delete_operation_with(&tx, &mint_url, &id)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+    async fn get_operations(&self) -> Result<Vec<PendingOperation>, Self::Error> {
+        let rows = self
+            .blocking(|this| this.get_timed_with(this.definition_operations(), None))
+            .await?;
+
+        let mut ops = vec![];
+        for js in rows {
+            let op = serde_json::from_str::<PendingOperation>(&js)?;
+            ops.push(op);
+        }
+
+        Ok(ops)
+    }
+    //
+    // keysets of mints
+    async fn add_keysets(&self, keysets: &[MintKeySet]) -> Result<(), Self::Error> {
+        let keysets = keysets.to_vec();
+        self.blocking(move |this| {
+            let tx = this.db.begin_write()?;
+            {
+                let mut table = tx.open_table(this.definition_keysets())?;
+                for ks in &keysets {
+                    debug!("add_keyset: {} {}", ks.mint, ks.keyset.id);
+
+                    let id = ks.keyset.id.to_string();
+                    let key = (ks.mint.as_str(), id.as_str());
+                    let ctime = table.get(key)?.map(|v| v.value().0);
+
+                    let js = serde_json::to_string(ks)?;
+                    table.insert(key, (ctime.unwrap_or(ks.time), js.as_str()))?;
+                }
+            }
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+    async fn get_keysets(&self, mint_url: &str) -> Result<Vec<MintKeySet>, Self::Error> {
+        let mint_url = mint_url.to_owned();
+        let rows = self
+            .blocking(move |this| {
+                this.get_timed_with(this.definition_keysets(), Some(mint_url.as_str()))
+            })
+            .await?;
+
+        let mut keysets = vec![];
+        for js in rows {
+            let ks = serde_json::from_str::<MintKeySet>(&js)?;
+            keysets.push(ks);
+        }
+
+        Ok(keysets)
+    }
+    //
+    async fn apply(&self, batch: &Batch) -> Result<(), Self::Error> {
+        debug!("apply: {} writes", batch.len());
+
+        let batch = batch.clone();
+        self.blocking(move |this| {
+            // the transaction aborts on drop if any write fails
+            let tx = this.db.begin_write()?;
+            for w in batch.writes() {
+                match w {
+                    Write::AddCounter(record) => this.add_counter_with(&tx, record)?,
+                    Write::DeleteProofs(mint_url, proofs) => {
+                        this.delete_proofs_with(&tx, mint_url, proofs)?
+                    }
+                    Write::AddProofs(mint_url, proofs) => {
+                        this.add_proofs_with(&tx, mint_url, proofs)?
+                    }
+                    Write::UpdateProofsState(mint_url, proofs, state, operation) => this
+                        .update_proofs_state_with(
+                            &tx,
+                            mint_url,
+                            proofs,
+                            *state,
+                            operation.as_deref(),
+                        )?,
+                    Write::AddTransaction(t) => this.add_transaction_with(&tx, t)?,
+                    Write::AddPendingMelt(melt) => this.add_pending_melt_with(&tx, melt)?,
+                    Write::DeletePendingMelt(mint_url, quote) => {
+                        this.delete_pending_melt_with(&tx, mint_url, quote)?
+                    }
+                    Write::DeleteOperation(mint_url, id) => {
+                        this.delete_operation_with(&tx, mint_url, id)?
+                    }
+                }
+            }
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    use cashu_wallet::store::tests::tmpfi;
+
+    #[tokio::test]
+    async fn it_works_mint() {
+        let (_td, tf) = tmpfi("test.redb");
+
+        let db = Redb::open(tf, Default::default()).unwrap();
+        cashu_wallet::store::tests::test_mint(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_counter() {
+        let (_td, tf) = tmpfi("test.redb");
+
+        let db = Redb::open(tf, Default::default()).unwrap();
+        cashu_wallet::store::tests::test_counter(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_proof() {
+        let (_td, tf) = tmpfi("test.redb");
+
+        let db = Redb::open(tf, Default::default()).unwrap();
+        cashu_wallet::store::tests::test_proof(&db, Some(true))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_proof_state() {
+        let (_td, tf) = tmpfi("test.redb");
+
+        let db = Redb::open(tf, Default::default()).unwrap();
+        cashu_wallet::store::tests::test_proof_state(&db)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_transaction_cashu() {
+        let (_td, tf) = tmpfi("test.redb");
+
+        let db = Redb::open(tf, Default::default()).unwrap();
+        cashu_wallet::store::tests::test_transaction_cashu(&db)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_transaction_ln() {
+        let (_td, tf) = tmpfi("test.redb");
+
+        let db = Redb::open(tf, Default::default()).unwrap();
+        cashu_wallet::store::tests::test_transaction_ln(&db)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_pending_melt() {
+        let (_td, tf) = tmpfi("test.redb");
+
+        let db = Redb::open(tf, Default::default()).unwrap();
+        cashu_wallet::store::tests::test_pending_melt(&db)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_keysets() {
+        let (_td, tf) = tmpfi("test.redb");
+
+        let db = Redb::open(tf, Default::default()).unwrap();
+        cashu_wallet::store::tests::test_keysets(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_operation() {
+        let (_td, tf) = tmpfi("test.redb");
+
+        let db = Redb::open(tf, Default::default()).unwrap();
+        cashu_wallet::store::tests::test_operation(&db)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_batch() {
+        let (_td, tf) = tmpfi("test.redb");
+
+        let db = Redb::open(tf, Default::default()).unwrap();
+        cashu_wallet::store::tests::test_batch(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_batch_rollback() {
+        let (_td, tf) = tmpfi("test.redb");
+
+        let db = Redb::open(tf, Default::default()).unwrap();
+        cashu_wallet::store::tests::test_batch_rollback(&db)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_reopen() {
+        let (_td, tf) = tmpfi("test.redb");
+
+        let db = Redb::open(&tf, Default::default()).unwrap();
+        let mint = Mint::new(cashu_wallet::types::tests::MINT_URL.to_owned(), None);
+        db.add_mint(&mint).await.unwrap();
+        drop(db);
+
+        let db = Redb::open(&tf, Default::default()).unwrap();
+        assert_eq!(db.get_mints().await.unwrap(), [mint]);
+    }
+}
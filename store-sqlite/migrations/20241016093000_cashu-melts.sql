@@ -0,0 +1,14 @@
+-- Add migration script here
+
+-- https://github.com/cashubtc/nuts/blob/main/05.md
+CREATE TABLE IF NOT EXISTS melts (
+    quote TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    -- PendingMelt json: inputs and blank outputs
+    content TEXT NOT NULL,
+    ctime bigint NOT NULL,
+    UNIQUE (quote, mint)
+);
+
+-- 
+ALTER TABLE transactions ADD COLUMN preimage TEXT;
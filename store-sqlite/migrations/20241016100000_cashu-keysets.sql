@@ -0,0 +1,11 @@
+-- Add migration script here
+
+-- https://github.com/cashubtc/nuts/blob/main/02.md
+CREATE TABLE IF NOT EXISTS keysets (
+    id TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    -- MintKeySet json: keys and info
+    content TEXT NOT NULL,
+    ctime bigint NOT NULL,
+    UNIQUE (id, mint)
+);
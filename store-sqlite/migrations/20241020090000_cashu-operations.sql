@@ -0,0 +1,11 @@
+-- Add migration script here
+
+-- the write-ahead journal of swaps and mints
+CREATE TABLE IF NOT EXISTS operations (
+    id TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    -- PendingOperation json: inputs, outputs and counters
+    content TEXT NOT NULL,
+    ctime bigint NOT NULL,
+    UNIQUE (id, mint)
+);
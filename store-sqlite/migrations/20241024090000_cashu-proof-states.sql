@@ -0,0 +1,8 @@
+-- Add migration script here
+
+-- the lifecycle of proofs: unspent, reserved, pending, spent
+ALTER TABLE proofs ADD COLUMN state TEXT NOT NULL DEFAULT 'unspent';
+-- the operation reserved the proofs: the transaction id of send, the quote of melt
+ALTER TABLE proofs ADD COLUMN operation TEXT;
+ALTER TABLE proofs ADD COLUMN reserved_at bigint;
+CREATE INDEX IF NOT EXISTS index_proofs_mint_state ON proofs (mint, state);
@@ -0,0 +1,4 @@
+-- Add migration script here
+
+-- the SPKI pins json of the mint TLS certificates
+ALTER TABLE mints ADD COLUMN pins TEXT;
@@ -21,6 +21,7 @@ use cashu_wallet::types::unixtime_ms;
 use futures_util::StreamExt;
 use sqlx::sqlite::SqliteConnectOptions;
 use sqlx::Row;
+use sqlx::SqliteConnection;
 use sqlx::SqlitePool;
 use std::collections::BTreeMap as Map;
 use std::num::TryFromIntError;
@@ -103,6 +104,21 @@ impl LitePool {
     pub fn definition_transactions<'a>(&self) -> &'static str {
         self.tables.transactions
     }
+
+    #[inline]
+    pub fn definition_melts<'a>(&self) -> &'static str {
+        self.tables.melts
+    }
+
+    #[inline]
+    pub fn definition_keysets<'a>(&self) -> &'static str {
+        self.tables.keysets
+    }
+
+    #[inline]
+    pub fn definition_operations<'a>(&self) -> &'static str {
+        self.tables.operations
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
@@ -112,6 +128,12 @@ pub struct Tables {
     counters: &'static str,
     /// add records for invoices
     transactions: &'static str,
+    /// in-flight melts
+    melts: &'static str,
+    /// keysets of mints
+    keysets: &'static str,
+    /// write-ahead journal of swaps and mints
+    operations: &'static str,
 }
 
 impl Default for Tables {
@@ -121,13 +143,23 @@ impl Default for Tables {
             proofs: "proofs",
             counters: "counters",
             transactions: "transactions",
+            melts: "melts",
+            keysets: "keysets",
+            operations: "operations",
         }
     }
 }
 
 impl Tables {
     pub fn check(&self) -> anyhow::Result<()> {
-        let strs = [self.mints, self.proofs, self.transactions];
+        let strs = [
+            self.mints,
+            self.proofs,
+            self.transactions,
+            self.melts,
+            self.keysets,
+            self.operations,
+        ];
         let mut names = strs.iter().filter(|s| !s.is_empty()).collect::<Vec<_>>();
         if names.len() != strs.len() {
             bail!("empty table name");
@@ -144,12 +176,13 @@ impl Tables {
 
 use cashu_wallet::cashu::nuts::{nut00::Witness, nut12::ProofDleq};
 use cashu_wallet::store::UnitedStore;
-use cashu_wallet::wallet::{Proof, ProofExtended, ProofsExtended, Record};
+use cashu_wallet::store::{Batch, Write};
+use cashu_wallet::wallet::{Proof, ProofExtended, ProofState, ProofsExtended, Record};
 use cashu_wallet::{ParseError, Url};
 
 use cashu_wallet::types::{
-    CashuTransaction, LNTransaction, Mint, Transaction, TransactionDirection, TransactionKind,
-    TransactionStatus,
+    CashuTransaction, LNTransaction, Mint, MintKeySet, PendingMelt, PendingOperation, Transaction,
+    TransactionDirection, TransactionKind, TransactionStatus,
 };
 
 #[derive(Debug)]
@@ -186,7 +219,7 @@ impl From<cashu_wallet::cashu::nuts::nut01::Error> for StoreError {
     }
 }
 
-/// "select id, kind, amount, status, io, info, ctime, token, mint, unit, fee",
+/// "select id, kind, amount, status, io, info, ctime, token, mint, unit, fee, preimage",
 macro_rules! transaction_from_row {
     ($row: expr) => {{
         let kind = $row.get::<'_, String, _>(1).parse::<TransactionKind>()?;
@@ -201,6 +234,10 @@ macro_rules! transaction_from_row {
         let token = $row.get::<'_, String, _>(7);
         let mint = $row.get::<'_, String, _>(8);
         let unit = $row.get::<'_, Option<String>, _>(9);
+        let fee = $row
+            .get::<'_, Option<i64>, _>(10)
+            .map(|i| u64::try_from(i))
+            .transpose()?;
 
         match kind {
             TransactionKind::Cashu => {
@@ -214,6 +251,7 @@ macro_rules! transaction_from_row {
                     token,
                     mint,
                     unit,
+                    fee,
                 };
 
                 tx.into()
@@ -229,10 +267,8 @@ macro_rules! transaction_from_row {
                     pr: token,
                     mint,
                     unit,
-                    fee: $row
-                        .get::<'_, Option<i64>, _>(10)
-                        .map(|i| u64::try_from(i))
-                        .transpose()?,
+                    fee,
+                    preimage: $row.get::<'_, Option<String>, _>(11),
                 };
 
                 tx.into()
@@ -276,6 +312,12 @@ macro_rules! proof_from_row {
             raw: p,
             ts: u64::try_from($row.get::<'_, i64, _>(5))?.into(),
             unit: $row.get::<'_, Option<String>, _>(6),
+            state: $row.get::<'_, String, _>(9).parse::<ProofState>()?,
+            operation: $row.get::<'_, Option<String>, _>(10),
+            reserved_at: $row
+                .get::<'_, Option<i64>, _>(11)
+                .map(|i| u64::try_from(i))
+                .transpose()?,
             js: String::new(),
         };
 
@@ -283,12 +325,13 @@ macro_rules! proof_from_row {
     }};
 }
 
-#[async_trait]
-impl UnitedStore for LitePool {
-    type Error = StoreError;
-
-    // counter records
-    async fn add_counter(&self, record: &Record) -> Result<(), Self::Error> {
+// the writes on a connection, so the batch shares a transaction
+impl LitePool {
+    async fn add_counter_with(
+        &self,
+        conn: &mut SqliteConnection,
+        record: &Record,
+    ) -> Result<(), StoreError> {
         debug!("add_counter: {:?}", record);
 
         let sql = format!(
@@ -306,12 +349,260 @@ impl UnitedStore for LitePool {
             .bind(&record.pubkey)
             .bind(&counter)
             .bind(&ts)
-            .execute(&self.db)
+            .execute(conn)
             .await?;
 
         Ok(())
     }
 
+    async fn delete_proofs_with(
+        &self,
+        conn: &mut SqliteConnection,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+    ) -> Result<(), StoreError> {
+        let mint = mint_url.as_str();
+
+        debug!("del_proofs: {:?}", proofs);
+
+        // delete can't where unit = null
+        let sql = format!(
+            "delete from {} where secret = ? and mint = ?;",
+            self.definition_proofs()
+        );
+
+        for p in proofs {
+            sqlx::query(&sql)
+                .bind(p.raw.secret.as_str())
+                .bind(mint)
+                // .bind(p.unit())
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn add_proofs_with(
+        &self,
+        conn: &mut SqliteConnection,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+    ) -> Result<(), StoreError> {
+        let mint = mint_url.as_str();
+
+        let sql = format!(
+            "insert into {} (secret, keyset_id, amount, c, mint, ctime, unit, dleq, witness, state, operation, reserved_at) values(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+            self.definition_proofs()
+        );
+
+        debug!("add_proofs: {:?}", proofs);
+        for p in proofs {
+            let c = p.raw.c.to_string();
+            let ts: i64 = p.ts.unwrap_or_else(unixtime_ms).try_into()?;
+            let amount: i64 = p.raw.amount.to_u64().try_into()?;
+
+            let mut dleq = None;
+            if let Some(w) = &p.raw.dleq {
+                let js = serde_json::to_string(&w)?;
+                dleq = Some(js);
+            }
+
+            let mut witness = None;
+            if let Some(w) = &p.raw.witness {
+                let js = serde_json::to_string(&w)?;
+                witness = Some(js);
+            }
+
+            sqlx::query(&sql)
+                .bind(p.raw.secret.as_str())
+                .bind(&p.raw.keyset_id.to_string())
+                .bind(amount)
+                .bind(&c)
+                .bind(mint)
+                .bind(ts)
+                .bind(p.unit())
+                .bind(dleq)
+                .bind(witness)
+                .bind(p.state.as_ref())
+                .bind(p.operation.as_deref())
+                .bind(p.reserved_at.map(|i| i64::try_from(i)).transpose()?)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn update_proofs_state_with(
+        &self,
+        conn: &mut SqliteConnection,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+        state: ProofState,
+        operation: Option<&str>,
+    ) -> Result<(), StoreError> {
+        let mint = mint_url.as_str();
+
+        debug!(
+            "update_proofs_state: {} {} {:?}",
+            proofs.len(),
+            state,
+            operation
+        );
+
+        let sql = if state == ProofState::Unspent {
+            format!(
+                "update {} set state = ?, operation = null, reserved_at = null where secret = ? and mint = ?;",
+                self.definition_proofs()
+            )
+        } else {
+            format!(
+                "update {} set state = ?, operation = coalesce(?, operation), reserved_at = coalesce(reserved_at, ?) where secret = ? and mint = ?;",
+                self.definition_proofs()
+            )
+        };
+
+        let ts = i64::try_from(unixtime_ms())?;
+        for p in proofs {
+            let mut query = sqlx::query(&sql).bind(state.as_ref());
+            if state != ProofState::Unspent {
+                query = query.bind(operation).bind(ts);
+            }
+            query
+                .bind(p.raw.secret.as_str())
+                .bind(mint)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn add_transaction_with(
+        &self,
+        conn: &mut SqliteConnection,
+        tx: &Transaction,
+    ) -> Result<(), StoreError> {
+        let id = tx.id();
+
+        let sql = format!(
+            "insert into {} (id, kind, amount, status, io, info, ctime, token, mint, unit, fee, preimage) values(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id, io) DO UPDATE SET status = excluded.status, info=excluded.info, fee=excluded.fee, preimage=excluded.preimage
+            ;",
+            self.definition_transactions()
+        );
+
+        debug!(
+            "add_transaction.sql: {} {} {}",
+            id,
+            tx.status(),
+            tx.direction()
+        );
+
+        let ts = tx.time() as i64;
+
+        sqlx::query(&sql)
+            .bind(&id)
+            .bind(tx.kind().as_ref())
+            .bind(i64::try_from(tx.amount())?)
+            .bind(tx.status().as_ref())
+            .bind(tx.direction().as_ref())
+            .bind(tx.info())
+            .bind(ts)
+            .bind(tx.content())
+            .bind(tx.mint_url())
+            .bind(tx.unit())
+            .bind(tx.fee().map(|f| i64::try_from(f)).transpose()?)
+            .bind(tx.preimage())
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn add_pending_melt_with(
+        &self,
+        conn: &mut SqliteConnection,
+        melt: &PendingMelt,
+    ) -> Result<(), StoreError> {
+        debug!("add_pending_melt: {} {}", melt.mint, melt.quote);
+
+        let sql = format!(
+            "insert into {} (quote, mint, content, ctime) values(?, ?, ?, ?)
+            ON CONFLICT(quote, mint) DO UPDATE SET content = excluded.content
+            ;",
+            self.definition_melts()
+        );
+
+        let js = serde_json::to_string(melt)?;
+        let ts = i64::try_from(melt.time)?;
+        sqlx::query(&sql)
+            .bind(&melt.quote)
+            .bind(&melt.mint)
+            .bind(&js)
+            .bind(ts)
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_pending_melt_with(
+        &self,
+        conn: &mut SqliteConnection,
+        mint_url: &str,
+        quote: &str,
+    ) -> Result<(), StoreError> {
+        debug!("delete_pending_melt: {} {}", mint_url, quote);
+
+        let sql = format!(
+            "delete from {} where quote = ? and mint = ?;",
+            self.definition_melts()
+        );
+
+        sqlx::query(&sql)
+            .bind(quote)
+            .bind(mint_url)
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_operation_with(
+        &self,
+        conn: &mut SqliteConnection,
+        mint_url: &str,
+        id: &str,
+    ) -> Result<(), StoreError> {
+        debug!("delete_operation: {} {}", mint_url, id);
+
+        let sql = format!(
+            "delete from {} where id = ? and mint = ?;",
+            self.definition_operations()
+        );
+
+        sqlx::query(&sql)
+            .bind(id)
+            .bind(mint_url)
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UnitedStore for LitePool {
+    type Error = StoreError;
+
+    // counter records
+    async fn add_counter(&self, record: &Record) -> Result<(), Self::Error> {
+        let mut conn = self.db.acquire().await?;
+        self.add_counter_with(&mut conn, record).await
+    }
+
     async fn delete_counters(&self, mint_url: &Url) -> Result<(), Self::Error> {
         debug!("delete_counters: {}", mint_url.as_str());
 
@@ -364,25 +655,9 @@ impl UnitedStore for LitePool {
         if proofs.is_empty() {
             return Ok(());
         }
-        let mint = mint_url.as_str();
-
-        debug!("del_proofs: {:?}", proofs);
-
-        // delete can't where unit = null
-        let sql = format!(
-            "delete from {} where secret = ? and mint = ?;",
-            self.definition_proofs()
-        );
 
         let mut ctx = self.db.begin().await?;
-        for p in proofs {
-            sqlx::query(&sql)
-                .bind(p.raw.secret.as_str())
-                .bind(mint)
-                // .bind(p.unit())
-                .execute(ctx.as_mut())
-                .await?;
-        }
+        self.delete_proofs_with(&mut ctx, mint_url, proofs).await?;
         ctx.commit().await?;
 
         Ok(())
@@ -395,45 +670,9 @@ impl UnitedStore for LitePool {
         if proofs.is_empty() {
             return Ok(());
         }
-        let mint = mint_url.as_str();
-
-        let sql = format!(
-            "insert into {} (secret, keyset_id, amount, c, mint, ctime, unit, dleq, witness) values(?, ?, ?, ?, ?, ?, ?, ?, ?);",
-            self.definition_proofs()
-        );
 
-        debug!("add_proofs: {:?}", proofs);
         let mut ctx = self.db.begin().await?;
-        for p in proofs {
-            let c = p.raw.c.to_string();
-            let ts: i64 = p.ts.unwrap_or_else(unixtime_ms).try_into()?;
-            let amount: i64 = p.raw.amount.to_u64().try_into()?;
-
-            let mut dleq = None;
-            if let Some(w) = &p.raw.dleq {
-                let js = serde_json::to_string(&w)?;
-                dleq = Some(js);
-            }
-
-            let mut witness = None;
-            if let Some(w) = &p.raw.witness {
-                let js = serde_json::to_string(&w)?;
-                witness = Some(js);
-            }
-
-            sqlx::query(&sql)
-                .bind(p.raw.secret.as_str())
-                .bind(&p.raw.keyset_id.to_string())
-                .bind(amount)
-                .bind(&c)
-                .bind(mint)
-                .bind(ts)
-                .bind(p.unit())
-                .bind(dleq)
-                .bind(witness)
-                .execute(ctx.as_mut())
-                .await?;
-        }
+        self.add_proofs_with(&mut ctx, mint_url, proofs).await?;
         ctx.commit().await?;
 
         Ok(())
@@ -448,17 +687,21 @@ impl UnitedStore for LitePool {
 
         let sql = if unit == CURRENCY_UNIT_SAT {
             format!(
-            "select secret, keyset_id, amount, c, mint, ctime, unit, dleq, witness from {} where mint=? and (unit=? or unit is null) order by ctime;",
+            "select secret, keyset_id, amount, c, mint, ctime, unit, dleq, witness, state, operation, reserved_at from {} where mint=? and (unit=? or unit is null) and state=? order by ctime;",
             self.definition_proofs()
         )
         } else {
             format!(
-                "select secret, keyset_id, amount, c, mint, ctime, unit, dleq, witness from {} where mint=? and unit =? order by ctime;",
+                "select secret, keyset_id, amount, c, mint, ctime, unit, dleq, witness, state, operation, reserved_at from {} where mint=? and unit =? and state=? order by ctime;",
                 self.definition_proofs()
             )
         };
 
-        let mut iter = sqlx::query(&sql).bind(mint).bind(unit).fetch(&self.db);
+        let mut iter = sqlx::query(&sql)
+            .bind(mint)
+            .bind(unit)
+            .bind(ProofState::Unspent.as_ref())
+            .fetch(&self.db);
 
         let mut proofs = vec![];
 
@@ -475,11 +718,14 @@ impl UnitedStore for LitePool {
         let mint = mint_url.as_str();
 
         let sql = format!(
-            "select secret, keyset_id, amount, c, mint, ctime, unit, dleq, witness from {} where mint=? order by ctime;",
+            "select secret, keyset_id, amount, c, mint, ctime, unit, dleq, witness, state, operation, reserved_at from {} where mint=? and state=? order by ctime;",
             self.definition_proofs()
         );
 
-        let mut iter = sqlx::query(&sql).bind(mint).fetch(&self.db);
+        let mut iter = sqlx::query(&sql)
+            .bind(mint)
+            .bind(ProofState::Unspent.as_ref())
+            .fetch(&self.db);
 
         let mut proofs = Map::new();
 
@@ -498,15 +744,47 @@ impl UnitedStore for LitePool {
     }
     async fn get_all_proofs(
         &self,
+    ) -> Result<Map<MintUrlWithUnitOwned, ProofsExtended>, Self::Error> {
+        self.get_all_proofs_with_state(&[ProofState::Unspent]).await
+    }
+    async fn update_proofs_state(
+        &self,
+        mint_url: &Url,
+        proofs: &[ProofExtended],
+        state: ProofState,
+        operation: Option<&str>,
+    ) -> Result<(), Self::Error> {
+        if proofs.is_empty() {
+            return Ok(());
+        }
+
+        let mut ctx = self.db.begin().await?;
+        self.update_proofs_state_with(&mut ctx, mint_url, proofs, state, operation)
+            .await?;
+        ctx.commit().await?;
+
+        Ok(())
+    }
+    async fn get_all_proofs_with_state(
+        &self,
+        states: &[ProofState],
     ) -> Result<Map<MintUrlWithUnitOwned, ProofsExtended>, Self::Error> {
         // debug!("get.proofs.len: {:?}", table.len());
+        if states.is_empty() {
+            return Ok(Map::new());
+        }
 
         let sql = format!(
-            "select secret, keyset_id, amount, c, mint, ctime, unit, dleq, witness from {} order by ctime;",
-            self.definition_proofs()
+            "select secret, keyset_id, amount, c, mint, ctime, unit, dleq, witness, state, operation, reserved_at from {} where state in ({}) order by ctime;",
+            self.definition_proofs(),
+            vec!["?"; states.len()].join(", "),
         );
 
-        let mut iter = sqlx::query(&sql).fetch(&self.db);
+        let mut query = sqlx::query(&sql);
+        for s in states {
+            query = query.bind(s.as_ref());
+        }
+        let mut iter = query.fetch(&self.db);
 
         let mut proofs = Map::new();
 
@@ -523,6 +801,60 @@ impl UnitedStore for LitePool {
 
         Ok(proofs)
     }
+    async fn get_proofs_with_state(
+        &self,
+        mint_url: &Url,
+        states: &[ProofState],
+    ) -> Result<ProofsExtended, Self::Error> {
+        if states.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let sql = format!(
+            "select secret, keyset_id, amount, c, mint, ctime, unit, dleq, witness, state, operation, reserved_at from {} where mint=? and state in ({}) order by ctime;",
+            self.definition_proofs(),
+            vec!["?"; states.len()].join(", "),
+        );
+
+        let mut query = sqlx::query(&sql).bind(mint_url.as_str());
+        for s in states {
+            query = query.bind(s.as_ref());
+        }
+        let mut iter = query.fetch(&self.db);
+
+        let mut proofs = vec![];
+
+        while let Some(it) = iter.next().await {
+            let it = it?;
+            let (_mint, p) = proof_from_row!(it);
+            proofs.push(p);
+        }
+
+        Ok(proofs)
+    }
+    async fn delete_proofs_with_state(
+        &self,
+        states: &[ProofState],
+        unix_timestamp_ms_le: u64,
+    ) -> Result<u64, Self::Error> {
+        if states.is_empty() {
+            return Ok(0);
+        }
+
+        let sql = format!(
+            "delete from {} where coalesce(reserved_at, ctime)<=? and state in ({});",
+            self.definition_proofs(),
+            vec!["?"; states.len()].join(", "),
+        );
+
+        let mut query = sqlx::query(&sql).bind(unix_timestamp_ms_le as i64);
+        for s in states {
+            query = query.bind(s.as_ref());
+        }
+        let row = query.execute(&self.db).await?;
+
+        Ok(row.rows_affected())
+    }
     /// try open tables
     async fn migrate(&self) -> Result<(), Self::Error> {
         self.init().await?;
@@ -537,9 +869,13 @@ impl UnitedStore for LitePool {
             let js = serde_json::to_string(&m)?;
             mi = Some(js);
         }
+        let mut pins = None;
+        if !mint.pins.is_empty() {
+            pins = Some(serde_json::to_string(&mint.pins)?);
+        }
         let sql = format!(
-            "insert into {} (url, active, info, ctime) values(?, ?, ?, ?)
-            ON CONFLICT(url) DO UPDATE SET active = excluded.active, info=excluded.info
+            "insert into {} (url, active, info, ctime, pins) values(?, ?, ?, ?, ?)
+            ON CONFLICT(url) DO UPDATE SET active = excluded.active, info=excluded.info, pins=excluded.pins
             ;",
             self.definition_mints()
         );
@@ -550,6 +886,7 @@ impl UnitedStore for LitePool {
             .bind(mint.active)
             .bind(&mi)
             .bind(ts)
+            .bind(&pins)
             .execute(&self.db)
             .await?;
 
@@ -557,7 +894,7 @@ impl UnitedStore for LitePool {
     }
     async fn get_mint(&self, mint_url: &str) -> Result<Option<Mint>, Self::Error> {
         let sql = format!(
-            "select url, active, ctime, info from {} where url=?",
+            "select url, active, ctime, info, pins from {} where url=?",
             self.definition_mints()
         );
 
@@ -573,18 +910,24 @@ impl UnitedStore for LitePool {
         let info =
             info.and_then(|i| serde_json::from_str::<cashu_wallet::types::MintInfo>(&i).ok());
 
+        let pins = it.get::<'_, Option<String>, _>(4);
+        let pins = pins
+            .and_then(|p| serde_json::from_str(&p).ok())
+            .unwrap_or_default();
+
         let mint = Mint {
             url: it.get(0),
             active: it.get(1),
             time: u64::try_from(it.get::<'_, i64, _>(2))?,
             info,
+            pins,
         };
 
         Ok(Some(mint))
     }
     async fn get_mints(&self) -> Result<Vec<Mint>, Self::Error> {
         let sql = format!(
-            "select url, active, ctime, info from {} order by url",
+            "select url, active, ctime, info, pins from {} order by url",
             self.definition_mints()
         );
 
@@ -596,11 +939,17 @@ impl UnitedStore for LitePool {
             let info =
                 info.and_then(|i| serde_json::from_str::<cashu_wallet::types::MintInfo>(&i).ok());
 
+            let pins = it.get::<'_, Option<String>, _>(4);
+            let pins = pins
+                .and_then(|p| serde_json::from_str(&p).ok())
+                .unwrap_or_default();
+
             let mint = Mint {
                 url: it.get(0),
                 active: it.get(1),
                 time: u64::try_from(it.get::<'_, i64, _>(2))?,
                 info,
+                pins,
             };
             mints.push(mint);
         }
@@ -610,47 +959,13 @@ impl UnitedStore for LitePool {
     //
     // tx
     async fn add_transaction(&self, tx: &Transaction) -> Result<(), Self::Error> {
-        let id = tx.id();
-
-        let sql = format!(
-            "insert into {} (id, kind, amount, status, io, info, ctime, token, mint, unit, fee) values(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-            ON CONFLICT(id, io) DO UPDATE SET status = excluded.status, info=excluded.info, fee=excluded.fee
-            ;",
-            self.definition_transactions()
-        );
-
-        debug!(
-            "add_transaction.sql: {} {} {}",
-            id,
-            tx.status(),
-            tx.direction()
-        );
-
-        let ts = tx.time() as i64;
-
-        let mut ctx = self.db.begin().await?;
-        sqlx::query(&sql)
-            .bind(&id)
-            .bind(tx.kind().as_ref())
-            .bind(i64::try_from(tx.amount())?)
-            .bind(tx.status().as_ref())
-            .bind(tx.direction().as_ref())
-            .bind(tx.info())
-            .bind(ts)
-            .bind(tx.content())
-            .bind(tx.mint_url())
-            .bind(tx.unit())
-            .bind(tx.fee().map(|f| i64::try_from(f)).transpose()?)
-            .execute(ctx.as_mut())
-            .await?;
-        ctx.commit().await?;
-
-        Ok(())
+        let mut conn = self.db.acquire().await?;
+        self.add_transaction_with(&mut conn, tx).await
     }
 
     async fn get_transaction(&self, txid: &str) -> Result<Option<Transaction>, Self::Error> {
         let sql = format!(
-            "select id, kind, amount, status, io, info, ctime, token, mint, unit, fee from {} where id=?;",
+            "select id, kind, amount, status, io, info, ctime, token, mint, unit, fee, preimage from {} where id=?;",
             self.definition_transactions()
         );
 
@@ -680,7 +995,7 @@ impl UnitedStore for LitePool {
         let status_array = status_slice.join(",");
 
         let sql = format!(
-            "select id, kind, amount, status, io, info, ctime, token, mint, unit, fee from {} where status in ({}) order by ctime;",
+            "select id, kind, amount, status, io, info, ctime, token, mint, unit, fee, preimage from {} where status in ({}) order by ctime;",
             self.definition_transactions(),
             status_array
         );
@@ -711,7 +1026,7 @@ impl UnitedStore for LitePool {
         let ks_array = ks_slice.join(",");
 
         let sql = format!(
-            "select id, kind, amount, status, io, info, ctime, token, mint, unit, fee from {} where kind in ({}) order by ctime desc limit ? offset ?;",
+            "select id, kind, amount, status, io, info, ctime, token, mint, unit, fee, preimage from {} where kind in ({}) order by ctime desc limit ? offset ?;",
             self.definition_transactions(), &ks_array
         );
 
@@ -755,6 +1070,163 @@ impl UnitedStore for LitePool {
 
         Ok(row.rows_affected())
     }
+    //
+    // in-flight melts
+    async fn add_pending_melt(&self, melt: &PendingMelt) -> Result<(), Self::Error> {
+        let mut conn = self.db.acquire().await?;
+        self.add_pending_melt_with(&mut conn, melt).await
+    }
+    async fn delete_pending_melt(&self, mint_url: &str, quote: &str) -> Result<(), Self::Error> {
+        let mut conn = self.db.acquire().await?;
+        self.delete_pending_melt_with(&mut conn, mint_url, quote)
+            .await
+    }
+    async fn get_pending_melts(&self) -> Result<Vec<PendingMelt>, Self::Error> {
+        let sql = format!(
+            "select content from {} order by ctime;",
+            self.definition_melts()
+        );
+
+        let mut rows = sqlx::query(&sql).fetch(&self.db);
+
+        let mut melts = vec![];
+        while let Some(it) = rows.next().await {
+            let it = it?;
+            let melt = serde_json::from_str::<PendingMelt>(it.get(0))?;
+            melts.push(melt);
+        }
+
+        Ok(melts)
+    }
+    //
+    // write-ahead journal of swaps and mints
+    async fn add_operation(&self, op: &PendingOperation) -> Result<(), Self::Error> {
+        debug!("add_operation: {} {} {:?}", op.mint, op.id, op.kind);
+
+        let sql = format!(
+            "insert into {} (id, mint, content, ctime) values(?, ?, ?, ?)
+            ON CONFLICT(id, mint) DO UPDATE SET content = excluded.content
+            ;",
+            self.definition_operations()
+        );
+
+        let js = serde_json::to_string(op)?;
+        let ts = i64::try_from(op.time)?;
+        sqlx::query(&sql)
+            .bind(&op.id)
+            .bind(&op.mint)
+            .bind(&js)
+            .bind(ts)
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+    async fn delete_operation(&self, mint_url: &str, id: &str) -> Result<(), Self::Error> {
+        let mut conn = self.db.acquire().await?;
+        self.delete_operation_with(&mut conn, mint_url, id).await
+    }
+    async fn get_operations(&self) -> Result<Vec<PendingOperation>, Self::Error> {
+        let sql = format!(
+            "select content from {} order by ctime;",
+            self.definition_operations()
+        );
+
+        let mut rows = sqlx::query(&sql).fetch(&self.db);
+
+        let mut ops = vec![];
+        while let Some(it) = rows.next().await {
+            let it = it?;
+            let op = serde_json::from_str::<PendingOperation>(it.get(0))?;
+            ops.push(op);
+        }
+
+        Ok(ops)
+    }
+    //
+    // keysets of mints
+    async fn add_keysets(&self, keysets: &[MintKeySet]) -> Result<(), Self::Error> {
+        let sql = format!(
+            "insert into {} (id, mint, content, ctime) values(?, ?, ?, ?)
+            ON CONFLICT(id, mint) DO UPDATE SET content = excluded.content
+            ;",
+            self.definition_keysets()
+        );
+
+        let mut ctx = self.db.begin().await?;
+        for ks in keysets {
+            debug!("add_keyset: {} {}", ks.mint, ks.keyset.id);
+
+            let js = serde_json::to_string(ks)?;
+            let ts = i64::try_from(ks.time)?;
+            sqlx::query(&sql)
+                .bind(ks.keyset.id.to_string())
+                .bind(&ks.mint)
+                .bind(&js)
+                .bind(ts)
+                .execute(ctx.as_mut())
+                .await?;
+        }
+        ctx.commit().await?;
+
+        Ok(())
+    }
+    async fn get_keysets(&self, mint_url: &str) -> Result<Vec<MintKeySet>, Self::Error> {
+        let sql = format!(
+            "select content from {} where mint = ? order by ctime;",
+            self.definition_keysets()
+        );
+
+        let mut rows = sqlx::query(&sql).bind(mint_url).fetch(&self.db);
+
+        let mut keysets = vec![];
+        while let Some(it) = rows.next().await {
+            let it = it?;
+            let ks = serde_json::from_str::<MintKeySet>(it.get(0))?;
+            keysets.push(ks);
+        }
+
+        Ok(keysets)
+    }
+    //
+    async fn apply(&self, batch: &Batch) -> Result<(), Self::Error> {
+        debug!("apply: {} writes", batch.len());
+
+        let mut ctx = self.db.begin().await?;
+        for w in batch.writes() {
+            match w {
+                Write::AddCounter(record) => self.add_counter_with(&mut ctx, record).await?,
+                Write::DeleteProofs(mint_url, proofs) => {
+                    self.delete_proofs_with(&mut ctx, mint_url, proofs).await?
+                }
+                Write::AddProofs(mint_url, proofs) => {
+                    self.add_proofs_with(&mut ctx, mint_url, proofs).await?
+                }
+                Write::UpdateProofsState(mint_url, proofs, state, operation) => {
+                    self.update_proofs_state_with(
+                        &mut ctx,
+                        mint_url,
+                        proofs,
+                        *state,
+                        operation.as_deref(),
+                    )
+                    .await?
+                }
+                Write::AddTransaction(tx) => self.add_transaction_with(&mut ctx, tx).await?,
+                Write::AddPendingMelt(melt) => self.add_pending_melt_with(&mut ctx, melt).await?,
+                Write::DeletePendingMelt(mint_url, quote) => {
+                    self.delete_pending_melt_with(&mut ctx, mint_url, quote)
+                        .await?
+                }
+                Write::DeleteOperation(mint_url, id) => {
+                    self.delete_operation_with(&mut ctx, mint_url, id).await?
+                }
+            }
+        }
+        ctx.commit().await?;
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -791,6 +1263,16 @@ pub mod tests {
             .unwrap();
     }
 
+    #[tokio::test]
+    async fn it_works_proof_state() {
+        let tf = "sqlite::memory:";
+
+        let db = LitePool::open(tf, Default::default()).await.unwrap();
+        cashu_wallet::store::tests::test_proof_state(&db)
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn it_works_transaction_cashu() {
         let tf = "sqlite::memory:";
@@ -810,4 +1292,50 @@ pub mod tests {
             .await
             .unwrap();
     }
+
+    #[tokio::test]
+    async fn it_works_pending_melt() {
+        let tf = "sqlite::memory:";
+
+        let db = LitePool::open(tf, Default::default()).await.unwrap();
+        cashu_wallet::store::tests::test_pending_melt(&db)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_keysets() {
+        let tf = "sqlite::memory:";
+
+        let db = LitePool::open(tf, Default::default()).await.unwrap();
+        cashu_wallet::store::tests::test_keysets(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_operation() {
+        let tf = "sqlite::memory:";
+
+        let db = LitePool::open(tf, Default::default()).await.unwrap();
+        cashu_wallet::store::tests::test_operation(&db)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_batch() {
+        let tf = "sqlite::memory:";
+
+        let db = LitePool::open(tf, Default::default()).await.unwrap();
+        cashu_wallet::store::tests::test_batch(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_batch_rollback() {
+        let tf = "sqlite::memory:";
+
+        let db = LitePool::open(tf, Default::default()).await.unwrap();
+        cashu_wallet::store::tests::test_batch_rollback(&db)
+            .await
+            .unwrap();
+    }
 }
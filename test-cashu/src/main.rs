@@ -7,7 +7,7 @@ use std::time::Instant;
 
 use cashu_wallet::store::UnitedStore;
 use cashu_wallet::types::TransactionStatus;
-use cashu_wallet::wallet::{HttpOptions, MintClient};
+use cashu_wallet::wallet::{HttpOptions, MintClient, ProofState};
 use cashu_wallet::{UnitedWallet, Url};
 
 #[tokio::main]
@@ -139,6 +139,11 @@ async fn main() {
         )
         .await;
     println!("delete_transactions: {:?}\n", res);
+    let res = w
+        .store()
+        .delete_proofs_with_state([ProofState::Spent].as_slice(), 1694758654680)
+        .await;
+    println!("delete_proofs_with_state: {:?}\n", res);
 
     info!("check_pendings: {:?}\n", w.check_pendings().await);
 
@@ -11,10 +11,7 @@ impl Opts {
     {
         match self.fun(wallet).await {
             Ok(_) => {}
-            Err(e) => {
-                error!("run failed: {}", e);
-                std::process::exit(1);
-            }
+            Err(e) => crate::exit_with(e),
         }
     }
 
@@ -16,6 +16,21 @@ use std::sync::Arc;
 
 use opts::{Cli, Commands, Parser};
 
+use cashu_wallet::UniError;
+
+/// exits with 75(EX_TEMPFAIL) if the mint maybe accept it later, the others 1
+pub fn exit_with<E: std::error::Error>(e: UniError<E>) -> ! {
+    error!("run failed: {}", e);
+    if let Some(code) = e.mint_error_code() {
+        warn!("mint error code: {:?}", code);
+    }
+    if e.is_retryable() {
+        warn!("retry it later");
+        std::process::exit(75);
+    }
+    std::process::exit(1)
+}
+
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
@@ -30,8 +45,9 @@ async fn main() {
             .init()
     }
 
-    use cashu_wallet::wallet::HttpOptions;
+    use cashu_wallet::wallet::{HttpOptions, RetryPolicy};
     use cashu_wallet::UnitedWallet;
+    use cashu_wallet_redb::Redb;
     use cashu_wallet_sqlite::LitePool;
 
     let mut mnemonic = None;
@@ -41,20 +57,30 @@ async fn main() {
         mnemonic = Some(Arc::new(m));
     }
 
+    let proxy = cli.proxy.clone();
+
     macro_rules! call {
         ($opts: expr) => {{
             let dburl = $opts.database.as_str();
             let timeout = $opts.timeout;
 
-            let c = HttpOptions::new()
+            let mut c = HttpOptions::new()
                 .connection_verbose(true)
                 .timeout_connect_ms(3000)
-                .timeout_swap_ms(timeout);
+                .timeout_swap_ms(timeout)
+                .retry(RetryPolicy::default());
+            if let Some(p) = proxy.as_deref() {
+                c = c.proxy(p).isolate_mints(true);
+            }
 
             if dburl.ends_with(".sqlite") || dburl.ends_with(".sqlite3") || dburl.ends_with(".db") {
                 let db = LitePool::open(dburl, Default::default()).await.unwrap();
                 let w = UnitedWallet::with_mnemonic(db, c, mnemonic);
                 $opts.run(w).await
+            } else if dburl.ends_with(".redb") {
+                let db = Redb::open(dburl, Default::default()).unwrap();
+                let w = UnitedWallet::with_mnemonic(db, c, mnemonic);
+                $opts.run(w).await
             } else {
                 panic!("unsupport database path/url")
             }
@@ -31,10 +31,7 @@ impl Opts {
 
         match res {
             Ok(_) => {}
-            Err(e) => {
-                error!("run failed: {}", e);
-                std::process::exit(1);
-            }
+            Err(e) => crate::exit_with(e),
         }
     }
 
@@ -55,7 +52,7 @@ impl Opts {
             .melt(
                 &mint_url,
                 self.request.clone(),
-                None,
+                self.amount,
                 Some(self.unit.as_str()),
                 qr,
             )
@@ -6,6 +6,12 @@ pub use clap::{Args, Parser, Subcommand};
 pub struct Cli {
     #[clap(subcommand)]
     pub command: Commands,
+    #[clap(
+        long,
+        global = true,
+        help = "The proxy of mints, socks5h://127.0.0.1:9050 for Tor"
+    )]
+    pub proxy: Option<String>,
 }
 
 use tracing::Level as LevelFilter;
@@ -260,6 +266,8 @@ pub struct MeltOpts {
     pub timeout: u64,
     #[clap(short, long, help = "the bolt11 Lightning invoice")]
     pub request: String,
+    #[clap(long, help = "the amount of the invoice without amount")]
+    pub amount: Option<u64>,
     // #[clap(short, long, help = "real pay the invoice")]
     // pub pay: bool,
     #[clap(long, default_value = "sat", help = "currency unit")]
@@ -12,10 +12,7 @@ impl Opts {
     {
         match self.fun(wallet).await {
             Ok(_) => {}
-            Err(e) => {
-                error!("run failed: {}", e);
-                std::process::exit(1);
-            }
+            Err(e) => crate::exit_with(e),
         }
     }
 
@@ -38,7 +35,9 @@ impl Opts {
             amount = ps.sum().to_u64();
         }
 
-        let select = cashu_wallet::select_send_proofs(amount, &mut ps)?;
+        let w = wallet.get_wallet(&mint_url)?;
+        let select =
+            cashu_wallet::select_send_proofs(amount, &mut ps, |p| w.input_fee_ppk(&p.keyset_id))?;
         if self.limit > 0 && select as u64 + 1 > self.limit {
             warn!(
                 "merge proofs, not exit!!!: {}/{} proofs > {}",
@@ -12,10 +12,7 @@ impl Opts {
     {
         match self.fun(wallet).await {
             Ok(_) => {}
-            Err(e) => {
-                error!("run failed: {}", e);
-                std::process::exit(1);
-            }
+            Err(e) => crate::exit_with(e),
         }
     }
 
@@ -32,13 +29,27 @@ impl Opts {
             info!("{:>2} {} {}: {}", i, k.mint(), k.unit(), v);
         }
 
+        let balances = wallet.get_balances_with_state().await?;
+        for (k, b) in balances.iter().filter(|(_, b)| b.reserved + b.pending > 0) {
+            info!(
+                "   {} {}: reserved {}, pending {}",
+                k.mint(),
+                k.unit(),
+                b.reserved,
+                b.pending
+            );
+        }
+
         if self.check {
             wallet.load_mints_from_database().await?;
 
+            let (roc, oc) = wallet.recover_operations().await?;
+            warn!("recover_operations ok: {}/{}", roc, oc);
+
             let (upc, pc) = wallet.check_pendings().await?;
             warn!("check_pendings ok: {}/{}", upc, pc);
 
-            if upc > 0 {
+            if upc > 0 || roc > 0 {
                 let balances = wallet.get_balances().await?;
                 for (i, (k, v)) in balances.iter().enumerate() {
                     info!("{:>2} {} {}: {}", i, k.mint(), k.unit(), v);